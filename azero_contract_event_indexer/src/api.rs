@@ -0,0 +1,175 @@
+//! Query parameters of the HTTP API, shared by the server and by `client`.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use subxt::utils::H256;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+	event_db::stats::{StatsMetric, StatsPeriod},
+	export::ExportFormat,
+	AccountId,
+};
+
+/// JSON body of the 400 response to a query outside the indexed blocks, from
+/// `DbError::BlocksNotInRange`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct BlocksNotInRange {
+	pub indexed_from: u32,
+	pub indexed_to: u32,
+	pub block_start: u32,
+	pub block_stop: u32,
+}
+
+impl fmt::Display for BlocksNotInRange {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"Blocks not in range, supported {}-{}, requested: {}-{}",
+			self.indexed_from, self.indexed_to, self.block_start, self.block_stop
+		)
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct GetEventsParams {
+	/// Either `block_start` and `block_stop`, or `time_start` and `time_stop` must be given.
+	pub block_start: Option<u32>,
+	pub block_stop: Option<u32>,
+	/// Milliseconds since the Unix epoch. Selects the blocks with timestamps in
+	/// `time_start..=time_stop`.
+	pub time_start: Option<u64>,
+	pub time_stop: Option<u64>,
+	pub contract_address: Option<AccountId>,
+	/// Only return events having this topic, e.g. an ink! event signature or a `#[ink(topic)]`
+	/// field. Hex encoded, with the `0x` prefix.
+	#[param(value_type = Option<String>)]
+	pub topic: Option<H256>,
+	/// `next_cursor` from a previous partial result, to continue right after it.
+	pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct GetCallsParams {
+	/// Either `block_start` and `block_stop`, or `time_start` and `time_stop` must be given.
+	pub block_start: Option<u32>,
+	pub block_stop: Option<u32>,
+	/// Milliseconds since the Unix epoch. Selects the blocks with timestamps in
+	/// `time_start..=time_stop`.
+	pub time_start: Option<u64>,
+	pub time_stop: Option<u64>,
+	pub contract_address: Option<AccountId>,
+	/// Only return calls of this ink! message selector. Hex encoded, with the `0x` prefix.
+	pub selector: Option<String>,
+	/// Only return calls of this ink! message, e.g. `PSP22::transfer`. Requires the metadata of
+	/// the contract to be registered.
+	pub message: Option<String>,
+	/// `next_cursor` from a previous partial result, to continue right after it.
+	pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct GetExportParams {
+	/// Either `block_start` and `block_stop`, or `time_start` and `time_stop` must be given.
+	pub block_start: Option<u32>,
+	pub block_stop: Option<u32>,
+	/// Milliseconds since the Unix epoch. Selects the blocks with timestamps in
+	/// `time_start..=time_stop`.
+	pub time_start: Option<u64>,
+	pub time_stop: Option<u64>,
+	pub contract_address: Option<AccountId>,
+	/// Only return events having this topic. Hex encoded, with the `0x` prefix.
+	#[param(value_type = Option<String>)]
+	pub topic: Option<H256>,
+	/// `ndjson` (the default) or `csv`.
+	#[serde(default)]
+	#[param(value_type = Option<ExportFormat>)]
+	pub format: ExportFormat,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct GetTopContractsParams {
+	/// Either `block_start` and `block_stop`, or `time_start` and `time_stop` must be given.
+	pub block_start: Option<u32>,
+	pub block_stop: Option<u32>,
+	/// Milliseconds since the Unix epoch. Selects the blocks with timestamps in
+	/// `time_start..=time_stop`.
+	pub time_start: Option<u64>,
+	pub time_stop: Option<u64>,
+	/// `calls` (the default), `emitted` or `unique_callers`.
+	#[serde(default)]
+	#[param(value_type = Option<StatsMetric>)]
+	pub metric: StatsMetric,
+	/// Number of contracts, 20 by default and at most 1000.
+	pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct GetContractActivityParams {
+	/// Milliseconds since the Unix epoch. Every period overlapping `time_start..=time_stop` is
+	/// returned whole.
+	pub time_start: u64,
+	pub time_stop: u64,
+	/// `day` (the default) or `week`.
+	#[serde(default)]
+	#[param(value_type = Option<StatsPeriod>)]
+	pub period: StatsPeriod,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct GetAccountActivityParams {
+	/// First block to search, the start of the indexed range by default. Pass `next_block_start`
+	/// of a partial page to get the next one.
+	pub block_start: Option<u32>,
+	/// Last block to search, the end of the indexed range by default.
+	pub block_stop: Option<u32>,
+	/// Number of extrinsics per page, 100 by default and at most 1000. A page is extended to the
+	/// end of the block of its last extrinsic.
+	pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct GetLiveEventsParams {
+	pub contract_address: Option<AccountId>,
+	/// Only `Called` events with this signed caller.
+	pub caller: Option<AccountId>,
+	/// Name of the event type, e.g. `Emitted` or `Called`.
+	pub event_type: Option<String>,
+	/// Id of the last received event, to first get the events indexed after it.
+	pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct GetBlockByTimeParams {
+	/// Milliseconds since the Unix epoch.
+	pub time: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct PostAuditParams {
+	pub block_start: u32,
+	pub block_stop: u32,
+	/// Re-ingest mismatched blocks from the chain.
+	#[serde(default)]
+	pub repair: bool,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::BlocksNotInRange;
+
+	#[test]
+	fn blocks_not_in_range_is_read_back_from_json_only() {
+		let error = BlocksNotInRange {
+			indexed_from: 100,
+			indexed_to: 200,
+			block_start: 150,
+			block_stop: 250,
+		};
+		let json = serde_json::to_string(&error).unwrap();
+		assert_eq!(serde_json::from_str::<BlocksNotInRange>(&json).unwrap(), error);
+		assert!(serde_json::from_str::<BlocksNotInRange>(&error.to_string()).is_err());
+		assert!(serde_json::from_str::<BlocksNotInRange>("Internal server error").is_err());
+	}
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use subxt::utils::H256;
+use utoipa::ToSchema;
+
+use crate::{
+	block_source::BlockSource,
+	config::IndexerContext,
+	event_db::{self, StoredBlock},
+	scraper::ScrapedBlock,
+};
+
+const AUDIT_RANGE_SIZE: u32 = 6;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BlockMismatch {
+	pub block_num: u32,
+	pub reason: String,
+	/// Whether the block was re-ingested from the chain.
+	pub repaired: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditReport {
+	pub block_start: u32,
+	pub block_stop: u32,
+	/// The last block checked so far.
+	pub checked_to: Option<u32>,
+	pub mismatches: Vec<BlockMismatch>,
+	/// Blocks with no row in `blocks`, indexed by a version that did not record blocks. They have
+	/// nothing to compare with the chain and are skipped.
+	pub unrecorded_blocks: u32,
+	pub is_finished: bool,
+	/// Set if the audit stopped before reaching `block_stop`.
+	pub error: Option<String>,
+}
+
+/// The report of the running or last finished audit.
+pub type AuditState = Arc<Mutex<Option<AuditReport>>>;
+
+// Describes how the stored block differs from the chain, if it does. The block must be recorded.
+fn compare(stored: &StoredBlock, hash: H256, scraped: &ScrapedBlock) -> Option<String> {
+	let chain_event_count = scraped.events.len() as u32;
+	if hash != scraped.block.hash {
+		return Some(format!("Hash {:?} differs from chain {:?}", hash, scraped.block.hash));
+	}
+	if stored.recorded_event_count != Some(chain_event_count) {
+		return Some(format!(
+			"Recorded event count {:?} differs from chain {}",
+			stored.recorded_event_count, chain_event_count
+		));
+	}
+	if stored.event_count != chain_event_count {
+		return Some(format!(
+			"Stored event count {} differs from chain {}",
+			stored.event_count, chain_event_count
+		));
+	}
+	None
+}
+
+/// Starts checking blocks `block_start..=block_stop` against the chain in the background, unless
+/// another audit is running, with the blocks of `source`. Progress is recorded in `state`. With
+/// `repair`, every mismatched block is re-ingested from the chain. Returns whether the audit was
+/// started.
+pub fn start_audit(
+	context: IndexerContext,
+	source: BlockSource,
+	block_start: u32,
+	block_stop: u32,
+	repair: bool,
+	state: AuditState,
+) -> bool {
+	{
+		let mut report = state.lock();
+		if report.as_ref().is_some_and(|report| !report.is_finished) {
+			return false;
+		}
+		*report = Some(AuditReport {
+			block_start,
+			block_stop,
+			checked_to: None,
+			mismatches: Vec::new(),
+			unrecorded_blocks: 0,
+			is_finished: false,
+			error: None,
+		});
+	}
+	tokio::spawn(audit(context, source, block_start, block_stop, repair, state));
+	true
+}
+
+async fn audit(
+	context: IndexerContext,
+	source: BlockSource,
+	block_start: u32,
+	block_stop: u32,
+	repair: bool,
+	state: AuditState,
+) {
+	let result = audit_ranges(&context, &source, block_start, block_stop, repair, &state).await;
+	let mut report = state.lock();
+	let report = report.as_mut().unwrap();
+	report.is_finished = true;
+	if let Err(e) = result {
+		log::error!("Audit failed: {}", e);
+		report.error = Some(e.to_string());
+	}
+	log::info!(
+		"Audit of {}-{} finished, {} mismatched blocks, {} unrecorded blocks skipped",
+		block_start,
+		block_stop,
+		report.mismatches.len(),
+		report.unrecorded_blocks
+	);
+}
+
+async fn audit_ranges(
+	context: &IndexerContext,
+	source: &BlockSource,
+	block_start: u32,
+	block_stop: u32,
+	repair: bool,
+	state: &AuditState,
+) -> anyhow::Result<()> {
+	let mut conn = event_db::get_connection_with_backoff(&context.database_file);
+	let mut num_from = block_start;
+	while num_from <= block_stop {
+		let num_to = u32::min(num_from + AUDIT_RANGE_SIZE - 1, block_stop);
+		let stored = event_db::get_stored_blocks(num_from, num_to, &conn)?;
+		let scraped = source.fetch_blocks(num_from, num_to).await?;
+
+		let mut mismatches = Vec::new();
+		let mut unrecorded_blocks = 0;
+		for (stored, scraped) in stored.iter().zip(scraped) {
+			let Some(hash) = stored.hash else {
+				unrecorded_blocks += 1;
+				continue;
+			};
+			// The chain is compared with what the indexer would store.
+			let scraped = context.contract_filter.apply(scraped).await?;
+			let scraped = context.call_decoder.apply(scraped).await?;
+			if let Some(reason) = compare(stored, hash, &scraped) {
+				log::info!("Audit: block {} mismatched: {}", stored.block_num, reason);
+				if repair {
+					event_db::replace_block(
+						scraped.events,
+						scraped.extrinsics,
+						&scraped.block,
+						&mut conn,
+					)?;
+				}
+				mismatches.push(BlockMismatch {
+					block_num: stored.block_num,
+					reason,
+					repaired: repair,
+				});
+			}
+		}
+
+		{
+			let mut report = state.lock();
+			let report = report.as_mut().unwrap();
+			report.mismatches.extend(mismatches);
+			report.unrecorded_blocks += unrecorded_blocks;
+			report.checked_to = Some(num_to);
+		}
+		num_from = num_to + 1;
+	}
+	Ok(())
+}
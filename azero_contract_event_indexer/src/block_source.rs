@@ -0,0 +1,196 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use azero_universal::RpcPool;
+
+use crate::{
+	config::Network,
+	get_finalized_block_num,
+	scraper::{fetch_blocks, ScrapedBlock},
+};
+
+/// Where the scraper gets blocks from.
+#[derive(Clone)]
+pub enum BlockSource {
+	/// Live RPC nodes.
+	Rpc(RpcPool),
+	/// Blocks recorded by `record_fixtures`, one JSON file per block. The recorded segment plays
+	/// the role of the chain: its last block is the finalized head.
+	Fixtures(PathBuf),
+}
+
+impl BlockSource {
+	/// The fixtures of `network` in `fixtures_dir`, which holds a directory per network named after
+	/// it, or `rpc_pool` if there are none.
+	pub fn new(fixtures_dir: Option<&Path>, network: Network, rpc_pool: RpcPool) -> Self {
+		let Some(dir) = fixtures_dir.map(|dir| dir.join(network.name())) else {
+			return BlockSource::Rpc(rpc_pool);
+		};
+		if dir.is_dir() {
+			return BlockSource::Fixtures(dir);
+		}
+		log::warn!("No fixtures for {} in {}, fetching from RPC", network.name(), dir.display());
+		BlockSource::Rpc(rpc_pool)
+	}
+
+	/// Like `new`, with the fixtures in `FIXTURES_DIR` if it is set.
+	pub fn from_env(network: Network, rpc_pool: RpcPool) -> Self {
+		let fixtures_dir = std::env::var("FIXTURES_DIR").ok().map(PathBuf::from);
+		Self::new(fixtures_dir.as_deref(), network, rpc_pool)
+	}
+
+	pub async fn finalized_block_num(&self) -> anyhow::Result<u32> {
+		match self {
+			BlockSource::Rpc(rpc_pool) => get_finalized_block_num(rpc_pool).await,
+			BlockSource::Fixtures(dir) => Ok(fixtures_range(dir)?.1),
+		}
+	}
+
+	/// The first block available, the scraper does not go below it.
+	pub fn first_block_num(&self) -> anyhow::Result<u32> {
+		match self {
+			BlockSource::Rpc(_) => Ok(0),
+			BlockSource::Fixtures(dir) => Ok(fixtures_range(dir)?.0),
+		}
+	}
+
+	pub async fn fetch_blocks(
+		&self,
+		num_start: u32,
+		num_end: u32,
+	) -> anyhow::Result<Vec<ScrapedBlock>> {
+		match self {
+			BlockSource::Rpc(rpc_pool) => fetch_blocks(rpc_pool, num_start, num_end).await,
+			BlockSource::Fixtures(dir) =>
+				(num_start..=num_end).map(|num| read_fixture(dir, num)).collect(),
+		}
+	}
+}
+
+fn fixture_path(dir: &Path, block_num: u32) -> PathBuf {
+	dir.join(format!("{}.json", block_num))
+}
+
+fn read_fixture(dir: &Path, block_num: u32) -> anyhow::Result<ScrapedBlock> {
+	let path = fixture_path(dir, block_num);
+	let file = fs::File::open(&path)
+		.map_err(|e| anyhow::anyhow!("Failed to open fixture {}: {}", path.display(), e))?;
+	Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+}
+
+fn write_fixture(dir: &Path, block: &ScrapedBlock) -> anyhow::Result<()> {
+	let file = fs::File::create(fixture_path(dir, block.block.block_num))?;
+	serde_json::to_writer(std::io::BufWriter::new(file), block)?;
+	Ok(())
+}
+
+// The lowest and highest recorded block.
+fn fixtures_range(dir: &Path) -> anyhow::Result<(u32, u32)> {
+	let mut nums = Vec::new();
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.extension().is_some_and(|ext| ext == "json") {
+			if let Some(num) = path.file_stem().and_then(|s| s.to_str()?.parse::<u32>().ok()) {
+				nums.push(num);
+			}
+		}
+	}
+	match (nums.iter().min(), nums.iter().max()) {
+		(Some(min), Some(max)) => Ok((*min, *max)),
+		_ => Err(anyhow::anyhow!("No fixtures in {}", dir.display())),
+	}
+}
+
+/// Records blocks `block_start..=block_stop` of `network` from RPC into its directory in
+/// `fixtures_dir`, to be used with `BlockSource::new`.
+pub async fn record_fixtures(
+	rpc_pool: &RpcPool,
+	network: Network,
+	fixtures_dir: &Path,
+	block_start: u32,
+	block_stop: u32,
+) -> anyhow::Result<()> {
+	const RECORD_RANGE_SIZE: u32 = 6;
+	let dir = &fixtures_dir.join(network.name());
+	fs::create_dir_all(dir)?;
+	let mut num_from = block_start;
+	while num_from <= block_stop {
+		let num_to = u32::min(num_from + RECORD_RANGE_SIZE - 1, block_stop);
+		for block in fetch_blocks(rpc_pool, num_from, num_to).await? {
+			write_fixture(dir, &block)?;
+		}
+		log::info!("Recorded blocks {}-{}", num_from, num_to);
+		num_from = num_to + 1;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{fixtures_range, read_fixture, write_fixture, BlockSource};
+	use crate::{
+		config::Network,
+		event_db::{BlockDetails, Event},
+		scraper::ScrapedBlock,
+		AccountId,
+	};
+	use azero_universal::RpcPool;
+	use subxt::utils::H256;
+
+	fn scraped_block(block_num: u32) -> ScrapedBlock {
+		ScrapedBlock {
+			block: BlockDetails {
+				block_num,
+				hash: H256([block_num as u8; 32]),
+				parent_hash: H256([block_num as u8 - 1; 32]),
+				timestamp: Some(1000 * block_num as u64),
+			},
+			extrinsics: Vec::new(),
+			events: vec![Event::new_emitted(
+				AccountId::from([1u8; 32]),
+				block_num,
+				0,
+				1,
+				vec![1, 2, 3],
+				vec![H256([7u8; 32])],
+			)],
+		}
+	}
+
+	#[tokio::test]
+	async fn fixtures_are_read_back_as_recorded() {
+		let dir = std::env::temp_dir().join(format!("fixtures_{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		for block_num in 10..=12 {
+			write_fixture(&dir, &scraped_block(block_num)).unwrap();
+		}
+
+		assert_eq!(fixtures_range(&dir).unwrap(), (10, 12));
+		let source = BlockSource::Fixtures(dir.clone());
+		assert_eq!(source.finalized_block_num().await.unwrap(), 12);
+		let blocks = source.fetch_blocks(11, 12).await.unwrap();
+		assert_eq!(blocks, vec![scraped_block(11), scraped_block(12)]);
+		assert!(read_fixture(&dir, 13).is_err());
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn networks_are_replayed_from_their_own_fixtures() {
+		let dir = std::env::temp_dir().join(format!("network_fixtures_{}", std::process::id()));
+		std::fs::create_dir_all(dir.join("testnet")).unwrap();
+		let source = |network| BlockSource::new(Some(&dir), network, RpcPool::new(["ws://a"]));
+
+		assert!(matches!(
+			source(Network::Testnet),
+			BlockSource::Fixtures(testnet) if testnet == dir.join("testnet")
+		));
+		assert!(matches!(source(Network::Mainnet), BlockSource::Rpc(_)));
+		let rpc = BlockSource::new(None, Network::Testnet, RpcPool::new(["ws://a"]));
+		assert!(matches!(rpc, BlockSource::Rpc(_)));
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
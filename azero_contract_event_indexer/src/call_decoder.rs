@@ -0,0 +1,338 @@
+use std::{collections::HashMap, fs, sync::Arc};
+
+use anyhow::Context;
+use scale_info::{PortableRegistry, TypeDef, TypeDefPrimitive};
+use serde::Deserialize;
+use subxt::{
+	ext::scale_value::{self, Composite, Primitive, Value, ValueDef},
+	utils::H256,
+};
+
+use crate::{
+	code_hashes::CodeHashes,
+	event_db::{CallInput, CalledDetails, EventType},
+	scraper::ScrapedBlock,
+	AccountId,
+};
+
+// The parts of an ink! metadata file needed to decode messages.
+#[derive(Deserialize)]
+struct MetadataFile {
+	source: MetadataSource,
+	spec: MetadataSpec,
+	#[serde(flatten)]
+	types: PortableRegistry,
+}
+
+#[derive(Deserialize)]
+struct MetadataSource {
+	hash: H256,
+}
+
+#[derive(Deserialize)]
+struct MetadataSpec {
+	messages: Vec<MessageSpec>,
+}
+
+#[derive(Deserialize)]
+struct MessageSpec {
+	label: String,
+	selector: String,
+	args: Vec<ArgSpec>,
+}
+
+#[derive(Deserialize)]
+struct ArgSpec {
+	label: String,
+	#[serde(rename = "type")]
+	type_spec: TypeSpec,
+}
+
+#[derive(Deserialize)]
+struct TypeSpec {
+	#[serde(rename = "type")]
+	id: u32,
+}
+
+struct Message {
+	label: String,
+	args: Vec<(String, u32)>,
+}
+
+/// The messages of one ink! contract code, from its metadata file.
+pub struct InkMetadata {
+	messages: HashMap<[u8; 4], Message>,
+	types: PortableRegistry,
+}
+
+impl InkMetadata {
+	/// Parses an ink! v4 metadata file, returns the code hash it belongs to as well.
+	pub fn parse(text: &str) -> anyhow::Result<(H256, Self)> {
+		let file: MetadataFile = serde_json::from_str(text)?;
+		let mut messages = HashMap::new();
+		for message in file.spec.messages {
+			let selector: [u8; 4] = hex::decode(message.selector.trim_start_matches("0x"))?
+				.try_into()
+				.map_err(|_| anyhow::anyhow!("invalid selector of {}", message.label))?;
+			let args = message.args.into_iter().map(|arg| (arg.label, arg.type_spec.id)).collect();
+			messages.insert(selector, Message { label: message.label, args });
+		}
+		Ok((file.source.hash, InkMetadata { messages, types: file.types }))
+	}
+
+	/// Fills in the message and its arguments. Leaves `input` as is if the selector is unknown,
+	/// and the arguments if they do not decode.
+	pub fn decode(&self, input: &mut CallInput) {
+		let Some(message) = self.messages.get(&input.selector) else {
+			return;
+		};
+		input.message = Some(message.label.clone());
+		let mut data = &input.data[4..];
+		let mut args = serde_json::Map::new();
+		for (label, type_id) in &message.args {
+			match scale_value::scale::decode_as_type(&mut data, *type_id, &self.types) {
+				Ok(value) => {
+					args.insert(label.clone(), self.to_json(value));
+				},
+				Err(_) => return,
+			}
+		}
+		input.args = Some(serde_json::Value::Object(args));
+	}
+
+	// Byte sequences are hex, account ids SS58, integers that do not fit JSON numbers strings and
+	// enum variants `{"<variant>": <fields>}`, or just the name for variants without fields.
+	fn to_json(&self, value: Value<u32>) -> serde_json::Value {
+		match value.value {
+			ValueDef::Composite(composite) => {
+				if let Some(bytes) = self.bytes(value.context, &composite) {
+					return serde_json::Value::String(format!("0x{}", hex::encode(bytes)));
+				}
+				if self.is_account_id(value.context) {
+					if let Some(account) = self.account_id(&composite) {
+						return serde_json::Value::String(account.to_string());
+					}
+				}
+				self.composite_to_json(composite)
+			},
+			ValueDef::Variant(variant) if variant.values.is_empty() =>
+				serde_json::Value::String(variant.name),
+			ValueDef::Variant(variant) => serde_json::Value::Object(
+				[(variant.name, self.composite_to_json(variant.values))].into_iter().collect(),
+			),
+			ValueDef::BitSequence(bits) =>
+				serde_json::Value::Array(bits.iter().map(serde_json::Value::Bool).collect()),
+			ValueDef::Primitive(primitive) => match primitive {
+				Primitive::Bool(b) => serde_json::Value::Bool(b),
+				Primitive::Char(c) => serde_json::Value::String(c.to_string()),
+				Primitive::String(s) => serde_json::Value::String(s),
+				Primitive::U128(n) => match u64::try_from(n) {
+					Ok(n) => serde_json::Value::from(n),
+					Err(_) => serde_json::Value::String(n.to_string()),
+				},
+				Primitive::I128(n) => match i64::try_from(n) {
+					Ok(n) => serde_json::Value::from(n),
+					Err(_) => serde_json::Value::String(n.to_string()),
+				},
+				Primitive::U256(bytes) | Primitive::I256(bytes) =>
+					serde_json::Value::String(format!("0x{}", hex::encode(bytes))),
+			},
+		}
+	}
+
+	fn composite_to_json(&self, composite: Composite<u32>) -> serde_json::Value {
+		match composite {
+			Composite::Named(fields) => serde_json::Value::Object(
+				fields.into_iter().map(|(name, value)| (name, self.to_json(value))).collect(),
+			),
+			// Newtypes are their field.
+			Composite::Unnamed(mut values) if values.len() == 1 =>
+				self.to_json(values.pop().unwrap()),
+			Composite::Unnamed(values) => serde_json::Value::Array(
+				values.into_iter().map(|value| self.to_json(value)).collect(),
+			),
+		}
+	}
+
+	// The bytes of a `Vec<u8>` or `[u8; N]`.
+	fn bytes(&self, type_id: u32, composite: &Composite<u32>) -> Option<Vec<u8>> {
+		let element = match &self.types.resolve(type_id)?.type_def {
+			TypeDef::Sequence(sequence) => sequence.type_param.id,
+			TypeDef::Array(array) => array.type_param.id,
+			_ => return None,
+		};
+		if !matches!(
+			self.types.resolve(element)?.type_def,
+			TypeDef::Primitive(TypeDefPrimitive::U8)
+		) {
+			return None;
+		}
+		composite
+			.values()
+			.map(|value| match value.value {
+				ValueDef::Primitive(Primitive::U128(n)) => u8::try_from(n).ok(),
+				_ => None,
+			})
+			.collect()
+	}
+
+	fn is_account_id(&self, type_id: u32) -> bool {
+		self.types
+			.resolve(type_id)
+			.is_some_and(|ty| ty.path.segments.last().is_some_and(|name| name == "AccountId"))
+	}
+
+	// `AccountId` wraps a `[u8; 32]`.
+	fn account_id(&self, composite: &Composite<u32>) -> Option<AccountId> {
+		let inner = composite.values().next()?;
+		let ValueDef::Composite(bytes) = &inner.value else {
+			return None;
+		};
+		let bytes: [u8; 32] = self.bytes(inner.context, bytes)?.try_into().ok()?;
+		Some(AccountId::from(bytes))
+	}
+}
+
+/// Decodes the inputs of calls to contracts whose code has registered ink! metadata.
+pub struct CallDecoder {
+	metadata: HashMap<H256, InkMetadata>,
+	code_hashes: Arc<CodeHashes>,
+}
+
+impl CallDecoder {
+	/// Registers every `*.json` file in `dir`, no metadata if not set.
+	pub fn from_dir(dir: Option<&str>, code_hashes: Arc<CodeHashes>) -> anyhow::Result<Self> {
+		let mut metadata = HashMap::new();
+		if let Some(dir) = dir {
+			for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir))? {
+				let path = entry?.path();
+				if path.extension().is_some_and(|extension| extension == "json") {
+					let text = fs::read_to_string(&path)?;
+					let (code_hash, contract) = InkMetadata::parse(&text)
+						.with_context(|| format!("Invalid ink! metadata {}", path.display()))?;
+					log::info!(
+						"Registered ink! metadata {} of code {:?}",
+						path.display(),
+						code_hash
+					);
+					metadata.insert(code_hash, contract);
+				}
+			}
+		}
+		Ok(CallDecoder { metadata, code_hashes })
+	}
+
+	/// Decodes the inputs of the `Called` events of `block`, by the code the contract ran at
+	/// `block`, as it may have changed since with `set_code_hash`.
+	pub async fn apply(&self, mut block: ScrapedBlock) -> anyhow::Result<ScrapedBlock> {
+		if self.metadata.is_empty() {
+			return Ok(block);
+		}
+		for event in &mut block.events {
+			if let (Some(contract), EventType::Called(CalledDetails { input: Some(input), .. })) =
+				(&event.contract_account_id, &mut event.event_type)
+			{
+				let code_hash = self.code_hashes.get(contract, &block.block).await?;
+				if let Some(contract) =
+					code_hash.and_then(|code_hash| self.metadata.get(&code_hash))
+				{
+					contract.decode(input);
+				}
+			}
+		}
+		Ok(block)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{CallDecoder, InkMetadata};
+	use crate::{
+		code_hashes::CodeHashes,
+		event_db::{BlockDetails, CallInput, CalledDetails, Event, EventType, Origin},
+		scraper::ScrapedBlock,
+		AccountId,
+	};
+	use azero_universal::RpcPool;
+	use codec::Encode;
+	use serde_json::json;
+	use std::sync::Arc;
+	use subxt::utils::H256;
+
+	const PSP22_METADATA: &str = include_str!("../../azero_contracts/metadata/psp22.json");
+
+	#[test]
+	fn decodes_psp22_transfer() {
+		let (code_hash, metadata) = InkMetadata::parse(PSP22_METADATA).unwrap();
+		assert_eq!(
+			format!("{:?}", code_hash),
+			"0x83b8f9d3290ffd2f398146288d1edc35ec4a18a86070b2288e7cae1e0ca3a697"
+		);
+
+		let to = AccountId::from([1u8; 32]);
+		let mut data = vec![0xdb, 0x20, 0xf9, 0xf5];
+		data.extend(([1u8; 32], u128::MAX, vec![0xabu8]).encode());
+		let mut input = CallInput::new(data).unwrap();
+		metadata.decode(&mut input);
+		assert_eq!(input.message.as_deref(), Some("PSP22::transfer"));
+		assert_eq!(
+			input.args,
+			Some(json!({
+				"to": to.to_string(),
+				"value": u128::MAX.to_string(),
+				"_data": "0xab",
+			}))
+		);
+
+		// Unknown selectors are left alone.
+		let mut input = CallInput::new(vec![1, 2, 3, 4]).unwrap();
+		metadata.decode(&mut input);
+		assert_eq!((input.message, input.args), (None, None));
+	}
+
+	#[tokio::test]
+	async fn calls_are_decoded_by_the_code_at_their_block() {
+		let (code_hash, metadata) = InkMetadata::parse(PSP22_METADATA).unwrap();
+		let code_hashes = Arc::new(CodeHashes::new(RpcPool::new(["ws://localhost:1"])));
+		let decoder = CallDecoder { metadata: [(code_hash, metadata)].into(), code_hashes };
+		let block = |num: u8| BlockDetails {
+			block_num: num as u32,
+			hash: H256([num; 32]),
+			parent_hash: H256([num - 1; 32]),
+			timestamp: Some(num as u64 * 1000),
+		};
+		let called = |block_num| {
+			Event::new(
+				AccountId::from([1u8; 32]),
+				block_num,
+				0,
+				1,
+				EventType::Called(CalledDetails {
+					caller: Origin::Root,
+					input: CallInput::new(vec![0x16, 0x2d, 0xf8, 0xc2]),
+				}),
+			)
+		};
+		let message = |scraped: &ScrapedBlock| match &scraped.events[0].event_type {
+			EventType::Called(CalledDetails { input: Some(input), .. }) => input.message.clone(),
+			_ => panic!("Not a call"),
+		};
+
+		// The contract ran the PSP22 code at block 7, and another code since block 8.
+		decoder
+			.code_hashes
+			.insert(H256([7u8; 32]), AccountId::from([1u8; 32]), Some(code_hash));
+		let scraped = ScrapedBlock { block: block(7), extrinsics: vec![], events: vec![called(7)] };
+		let decoded = decoder.apply(scraped).await.unwrap();
+		assert_eq!(message(&decoded).as_deref(), Some("PSP22::total_supply"));
+
+		decoder.code_hashes.insert(
+			H256([8u8; 32]),
+			AccountId::from([1u8; 32]),
+			Some(H256([9u8; 32])),
+		);
+		let scraped = ScrapedBlock { block: block(8), extrinsics: vec![], events: vec![called(8)] };
+		let decoded = decoder.apply(scraped).await.unwrap();
+		assert_eq!(message(&decoded), None);
+	}
+}
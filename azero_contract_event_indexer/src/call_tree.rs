@@ -0,0 +1,195 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+	event_db::{get_extrinsic, CalledDetails, DbError, Event, EventType, Extrinsic, Origin},
+	AccountId,
+};
+
+/// The execution of a contract. The runtime emits the `Called`, `DelegateCalled` or
+/// `Instantiated` event of a frame once it returns, after the events of everything that happened
+/// inside it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct CallFrame {
+	/// The `Called`, `DelegateCalled` or `Instantiated` event of the frame.
+	pub entry: Event,
+	/// Events emitted and calls made in the frame, in execution order.
+	pub children: Vec<CallTreeNode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum CallTreeNode {
+	Frame(CallFrame),
+	Event(Event),
+}
+
+impl CallFrame {
+	/// The events of the frame and of the frames nested in it, in the order they were emitted,
+	/// ending with `entry`.
+	pub fn events(&self) -> Vec<&Event> {
+		let mut events = Vec::new();
+		for child in &self.children {
+			match child {
+				CallTreeNode::Frame(frame) => events.extend(frame.events()),
+				CallTreeNode::Event(event) => events.push(event),
+			}
+		}
+		events.push(&self.entry);
+		events
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct ExtrinsicCallTree {
+	pub extrinsic: Extrinsic,
+	/// The calls made by the extrinsic itself, and the events outside of any known frame.
+	pub calls: Vec<CallTreeNode>,
+}
+
+// The contract in whose frame `node` happened, if it is known.
+fn owner(node: &CallTreeNode) -> Option<&AccountId> {
+	match node {
+		CallTreeNode::Event(event) => match event.event_type {
+			EventType::Emitted(_) |
+			EventType::Terminated(_) |
+			EventType::ContractCodeUpdated(_) => event.contract_account_id.as_ref(),
+			_ => None,
+		},
+		CallTreeNode::Frame(frame) => match &frame.entry.event_type {
+			EventType::Called(CalledDetails { caller: Origin::Signed(caller), .. }) => Some(caller),
+			EventType::Instantiated(details) => Some(&details.deployer),
+			// The code runs in the frame of the contract that makes the delegate call.
+			EventType::DelegateCalled(_) => frame.entry.contract_account_id.as_ref(),
+			_ => None,
+		},
+	}
+}
+
+/// Nests the events of one extrinsic, ordered by index, into the frames of the contracts they
+/// happened in. A frame takes the events and frames of its contract that directly precede its
+/// entry event. The events a contract emits before making a delegate call or calling itself are
+/// not told apart from those of the inner frame, they go to the inner frame.
+pub fn build_call_tree(events: Vec<Event>) -> Vec<CallTreeNode> {
+	let mut nodes = Vec::new();
+	for event in events {
+		match event.event_type {
+			EventType::Called(_) | EventType::DelegateCalled(_) | EventType::Instantiated(_) => {
+				let start = nodes
+					.iter()
+					.rposition(|node| owner(node) != event.contract_account_id.as_ref())
+					.map_or(0, |i| i + 1);
+				let children = nodes.split_off(start);
+				nodes.push(CallTreeNode::Frame(CallFrame { entry: event, children }));
+			},
+			_ => nodes.push(CallTreeNode::Event(event)),
+		}
+	}
+	nodes
+}
+
+/// The call tree of an extrinsic, `None` if the extrinsic is not indexed.
+pub fn get_call_tree(
+	block_num: u32,
+	extrinsic_index: u32,
+	conn: &Connection,
+) -> Result<Option<ExtrinsicCallTree>, DbError> {
+	Ok(get_extrinsic(block_num, extrinsic_index, conn)?.map(|found| ExtrinsicCallTree {
+		extrinsic: found.extrinsic,
+		calls: build_call_tree(found.events),
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{build_call_tree, CallFrame, CallTreeNode};
+	use crate::{
+		event_db::{CodeDetails, Event, EventType, InstantiatedDetails, Origin},
+		AccountId,
+	};
+	use subxt::utils::H256;
+
+	fn account(id: u8) -> AccountId {
+		AccountId::from([id; 32])
+	}
+
+	fn emitted(contract: u8, event_index: u32) -> Event {
+		Event::new_emitted(account(contract), 7, event_index, 1, vec![event_index as u8], vec![])
+	}
+
+	fn called(contract: u8, caller: u8, event_index: u32) -> Event {
+		Event::new_called(account(contract), 7, event_index, 1, Origin::Signed(account(caller)))
+	}
+
+	fn frame(entry: Event, children: Vec<CallTreeNode>) -> CallTreeNode {
+		CallTreeNode::Frame(CallFrame { entry, children })
+	}
+
+	#[test]
+	fn nested_calls_become_frames() {
+		// Account 9 calls router 1, which calls pair 2 (emitting twice) and then deploys 3 whose
+		// constructor delegates to code 0x05. Then account 9 calls router 1 again.
+		let delegate_called = Event::new(
+			account(3),
+			7,
+			4,
+			1,
+			EventType::DelegateCalled(CodeDetails { code_hash: H256([5u8; 32]) }),
+		);
+		let instantiated = Event::new(
+			account(3),
+			7,
+			5,
+			1,
+			EventType::Instantiated(InstantiatedDetails { deployer: account(1), code_hash: None }),
+		);
+		let events = vec![
+			emitted(1, 0),
+			emitted(2, 1),
+			emitted(2, 2),
+			called(2, 1, 3),
+			delegate_called.clone(),
+			instantiated.clone(),
+			called(1, 9, 6),
+			emitted(1, 7),
+			called(1, 9, 8),
+		];
+		let tree = build_call_tree(events.clone());
+		assert_eq!(
+			tree,
+			vec![
+				frame(
+					called(1, 9, 6),
+					vec![
+						CallTreeNode::Event(emitted(1, 0)),
+						frame(
+							called(2, 1, 3),
+							vec![
+								CallTreeNode::Event(emitted(2, 1)),
+								CallTreeNode::Event(emitted(2, 2))
+							]
+						),
+						frame(instantiated, vec![frame(delegate_called, vec![])]),
+					]
+				),
+				frame(called(1, 9, 8), vec![CallTreeNode::Event(emitted(1, 7))]),
+			]
+		);
+		let CallTreeNode::Frame(first) = &tree[0] else { panic!("not a frame") };
+		let flattened: Vec<Event> = first.events().into_iter().cloned().collect();
+		assert_eq!(flattened, events[..7]);
+	}
+
+	#[test]
+	fn events_without_a_frame_stay_at_the_top() {
+		// E.g. the `Called` event of contract 1 was filtered out.
+		let tree = build_call_tree(vec![emitted(2, 0), called(2, 1, 1), emitted(1, 2)]);
+		assert_eq!(
+			tree,
+			vec![
+				frame(called(2, 1, 1), vec![CallTreeNode::Event(emitted(2, 0))]),
+				CallTreeNode::Event(emitted(1, 2)),
+			]
+		);
+	}
+}
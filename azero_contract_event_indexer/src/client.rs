@@ -0,0 +1,561 @@
+//! Typed client of the HTTP API, see `EventIndexerClient`. `ApiClient` is the retrying transport it
+//! is built on, shared with the clients of other indexers.
+
+use std::time::Duration;
+
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use subxt::utils::H256;
+use thiserror::Error;
+
+use crate::{
+	api::{
+		BlocksNotInRange, GetAccountActivityParams, GetBlockByTimeParams, GetCallsParams,
+		GetContractActivityParams, GetEventsParams, GetExportParams, GetLiveEventsParams,
+		GetTopContractsParams, PostAuditParams,
+	},
+	audit::AuditReport,
+	call_tree::ExtrinsicCallTree,
+	event_db::{
+		activity::{AccountActivity, AccountActivityPage},
+		registry::{CodeRecord, ContractRecord},
+		stats::{ContractActivity, ContractRank},
+		Event, ExtrinsicWithEvents,
+	},
+	export::ExportFormat,
+	snapshot::SnapshotInfo,
+	AccountId, Bounds, QueryResultEvents,
+};
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+	/// The server answered `DbError::BlocksNotInRange`.
+	#[error("Queried blocks ({2}, {3}) are not in the indexed range [{0}, {1}]")]
+	BlocksNotInRange(u32, u32, u32, u32),
+	#[error("Bad request: {0}")]
+	BadRequest(String),
+	#[error("Unexpected status {0}: {1}")]
+	Status(StatusCode, String),
+	#[error("HTTP error: {0}")]
+	Http(#[from] reqwest::Error),
+	#[error("Invalid response: {0}")]
+	InvalidResponse(String),
+}
+
+impl ClientError {
+	fn from_status(status: StatusCode, body: String) -> Self {
+		if status != StatusCode::BAD_REQUEST {
+			return ClientError::Status(status, body);
+		}
+		match serde_json::from_str::<BlocksNotInRange>(&body) {
+			Ok(e) => ClientError::BlocksNotInRange(
+				e.indexed_from,
+				e.indexed_to,
+				e.block_start,
+				e.block_stop,
+			),
+			Err(_) => ClientError::BadRequest(body),
+		}
+	}
+}
+
+/// GET requests failing to connect or with a 5xx or 429 status are retried, waiting
+/// `initial_backoff` and then twice as long each time, up to `max_backoff`. Requests with any other
+/// method, which could take effect twice, are sent once.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+	pub max_retries: u32,
+	pub initial_backoff: Duration,
+	pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		RetryPolicy {
+			max_retries: 5,
+			initial_backoff: Duration::from_millis(200),
+			max_backoff: Duration::from_secs(10),
+		}
+	}
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+	status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// An HTTP API at `base_url`, e.g. `http://localhost:3000`.
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+	base_url: String,
+	http: reqwest::Client,
+	retry: RetryPolicy,
+}
+
+impl ApiClient {
+	pub fn new(base_url: &str) -> Self {
+		ApiClient {
+			base_url: base_url.trim_end_matches('/').to_string(),
+			http: reqwest::Client::new(),
+			retry: RetryPolicy::default(),
+		}
+	}
+
+	pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+		self.retry = retry;
+		self
+	}
+
+	pub fn get(&self, path: &str) -> RequestBuilder {
+		self.http.get(format!("{}{}", self.base_url, path))
+	}
+
+	pub fn post(&self, path: &str) -> RequestBuilder {
+		self.http.post(format!("{}{}", self.base_url, path))
+	}
+
+	/// Sends `request`, retrying by the `RetryPolicy`. Responses with an error status become a
+	/// `ClientError`.
+	pub async fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+		let is_get = request
+			.try_clone()
+			.and_then(|request| request.build().ok())
+			.is_some_and(|request| request.method() == Method::GET);
+		if !is_get {
+			let response = request.send().await?;
+			let status = response.status();
+			if status.is_success() {
+				return Ok(response);
+			}
+			return Err(ClientError::from_status(status, response.text().await?));
+		}
+		let mut backoff = self.retry.initial_backoff;
+		let mut retries = 0;
+		loop {
+			let attempt = request.try_clone().ok_or_else(|| {
+				ClientError::InvalidResponse("Request can not be retried".to_string())
+			})?;
+			let error = match attempt.send().await {
+				Ok(response) if response.status().is_success() => return Ok(response),
+				Ok(response) => {
+					let status = response.status();
+					let error = ClientError::from_status(status, response.text().await?);
+					if !is_retryable(status) {
+						return Err(error);
+					}
+					error
+				},
+				Err(e) => ClientError::Http(e),
+			};
+			if retries >= self.retry.max_retries {
+				return Err(error);
+			}
+			log::warn!("Request failed, retrying in {:?}: {}", backoff, error);
+			tokio::time::sleep(backoff).await;
+			backoff = (backoff * 2).min(self.retry.max_backoff);
+			retries += 1;
+		}
+	}
+
+	pub async fn json<T: DeserializeOwned>(
+		&self,
+		request: RequestBuilder,
+	) -> Result<T, ClientError> {
+		Ok(self.send(request).await?.json().await?)
+	}
+
+	/// Like `json`, but `None` on 404.
+	pub async fn optional_json<T: DeserializeOwned>(
+		&self,
+		request: RequestBuilder,
+	) -> Result<Option<T>, ClientError> {
+		match self.json(request).await {
+			Ok(value) => Ok(Some(value)),
+			Err(ClientError::Status(StatusCode::NOT_FOUND, _)) => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+}
+
+/// Client of the API of `azero_contract_event_indexer`. The `all_*` methods follow the pages of
+/// partial results until the end.
+#[derive(Debug, Clone)]
+pub struct EventIndexerClient {
+	api: ApiClient,
+	/// Sent with the requests to `/admin` routes, see `ADMIN_TOKEN` of the server.
+	admin_token: Option<String>,
+}
+
+impl EventIndexerClient {
+	pub fn new(base_url: &str) -> Self {
+		EventIndexerClient { api: ApiClient::new(base_url), admin_token: None }
+	}
+
+	pub fn with_retry(self, retry: RetryPolicy) -> Self {
+		EventIndexerClient { api: self.api.with_retry(retry), ..self }
+	}
+
+	pub fn with_admin_token(self, admin_token: &str) -> Self {
+		EventIndexerClient { admin_token: Some(admin_token.to_string()), ..self }
+	}
+
+	fn admin(&self, request: RequestBuilder) -> RequestBuilder {
+		match &self.admin_token {
+			Some(token) => request.bearer_auth(token),
+			None => request,
+		}
+	}
+
+	pub async fn status(&self) -> Result<Bounds, ClientError> {
+		self.api.json(self.api.get("/status")).await
+	}
+
+	/// The first indexed block with a timestamp at or after `time`, `None` if all are older.
+	pub async fn first_block_since(&self, time: u64) -> Result<Option<u32>, ClientError> {
+		let params = GetBlockByTimeParams { time };
+		self.api.optional_json(self.api.get("/blocks/by_time").query(&params)).await
+	}
+
+	pub async fn events(&self, params: &GetEventsParams) -> Result<QueryResultEvents, ClientError> {
+		self.api.json(self.api.get("/events").query(params)).await
+	}
+
+	pub async fn all_events(&self, params: &GetEventsParams) -> Result<Vec<Event>, ClientError> {
+		let mut params = params.clone();
+		let mut events = Vec::new();
+		loop {
+			let page = self.events(&params).await?;
+			events.extend(page.data);
+			match next_cursor(page.is_complete, page.next_cursor)? {
+				Some(cursor) => params.cursor = Some(cursor),
+				None => return Ok(events),
+			}
+		}
+	}
+
+	pub async fn calls(&self, params: &GetCallsParams) -> Result<QueryResultEvents, ClientError> {
+		self.api.json(self.api.get("/calls").query(params)).await
+	}
+
+	pub async fn all_calls(&self, params: &GetCallsParams) -> Result<Vec<Event>, ClientError> {
+		let mut params = params.clone();
+		let mut events = Vec::new();
+		loop {
+			let page = self.calls(&params).await?;
+			events.extend(page.data);
+			match next_cursor(page.is_complete, page.next_cursor)? {
+				Some(cursor) => params.cursor = Some(cursor),
+				None => return Ok(events),
+			}
+		}
+	}
+
+	/// All the events of `/export`, read as NDJSON whatever `params.format` is.
+	pub async fn export(&self, params: &GetExportParams) -> Result<Vec<Event>, ClientError> {
+		let params = GetExportParams { format: ExportFormat::Ndjson, ..params.clone() };
+		let body = self.api.send(self.api.get("/export").query(&params)).await?.text().await?;
+		body.lines()
+			.filter(|line| !line.is_empty())
+			.map(|line| {
+				serde_json::from_str(line).map_err(|e| ClientError::InvalidResponse(e.to_string()))
+			})
+			.collect()
+	}
+
+	/// Follows `/events/live`, reconnecting from the last received event when the connection drops.
+	pub fn live_events(&self, params: GetLiveEventsParams) -> LiveEvents {
+		LiveEvents { api: self.api.clone(), params, response: None, buffer: Vec::new() }
+	}
+
+	pub async fn extrinsic(
+		&self,
+		block_num: u32,
+		extrinsic_index: u32,
+	) -> Result<Option<ExtrinsicWithEvents>, ClientError> {
+		let path = format!("/extrinsic/{}/{}", block_num, extrinsic_index);
+		self.api.optional_json(self.api.get(&path)).await
+	}
+
+	pub async fn call_tree(
+		&self,
+		block_num: u32,
+		extrinsic_index: u32,
+	) -> Result<Option<ExtrinsicCallTree>, ClientError> {
+		let path = format!("/extrinsic/{}/{}/call_tree", block_num, extrinsic_index);
+		self.api.optional_json(self.api.get(&path)).await
+	}
+
+	pub async fn extrinsics_by_hash(
+		&self,
+		hash: &H256,
+	) -> Result<Vec<ExtrinsicWithEvents>, ClientError> {
+		self.api.json(self.api.get(&format!("/extrinsic_by_hash/{:?}", hash))).await
+	}
+
+	pub async fn top_contracts(
+		&self,
+		params: &GetTopContractsParams,
+	) -> Result<Vec<ContractRank>, ClientError> {
+		self.api.json(self.api.get("/stats/top").query(params)).await
+	}
+
+	pub async fn contract_activity(
+		&self,
+		contract: &AccountId,
+		params: &GetContractActivityParams,
+	) -> Result<Vec<ContractActivity>, ClientError> {
+		let path = format!("/stats/contract/{}", contract);
+		self.api.json(self.api.get(&path).query(params)).await
+	}
+
+	pub async fn contract(
+		&self,
+		contract: &AccountId,
+	) -> Result<Option<ContractRecord>, ClientError> {
+		self.api.optional_json(self.api.get(&format!("/contracts/{}", contract))).await
+	}
+
+	pub async fn code(&self, code_hash: &H256) -> Result<Option<CodeRecord>, ClientError> {
+		self.api.optional_json(self.api.get(&format!("/codes/{:?}", code_hash))).await
+	}
+
+	pub async fn account_activity(
+		&self,
+		account: &AccountId,
+		params: &GetAccountActivityParams,
+	) -> Result<AccountActivityPage, ClientError> {
+		let path = format!("/accounts/{}/activity", account);
+		self.api.json(self.api.get(&path).query(params)).await
+	}
+
+	pub async fn all_account_activity(
+		&self,
+		account: &AccountId,
+		params: &GetAccountActivityParams,
+	) -> Result<Vec<AccountActivity>, ClientError> {
+		let mut params = params.clone();
+		let mut activity = Vec::new();
+		loop {
+			let page = self.account_activity(account, &params).await?;
+			activity.extend(page.data);
+			match (page.is_complete, page.next_block_start) {
+				(true, _) => return Ok(activity),
+				(false, Some(block_start)) => params.block_start = Some(block_start),
+				(false, None) =>
+					return Err(ClientError::InvalidResponse(
+						"Partial result without a next block".to_string(),
+					)),
+			}
+		}
+	}
+
+	/// Starts an audit, whose report is polled with `audit`.
+	pub async fn start_audit(&self, params: &PostAuditParams) -> Result<(), ClientError> {
+		self.api.send(self.admin(self.api.post("/admin/audit")).query(params)).await?;
+		Ok(())
+	}
+
+	pub async fn audit(&self) -> Result<Option<AuditReport>, ClientError> {
+		self.api.json(self.admin(self.api.get("/admin/audit"))).await
+	}
+
+	pub async fn take_snapshot(&self) -> Result<SnapshotInfo, ClientError> {
+		self.api.json(self.admin(self.api.post("/admin/snapshot"))).await
+	}
+
+	/// The Prometheus text exposition of the indexer metrics.
+	pub async fn metrics(&self) -> Result<String, ClientError> {
+		Ok(self.api.send(self.api.get("/metrics")).await?.text().await?)
+	}
+}
+
+fn next_cursor(
+	is_complete: bool,
+	next_cursor: Option<String>,
+) -> Result<Option<String>, ClientError> {
+	match (is_complete, next_cursor) {
+		(true, _) => Ok(None),
+		(false, Some(cursor)) => Ok(Some(cursor)),
+		(false, None) =>
+			Err(ClientError::InvalidResponse("Partial result without a cursor".to_string())),
+	}
+}
+
+/// The events of `/events/live`, see `EventIndexerClient::live_events`.
+pub struct LiveEvents {
+	api: ApiClient,
+	/// `cursor` is the id of the last received event.
+	params: GetLiveEventsParams,
+	response: Option<Response>,
+	/// Received bytes of the next message.
+	buffer: Vec<u8>,
+}
+
+impl LiveEvents {
+	/// Waits for the next indexed event.
+	pub async fn next(&mut self) -> Result<Event, ClientError> {
+		loop {
+			if let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+				let message: Vec<u8> = self.buffer.drain(..end + 2).collect();
+				if let Some(event) = self.on_message(&String::from_utf8_lossy(&message))? {
+					return Ok(event);
+				}
+				continue;
+			}
+			let response = match &mut self.response {
+				Some(response) => response,
+				None => {
+					let request = self.api.get("/events/live").query(&self.params);
+					self.response.insert(self.api.send(request).await?)
+				},
+			};
+			match response.chunk().await {
+				Ok(Some(chunk)) => self.buffer.extend_from_slice(&chunk),
+				result => {
+					if let Err(e) = result {
+						log::warn!("Live events connection failed, reconnecting: {}", e);
+					}
+					self.response = None;
+					self.buffer.clear();
+					tokio::time::sleep(self.api.retry.initial_backoff).await;
+				},
+			}
+		}
+	}
+
+	// Parses one server-sent event, `None` for keep-alive comments.
+	fn on_message(&mut self, message: &str) -> Result<Option<Event>, ClientError> {
+		let (mut id, mut event_name, mut data) = (None, None, Vec::new());
+		for line in message.lines() {
+			let (field, value) = line.split_once(':').unwrap_or((line, ""));
+			let value = value.strip_prefix(' ').unwrap_or(value);
+			match field {
+				"id" => id = Some(value.to_string()),
+				"event" => event_name = Some(value),
+				"data" => data.push(value),
+				_ => {},
+			}
+		}
+		if data.is_empty() {
+			return Ok(None);
+		}
+		let data = data.join("\n");
+		if event_name == Some("error") {
+			return Err(ClientError::InvalidResponse(data));
+		}
+		let event =
+			serde_json::from_str(&data).map_err(|e| ClientError::InvalidResponse(e.to_string()))?;
+		if id.is_some() {
+			self.params.cursor = id;
+		}
+		Ok(Some(event))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ClientError, EventIndexerClient, RetryPolicy};
+	use crate::{
+		api::{BlocksNotInRange, GetEventsParams},
+		event_db::Event,
+		AccountId, QueryResultEvents,
+	};
+	use axum::{
+		extract::Query,
+		http::StatusCode,
+		response::IntoResponse,
+		routing::{get, post},
+		Json,
+	};
+	use std::{
+		sync::{
+			atomic::{AtomicU32, Ordering},
+			Arc,
+		},
+		time::Duration,
+	};
+
+	fn emitted(block_num: u32) -> Event {
+		Event::new_emitted(AccountId::from([1u8; 32]), block_num, 0, 0, vec![], vec![])
+	}
+
+	// Serves `/events` in pages of one block, after failing the first request.
+	async fn serve() -> String {
+		let requests = Arc::new(AtomicU32::new(0));
+		let events = move |Query(params): Query<GetEventsParams>| {
+			let requests = Arc::clone(&requests);
+			async move {
+				if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+					return StatusCode::SERVICE_UNAVAILABLE.into_response();
+				}
+				let (block_start, block_stop) =
+					(params.block_start.unwrap(), params.block_stop.unwrap());
+				if block_stop > 102 {
+					let error = BlocksNotInRange {
+						indexed_from: 100,
+						indexed_to: 102,
+						block_start,
+						block_stop,
+					};
+					return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+				}
+				let block_num =
+					params.cursor.map_or(block_start, |c| c.parse::<u32>().unwrap() + 1);
+				Json(QueryResultEvents {
+					data: vec![emitted(block_num)],
+					is_complete: block_num == block_stop,
+					next_cursor: Some(block_num.to_string()),
+				})
+				.into_response()
+			}
+		};
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		let app = axum::Router::new().route("/events", get(events));
+		tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+		url
+	}
+
+	#[tokio::test]
+	async fn retries_and_follows_pages() {
+		let client = EventIndexerClient::new(&serve().await).with_retry(RetryPolicy {
+			initial_backoff: Duration::from_millis(1),
+			..Default::default()
+		});
+		let params =
+			GetEventsParams { block_start: Some(100), block_stop: Some(102), ..Default::default() };
+		let events = client.all_events(&params).await.unwrap();
+		assert_eq!(events, vec![emitted(100), emitted(101), emitted(102)]);
+
+		let params = GetEventsParams { block_stop: Some(105), ..params };
+		assert!(matches!(
+			client.all_events(&params).await,
+			Err(ClientError::BlocksNotInRange(100, 102, 100, 105))
+		));
+	}
+
+	#[tokio::test]
+	async fn posts_are_not_retried() {
+		let requests = Arc::new(AtomicU32::new(0));
+		let snapshot = {
+			let requests = Arc::clone(&requests);
+			move || async move {
+				requests.fetch_add(1, Ordering::SeqCst);
+				StatusCode::SERVICE_UNAVAILABLE
+			}
+		};
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		let app = axum::Router::new().route("/admin/snapshot", post(snapshot));
+		tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+		let client = EventIndexerClient::new(&url).with_retry(RetryPolicy {
+			initial_backoff: Duration::from_millis(1),
+			..Default::default()
+		});
+		assert!(matches!(
+			client.take_snapshot().await,
+			Err(ClientError::Status(StatusCode::SERVICE_UNAVAILABLE, _))
+		));
+		assert_eq!(requests.load(Ordering::SeqCst), 1);
+	}
+}
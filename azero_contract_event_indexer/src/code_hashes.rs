@@ -0,0 +1,106 @@
+use std::collections::{HashMap, VecDeque};
+
+use azero_universal::{contract_info::backwards_compatible_get_contract_info_at, RpcPool};
+use parking_lot::Mutex;
+use subxt::utils::H256;
+
+use crate::{event_db::BlockDetails, AccountId};
+
+/// Code hashes of contracts at the blocks their events are in. A contract may change its code
+/// with `set_code_hash`, so the code it runs now says nothing about the code of its past events.
+pub struct CodeHashes {
+	rpc_pool: RpcPool,
+	cache: Mutex<Cache>,
+}
+
+// How many blocks the code hashes are cached for. Several ranges are scraped at once, so events
+// of different blocks come in interleaved.
+const CACHED_BLOCKS: usize = 256;
+
+// The code hashes looked up by block, `None` for accounts that are not live contracts there.
+// The block first cached is evicted first.
+#[derive(Default)]
+struct Cache {
+	blocks: HashMap<H256, HashMap<AccountId, Option<H256>>>,
+	order: VecDeque<H256>,
+}
+
+impl CodeHashes {
+	pub fn new(rpc_pool: RpcPool) -> Self {
+		CodeHashes { rpc_pool, cache: Mutex::new(Cache::default()) }
+	}
+
+	/// The code hash of `contract` in the state after `block`, or, for a contract terminated in
+	/// `block`, in the state before it.
+	pub async fn get(
+		&self,
+		contract: &AccountId,
+		block: &BlockDetails,
+	) -> anyhow::Result<Option<H256>> {
+		if let Some(code_hash) = self.cached(contract, block.hash) {
+			return Ok(code_hash);
+		}
+		let mut code_hash = self.lookup(contract, block.hash).await?;
+		if code_hash.is_none() {
+			code_hash = self.lookup(contract, block.parent_hash).await?;
+		}
+		self.insert(block.hash, contract.clone(), code_hash);
+		Ok(code_hash)
+	}
+
+	fn cached(&self, contract: &AccountId, block_hash: H256) -> Option<Option<H256>> {
+		self.cache.lock().blocks.get(&block_hash)?.get(contract).copied()
+	}
+
+	async fn lookup(&self, contract: &AccountId, block_hash: H256) -> anyhow::Result<Option<H256>> {
+		self.rpc_pool
+			.run(|connection| async move {
+				let info = backwards_compatible_get_contract_info_at(
+					&connection.client,
+					contract,
+					block_hash,
+				)
+				.await?;
+				Ok(info.map(|info| info.code_hash))
+			})
+			.await
+	}
+
+	pub(crate) fn insert(&self, block_hash: H256, contract: AccountId, code_hash: Option<H256>) {
+		let mut cache = self.cache.lock();
+		if !cache.blocks.contains_key(&block_hash) {
+			if cache.order.len() == CACHED_BLOCKS {
+				let oldest = cache.order.pop_front().expect("the cache is full");
+				cache.blocks.remove(&oldest);
+			}
+			cache.order.push_back(block_hash);
+		}
+		cache.blocks.entry(block_hash).or_default().insert(contract, code_hash);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{CodeHashes, CACHED_BLOCKS};
+	use crate::AccountId;
+	use azero_universal::RpcPool;
+	use subxt::utils::H256;
+
+	#[test]
+	fn code_hashes_are_cached_for_the_last_blocks() {
+		let code_hashes = CodeHashes::new(RpcPool::new(["ws://localhost:1"]));
+		let contract = AccountId::from([1u8; 32]);
+		let block_hash = |i: usize| H256::from_low_u64_be(i as u64);
+		for i in 0..=CACHED_BLOCKS {
+			code_hashes.insert(block_hash(i), contract.clone(), Some(H256([i as u8; 32])));
+		}
+
+		assert_eq!(code_hashes.cached(&contract, block_hash(0)), None);
+		assert_eq!(code_hashes.cached(&contract, block_hash(1)), Some(Some(H256([1u8; 32]))));
+		assert_eq!(
+			code_hashes.cached(&contract, block_hash(CACHED_BLOCKS)),
+			Some(Some(H256([CACHED_BLOCKS as u8; 32])))
+		);
+		assert_eq!(code_hashes.cached(&AccountId::from([2u8; 32]), block_hash(1)), None);
+	}
+}
@@ -0,0 +1,212 @@
+use std::{collections::BTreeSet, fs, sync::Arc};
+
+use azero_config::{WS_AZERO_MAINNET, WS_AZERO_TESTNET};
+use azero_universal::RpcPool;
+use serde::Deserialize;
+use subxt::utils::H256;
+
+use crate::{
+	call_decoder::CallDecoder,
+	code_hashes::CodeHashes,
+	contract_filter::ContractFilter,
+	metrics::Metrics,
+	snapshot::{SnapshotConfig, Snapshots},
+	AccountId,
+};
+
+// Kind of arbitrary choice for Aleph Zero mainnet. It contains all of Common and much more actually
+// (events since December 2023).
+const MAINNET_FIRST_BLOCK: u32 = 65000000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+	#[default]
+	Mainnet,
+	Testnet,
+}
+
+impl Network {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Network::Mainnet => "mainnet",
+			Network::Testnet => "testnet",
+		}
+	}
+
+	fn default_endpoint(&self) -> &'static str {
+		match self {
+			Network::Mainnet => WS_AZERO_MAINNET,
+			Network::Testnet => WS_AZERO_TESTNET,
+		}
+	}
+
+	fn default_first_block(&self) -> u32 {
+		match self {
+			Network::Mainnet => MAINNET_FIRST_BLOCK,
+			Network::Testnet => 0,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContractSet {
+	pub addresses: BTreeSet<AccountId>,
+	pub code_hashes: BTreeSet<H256>,
+}
+
+/// Which contracts to index.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum ContractSelection {
+	#[default]
+	All,
+	Allow(ContractSet),
+	Deny(ContractSet),
+}
+
+/// Settings of the indexer of one network. Every field is optional, an empty config indexes all
+/// contracts on mainnet.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndexerConfig {
+	pub network: Network,
+	/// `db/<network>_events.db` by default.
+	pub database_file: Option<String>,
+	/// The public node of the network by default, or `RPC_ENDPOINTS` if only one network is
+	/// indexed.
+	pub rpc_endpoints: Option<Vec<String>>,
+	/// The scraper does not go below this block, 0 indexes from genesis.
+	pub first_block: Option<u32>,
+	pub contracts: ContractSelection,
+	pub snapshots: SnapshotConfig,
+	/// Snapshot to start from if the database file does not exist yet.
+	pub restore_from: Option<String>,
+	/// Directory of ink! metadata files, used to decode the inputs of calls to contracts running
+	/// their code.
+	pub ink_metadata_dir: Option<String>,
+}
+
+// A config file holds either the config of one network, or a list of them.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MultiNetworkConfig {
+	networks: Vec<IndexerConfig>,
+}
+
+impl IndexerConfig {
+	/// The configs in the file named by `INDEXER_CONFIG`, or the default one if it is not set.
+	pub fn from_env() -> anyhow::Result<Vec<Self>> {
+		match std::env::var("INDEXER_CONFIG") {
+			Ok(path) => {
+				let text = fs::read_to_string(&path)
+					.map_err(|e| anyhow::anyhow!("Failed to open config {}: {}", path, e))?;
+				Self::parse(&text).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path, e))
+			},
+			Err(_) => Ok(vec![Self::default()]),
+		}
+	}
+
+	fn parse(text: &str) -> anyhow::Result<Vec<Self>> {
+		let value: serde_json::Value = serde_json::from_str(text)?;
+		let configs = if value.get("networks").is_some() {
+			serde_json::from_value::<MultiNetworkConfig>(value)?.networks
+		} else {
+			vec![serde_json::from_value(value)?]
+		};
+		if configs.is_empty() {
+			anyhow::bail!("no network configured");
+		}
+		let mut networks = BTreeSet::new();
+		for config in &configs {
+			if !networks.insert(config.network.name()) {
+				anyhow::bail!("network {} configured twice", config.network.name());
+			}
+		}
+		Ok(configs)
+	}
+
+	/// Resolves `configs`, the first one is the default network of the API.
+	pub fn into_contexts(configs: Vec<Self>) -> anyhow::Result<Vec<IndexerContext>> {
+		let single_network = configs.len() == 1;
+		let contexts: Vec<IndexerContext> = configs
+			.into_iter()
+			.map(|config| config.into_context(single_network))
+			.collect::<anyhow::Result<_>>()?;
+		let mut database_files = BTreeSet::new();
+		for context in &contexts {
+			if !database_files.insert(context.database_file.as_str()) {
+				anyhow::bail!("database file {} used by several networks", context.database_file);
+			}
+		}
+		Ok(contexts)
+	}
+
+	fn into_context(self, single_network: bool) -> anyhow::Result<IndexerContext> {
+		let rpc_pool = match self.rpc_endpoints {
+			Some(endpoints) => RpcPool::new(endpoints),
+			None if single_network =>
+				RpcPool::from_env("RPC_ENDPOINTS", self.network.default_endpoint()),
+			None => RpcPool::new([self.network.default_endpoint()]),
+		};
+		let database_file = self
+			.database_file
+			.unwrap_or_else(|| format!("db/{}_events.db", self.network.name()));
+		let code_hashes = Arc::new(CodeHashes::new(rpc_pool.clone()));
+		Ok(IndexerContext {
+			network: self.network,
+			snapshots: Arc::new(Snapshots::new(
+				self.snapshots,
+				self.network,
+				database_file.clone(),
+			)),
+			restore_from: self.restore_from,
+			database_file,
+			first_block: self.first_block.unwrap_or_else(|| self.network.default_first_block()),
+			contract_filter: Arc::new(ContractFilter::new(self.contracts, code_hashes.clone())),
+			call_decoder: Arc::new(CallDecoder::from_dir(
+				self.ink_metadata_dir.as_deref(),
+				code_hashes,
+			)?),
+			rpc_pool,
+			metrics: Arc::new(Metrics::default()),
+		})
+	}
+}
+
+/// What the scraper and the API of an indexer share, resolved from its `IndexerConfig`.
+#[derive(Clone)]
+pub struct IndexerContext {
+	pub network: Network,
+	pub database_file: String,
+	pub rpc_pool: RpcPool,
+	pub first_block: u32,
+	pub contract_filter: Arc<ContractFilter>,
+	pub call_decoder: Arc<CallDecoder>,
+	pub metrics: Arc<Metrics>,
+	pub snapshots: Arc<Snapshots>,
+	pub restore_from: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{IndexerConfig, Network};
+
+	#[test]
+	fn config_file_holds_one_network_or_a_list() {
+		let configs = IndexerConfig::parse(r#"{"network": "testnet", "first_block": 5}"#).unwrap();
+		assert_eq!(configs.len(), 1);
+		assert_eq!(configs[0].network, Network::Testnet);
+		assert_eq!(configs[0].first_block, Some(5));
+
+		let configs =
+			IndexerConfig::parse(r#"{"networks": [{}, {"network": "testnet"}]}"#).unwrap();
+		let networks: Vec<Network> = configs.iter().map(|config| config.network).collect();
+		assert_eq!(networks, vec![Network::Mainnet, Network::Testnet]);
+
+		assert!(IndexerConfig::parse(r#"{"networks": []}"#).is_err());
+		assert!(IndexerConfig::parse(r#"{"networks": [{}, {"network": "mainnet"}]}"#).is_err());
+		assert!(IndexerConfig::parse(r#"{"networks": [{}], "first_block": 5}"#).is_err());
+	}
+}
@@ -0,0 +1,224 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use subxt::utils::H256;
+
+use crate::{
+	code_hashes::CodeHashes,
+	config::{ContractSelection, ContractSet},
+	event_db::{BlockDetails, Event, EventType},
+	scraper::ScrapedBlock,
+	AccountId,
+};
+
+// Code hashes carried by the event itself.
+fn event_code_hashes(event: &Event) -> Vec<H256> {
+	match &event.event_type {
+		EventType::CodeStored(details) => vec![details.code_hash],
+		EventType::CodeRemoved(details) => vec![details.code_hash],
+		EventType::DelegateCalled(details) => vec![details.code_hash],
+		EventType::ContractCodeUpdated(details) =>
+			vec![details.old_code_hash, details.new_code_hash],
+		EventType::Instantiated(details) => details.code_hash.into_iter().collect(),
+		_ => Vec::new(),
+	}
+}
+
+/// Drops the events of contracts the indexer is not configured for, see `ContractSelection`. An
+/// event matches a `ContractSet` if its contract address is listed, if it carries a listed code
+/// hash, or if its contract runs a listed code hash at the block of the event.
+pub struct ContractFilter {
+	selection: ContractSelection,
+	code_hashes: Arc<CodeHashes>,
+}
+
+impl ContractFilter {
+	pub fn new(selection: ContractSelection, code_hashes: Arc<CodeHashes>) -> Self {
+		ContractFilter { selection, code_hashes }
+	}
+
+	pub fn is_all(&self) -> bool {
+		matches!(self.selection, ContractSelection::All)
+	}
+
+	async fn matches(
+		&self,
+		set: &ContractSet,
+		event: &Event,
+		block: &BlockDetails,
+	) -> anyhow::Result<bool> {
+		if event.contract_account_id.as_ref().is_some_and(|c| set.addresses.contains(c)) ||
+			event_code_hashes(event)
+				.iter()
+				.any(|code_hash| set.code_hashes.contains(code_hash))
+		{
+			return Ok(true);
+		}
+		// Code events are not tied to a contract.
+		let Some(contract) = &event.contract_account_id else {
+			return Ok(false);
+		};
+		if set.code_hashes.is_empty() {
+			return Ok(false);
+		}
+		Ok(self
+			.code_hashes
+			.get(contract, block)
+			.await?
+			.is_some_and(|code_hash| set.code_hashes.contains(&code_hash)))
+	}
+
+	async fn keeps(&self, event: &Event, block: &BlockDetails) -> anyhow::Result<bool> {
+		match &self.selection {
+			ContractSelection::All => Ok(true),
+			ContractSelection::Allow(set) => self.matches(set, event, block).await,
+			ContractSelection::Deny(set) => Ok(!self.matches(set, event, block).await?),
+		}
+	}
+
+	/// Keeps the selected events of `block`, and the extrinsics they were emitted in. The block
+	/// itself is always kept.
+	pub async fn apply(&self, block: ScrapedBlock) -> anyhow::Result<ScrapedBlock> {
+		if self.is_all() {
+			return Ok(block);
+		}
+		let mut events = Vec::new();
+		for event in block.events {
+			if self.keeps(&event, &block.block).await? {
+				events.push(event);
+			}
+		}
+		let extrinsic_indices: BTreeSet<u32> =
+			events.iter().map(|event| event.extrinsic_index).collect();
+		let extrinsics = block
+			.extrinsics
+			.into_iter()
+			.filter(|extrinsic| extrinsic_indices.contains(&extrinsic.extrinsic_index))
+			.collect();
+		Ok(ScrapedBlock { block: block.block, extrinsics, events })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::ContractFilter;
+	use crate::{
+		code_hashes::CodeHashes,
+		config::{ContractSelection, ContractSet},
+		event_db::{BlockDetails, CodeDetails, CodeStoredDetails, Event, EventType, Extrinsic},
+		scraper::ScrapedBlock,
+		AccountId,
+	};
+	use azero_universal::RpcPool;
+	use std::sync::Arc;
+	use subxt::utils::H256;
+
+	fn emitted(contract: u8, event_index: u32, extrinsic_index: u32) -> Event {
+		Event::new_emitted(
+			AccountId::from([contract; 32]),
+			7,
+			event_index,
+			extrinsic_index,
+			vec![],
+			vec![],
+		)
+	}
+
+	fn extrinsic(extrinsic_index: u32) -> Extrinsic {
+		Extrinsic {
+			block_num: 7,
+			extrinsic_index,
+			hash: H256([extrinsic_index as u8; 32]),
+			signer: None,
+			pallet: "Contracts".to_string(),
+			call: "call".to_string(),
+			success: true,
+			fee: None,
+		}
+	}
+
+	fn block() -> ScrapedBlock {
+		ScrapedBlock {
+			block: BlockDetails {
+				block_num: 7,
+				hash: H256([7u8; 32]),
+				parent_hash: H256([6u8; 32]),
+				timestamp: Some(7000),
+			},
+			extrinsics: vec![extrinsic(1), extrinsic(2), extrinsic(3)],
+			events: vec![
+				emitted(1, 0, 1),
+				emitted(2, 1, 2),
+				Event::new(
+					AccountId::from([3u8; 32]),
+					7,
+					2,
+					3,
+					EventType::DelegateCalled(CodeDetails { code_hash: H256([9u8; 32]) }),
+				),
+			],
+		}
+	}
+
+	fn filter(selection: ContractSelection) -> ContractFilter {
+		ContractFilter::new(
+			selection,
+			Arc::new(CodeHashes::new(RpcPool::new(["ws://localhost:1"]))),
+		)
+	}
+
+	#[tokio::test]
+	async fn allowlist_keeps_listed_contracts_and_their_extrinsics() {
+		let set = ContractSet {
+			addresses: [AccountId::from([1u8; 32])].into(),
+			code_hashes: Default::default(),
+		};
+		let filtered = filter(ContractSelection::Allow(set)).apply(block()).await.unwrap();
+		assert_eq!(filtered.events, vec![emitted(1, 0, 1)]);
+		assert_eq!(filtered.extrinsics, vec![extrinsic(1)]);
+		assert_eq!(filtered.block, block().block);
+	}
+
+	#[tokio::test]
+	async fn denylist_drops_events_carrying_listed_code_hash() {
+		let set = ContractSet {
+			addresses: [AccountId::from([2u8; 32])].into(),
+			code_hashes: [H256([9u8; 32])].into(),
+		};
+		// Contract 1 is looked up on the chain, which is unreachable here, so give it a known code
+		// hash first.
+		let filter = filter(ContractSelection::Deny(set));
+		filter.code_hashes.insert(
+			H256([7u8; 32]),
+			AccountId::from([1u8; 32]),
+			Some(H256([8u8; 32])),
+		);
+		let filtered = filter.apply(block()).await.unwrap();
+		assert_eq!(filtered.events, vec![emitted(1, 0, 1)]);
+		assert_eq!(filtered.extrinsics, vec![extrinsic(1)]);
+	}
+
+	#[tokio::test]
+	async fn code_events_of_other_code_are_dropped_without_a_lookup() {
+		let set =
+			ContractSet { addresses: Default::default(), code_hashes: [H256([9u8; 32])].into() };
+		let uploaded = Event::new_code(
+			7,
+			0,
+			1,
+			EventType::CodeStored(CodeStoredDetails {
+				code_hash: H256([5u8; 32]),
+				uploader: Some(AccountId::from([4u8; 32])),
+			}),
+		);
+		let scraped = ScrapedBlock { events: vec![uploaded], ..block() };
+		// The chain is unreachable here, so a lookup would fail the block.
+		let filtered = filter(ContractSelection::Allow(set)).apply(scraped).await.unwrap();
+		assert!(filtered.events.is_empty());
+		assert!(filtered.extrinsics.is_empty());
+	}
+
+	#[tokio::test]
+	async fn everything_is_kept_by_default() {
+		assert_eq!(filter(ContractSelection::All).apply(block()).await.unwrap(), block());
+	}
+}
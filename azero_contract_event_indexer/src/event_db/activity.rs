@@ -0,0 +1,94 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{
+	check_indexed_range, extrinsic_events, extrinsic_from_row, get_bounds_with_conn, DbError,
+	Event, Extrinsic, EXTRINSIC_COLUMNS,
+};
+use crate::AccountId;
+
+/// An extrinsic in which an account called a contract.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct AccountActivity {
+	pub block_num: u32,
+	pub extrinsic_index: u32,
+	/// `None` for blocks indexed by a version that did not store extrinsics.
+	pub extrinsic: Option<Extrinsic>,
+	/// The contracts with events in the extrinsic, in the order they first appear.
+	pub contracts: Vec<AccountId>,
+	/// All the stored events of the extrinsic, not only the calls made by the account.
+	pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct AccountActivityPage {
+	pub data: Vec<AccountActivity>,
+	/// Whether the activity up to the end of the requested range is included.
+	pub is_complete: bool,
+	/// Present if the page is partial. Pass it back as `block_start` to get the next page, pages
+	/// never split a block.
+	pub next_block_start: Option<u32>,
+}
+
+/// The extrinsics of `block_start..=block_stop` in which `account` called a contract, directly or
+/// through another contract. The range defaults to the whole indexed range. After `limit`
+/// extrinsics the page ends with the block of the last one.
+pub fn get_account_activity(
+	account: &AccountId,
+	block_start: Option<u32>,
+	block_stop: Option<u32>,
+	limit: u32,
+	conn: &Connection,
+) -> Result<AccountActivityPage, DbError> {
+	let (indexed_from, indexed_to) = get_bounds_with_conn(conn)?;
+	let block_start = block_start.unwrap_or(indexed_from);
+	let block_stop = block_stop.unwrap_or(indexed_to);
+	if block_start > block_stop {
+		return Ok(AccountActivityPage {
+			data: Vec::new(),
+			is_complete: true,
+			next_block_start: None,
+		});
+	}
+	check_indexed_range(block_start, block_stop, conn)?;
+
+	let mut stmt = conn.prepare(
+		"SELECT DISTINCT block_num, extrinsic_index FROM events
+		WHERE caller = ?1 AND event_type = 'called' AND block_num BETWEEN ?2 AND ?3
+		ORDER BY block_num ASC, extrinsic_index ASC",
+	)?;
+	let mut rows = stmt.query(params![&account.0[..], block_start, block_stop])?;
+	let mut extrinsics: Vec<(u32, u32)> = Vec::new();
+	let mut next_block_start = None;
+	while let Some(row) = rows.next()? {
+		let (block_num, extrinsic_index): (u32, u32) = (row.get(0)?, row.get(1)?);
+		if extrinsics.len() >= limit as usize &&
+			extrinsics.last().is_some_and(|(last_block, _)| *last_block != block_num)
+		{
+			next_block_start = Some(block_num);
+			break;
+		}
+		extrinsics.push((block_num, extrinsic_index));
+	}
+
+	let mut extrinsic_stmt = conn.prepare(&format!(
+		"SELECT {} FROM extrinsics WHERE block_num = ?1 AND extrinsic_index = ?2",
+		EXTRINSIC_COLUMNS
+	))?;
+	let mut data = Vec::new();
+	for (block_num, extrinsic_index) in extrinsics {
+		let extrinsic = extrinsic_stmt
+			.query_row(params![block_num, extrinsic_index], extrinsic_from_row)
+			.optional()?;
+		let events = extrinsic_events(block_num, extrinsic_index, conn)?;
+		let mut contracts: Vec<AccountId> = Vec::new();
+		for contract in events.iter().filter_map(|event| event.contract_account_id.as_ref()) {
+			if !contracts.contains(contract) {
+				contracts.push(contract.clone());
+			}
+		}
+		data.push(AccountActivity { block_num, extrinsic_index, extrinsic, contracts, events });
+	}
+	Ok(AccountActivityPage { data, is_complete: next_block_start.is_none(), next_block_start })
+}
@@ -1,22 +1,93 @@
 use crate::{AccountId, QueryResult};
-use rusqlite::{params, Connection, Result as SqliteResult};
+use rusqlite::{
+	params, types::Type, Connection, Error::FromSqlConversionFailure, Result as SqliteResult,
+};
 use serde::{Deserialize, Serialize};
-use serde_with::{hex::Hex, serde_as};
+use serde_with::{hex::Hex, serde_as, DisplayFromStr};
 use std::path::Path;
+use subxt::utils::H256;
 use thiserror::Error;
 use utoipa::ToSchema;
-pub const DATABASE_FILE: &str = "db/mainnet_events.db";
+
+pub mod activity;
+pub mod registry;
+pub mod stats;
+#[cfg(test)]
+mod tests;
+
 const MAX_TOTAL_RESULT_SIZE: usize = 1256000;
+const EVENT_COLUMNS: &str = "contract_account_id, block_num, event_index, extrinsic_index, \
+	event_type, caller, data, deployer, beneficiary, code_hash, old_code_hash, message, args, \
+	uploader, remover";
+// `contract_account_id` is NULL for code events, their account, if named, is in `uploader` or
+// `remover`.
+const EVENTS_TABLE: &str = "(
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	contract_account_id BLOB,
+	block_num INTEGER NOT NULL,
+	event_index INTEGER NOT NULL,
+	extrinsic_index INTEGER NOT NULL,
+	event_type TEXT NOT NULL,
+	caller BLOB,
+	data BLOB NOT NULL,
+	deployer BLOB,
+	beneficiary BLOB,
+	code_hash BLOB,
+	old_code_hash BLOB,
+	selector BLOB,
+	message TEXT,
+	args TEXT,
+	uploader BLOB,
+	remover BLOB,
+	UNIQUE (block_num, event_index)
+)";
+const EVENTS_TABLE_COLUMNS: &str = "id, contract_account_id, block_num, event_index, \
+	extrinsic_index, event_type, caller, data, deployer, beneficiary, code_hash, old_code_hash, \
+	selector, message, args, uploader, remover";
+const TOPICS_QUERY: &str = "SELECT topic FROM event_topics
+	WHERE block_num = ?1 AND event_index = ?2
+	ORDER BY topic_index ASC";
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub enum EventType {
 	Emitted(EmittedDetails),
 	Called(CalledDetails),
+	Instantiated(InstantiatedDetails),
+	Terminated(TerminatedDetails),
+	CodeStored(CodeStoredDetails),
+	CodeRemoved(CodeRemovedDetails),
+	ContractCodeUpdated(CodeUpdatedDetails),
+	DelegateCalled(CodeDetails),
+}
+
+impl EventType {
+	/// The name of the variant, as it appears in JSON.
+	pub fn name(&self) -> &'static str {
+		match self {
+			EventType::Emitted(_) => "Emitted",
+			EventType::Called(_) => "Called",
+			EventType::Instantiated(_) => "Instantiated",
+			EventType::Terminated(_) => "Terminated",
+			EventType::CodeStored(_) => "CodeStored",
+			EventType::CodeRemoved(_) => "CodeRemoved",
+			EventType::ContractCodeUpdated(_) => "ContractCodeUpdated",
+			EventType::DelegateCalled(_) => "DelegateCalled",
+		}
+	}
+
+	pub fn caller(&self) -> Option<&Origin> {
+		match self {
+			EventType::Called(details) => Some(&details.caller),
+			_ => None,
+		}
+	}
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub struct Event {
-	pub contract_account_id: AccountId,
+	/// The contract the event is about, `None` for `CodeStored` and `CodeRemoved`, which are not
+	/// tied to any contract.
+	pub contract_account_id: Option<AccountId>,
 	pub block_num: u32,
 	pub event_index: u32,
 	pub extrinsic_index: u32,
@@ -27,24 +98,54 @@ impl Event {
 	fn size(&self) -> usize {
 		let base = 32 + 4 + 4 + 4;
 		match &self.event_type {
-			EventType::Emitted(details) => base + details.data.len(),
-			EventType::Called(_) => base + 32,
+			EventType::Emitted(details) => base + details.data.len() + 32 * details.topics.len(),
+			EventType::Called(CalledDetails { input: Some(input), .. }) =>
+				base + 32 + input.data.len(),
+			EventType::ContractCodeUpdated(_) => base + 64,
+			_ => base + 32,
 		}
 	}
 
+	pub fn new(
+		contract_account_id: AccountId,
+		block_num: u32,
+		event_index: u32,
+		extrinsic_index: u32,
+		event_type: EventType,
+	) -> Self {
+		Self {
+			contract_account_id: Some(contract_account_id),
+			block_num,
+			event_index,
+			extrinsic_index,
+			event_type,
+		}
+	}
+
+	/// A `CodeStored` or `CodeRemoved` event.
+	pub fn new_code(
+		block_num: u32,
+		event_index: u32,
+		extrinsic_index: u32,
+		event_type: EventType,
+	) -> Self {
+		Self { contract_account_id: None, block_num, event_index, extrinsic_index, event_type }
+	}
+
 	pub fn new_emitted(
 		contract_account_id: AccountId,
 		block_num: u32,
 		event_index: u32,
 		extrinsic_index: u32,
 		data: Vec<u8>,
+		topics: Vec<H256>,
 	) -> Self {
 		Self {
-			contract_account_id,
+			contract_account_id: Some(contract_account_id),
 			block_num,
 			event_index,
 			extrinsic_index,
-			event_type: EventType::Emitted(EmittedDetails { data }),
+			event_type: EventType::Emitted(EmittedDetails { data, topics }),
 		}
 	}
 
@@ -53,14 +154,14 @@ impl Event {
 		block_num: u32,
 		event_index: u32,
 		extrinsic_index: u32,
-		caller: AccountId,
+		caller: Origin,
 	) -> Self {
 		Self {
-			contract_account_id,
+			contract_account_id: Some(contract_account_id),
 			block_num,
 			event_index,
 			extrinsic_index,
-			event_type: EventType::Called(CalledDetails { caller }),
+			event_type: EventType::Called(CalledDetails { caller, input: None }),
 		}
 	}
 }
@@ -70,65 +171,297 @@ impl Event {
 pub struct EmittedDetails {
 	#[serde_as(as = "Hex")]
 	pub data: Vec<u8>,
+	/// ink! puts the event signature and the `#[ink(topic)]` fields here.
+	#[serde(default)]
+	#[schema(value_type = Vec<String>)]
+	pub topics: Vec<H256>,
+}
+
+/// Who called a contract: a signed account, or `Root` for sudo and governance calls.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum Origin {
+	Root,
+	Signed(AccountId),
+}
+
+impl From<AccountId> for Origin {
+	fn from(account: AccountId) -> Self {
+		Self::Signed(account)
+	}
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub struct CalledDetails {
-	pub caller: AccountId,
+	pub caller: Origin,
+	/// Set if the extrinsic made the call itself rather than through another contract, in blocks
+	/// indexed by a version that records call inputs.
+	#[serde(default)]
+	pub input: Option<CallInput>,
+}
+
+/// What a contract was called with.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct CallInput {
+	/// The ink! message selector, i.e. the first 4 bytes of `data`.
+	#[serde_as(as = "Hex")]
+	#[schema(value_type = String)]
+	pub selector: [u8; 4],
+	#[serde_as(as = "Hex")]
+	#[schema(value_type = String)]
+	pub data: Vec<u8>,
+	/// Name of the ink! message, if the metadata of the contract is registered.
+	pub message: Option<String>,
+	/// The arguments of the message by name, if the metadata of the contract is registered.
+	#[schema(value_type = Option<Object>)]
+	pub args: Option<serde_json::Value>,
+}
+
+impl CallInput {
+	/// `None` if `data` is too short to hold a selector.
+	pub fn new(data: Vec<u8>) -> Option<Self> {
+		let selector = data.get(..4)?.try_into().unwrap();
+		Some(Self { selector, data, message: None, args: None })
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct InstantiatedDetails {
+	pub deployer: AccountId,
+	/// The code the contract was instantiated with, if it was read from the chain.
+	#[serde(default)]
+	#[schema(value_type = Option<String>)]
+	pub code_hash: Option<H256>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct TerminatedDetails {
+	pub beneficiary: AccountId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct CodeDetails {
+	#[schema(value_type = String)]
+	pub code_hash: H256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct CodeStoredDetails {
+	#[schema(value_type = String)]
+	pub code_hash: H256,
+	/// `None` in runtime 68, whose events do not name the uploader.
+	pub uploader: Option<AccountId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct CodeRemovedDetails {
+	#[schema(value_type = String)]
+	pub code_hash: H256,
+	/// `None` in runtime 68, whose events do not name the remover.
+	pub remover: Option<AccountId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct CodeUpdatedDetails {
+	#[schema(value_type = String)]
+	pub old_code_hash: H256,
+	#[schema(value_type = String)]
+	pub new_code_hash: H256,
+}
+
+/// A block in the indexed range. It is stored even if it has no contract events, so an empty block
+/// can be told apart from a missing one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockDetails {
+	pub block_num: u32,
+	pub hash: H256,
+	pub parent_hash: H256,
+	/// Milliseconds since the Unix epoch, as set by `Timestamp::set` in the block. `None` if the
+	/// state of the block has no timestamp, as for the genesis block.
+	pub timestamp: Option<u64>,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct Extrinsic {
+	pub block_num: u32,
+	pub extrinsic_index: u32,
+	#[schema(value_type = String)]
+	pub hash: H256,
+	/// `None` for unsigned extrinsics.
+	pub signer: Option<AccountId>,
+	pub pallet: String,
+	pub call: String,
+	pub success: bool,
+	/// Fee paid, including the tip. `None` if no fee was charged.
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	#[schema(value_type = Option<String>)]
+	pub fee: Option<u128>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct ExtrinsicWithEvents {
+	pub extrinsic: Extrinsic,
+	pub events: Vec<Event>,
 }
 
 struct DBEvent {
-	pub contract_account_id: [u8; 32],
+	pub contract_account_id: Option<[u8; 32]>,
 	pub block_num: u32,
 	pub event_index: u32,
 	pub extrinsic_index: u32,
 	pub event_type: String,
+	/// For `called` this is `None` when the caller is `Root`.
 	pub caller: Option<[u8; 32]>,
 	pub data: Vec<u8>,
+	pub deployer: Option<[u8; 32]>,
+	pub beneficiary: Option<[u8; 32]>,
+	/// For `contract_code_updated` this is the new code hash.
+	pub code_hash: Option<[u8; 32]>,
+	pub old_code_hash: Option<[u8; 32]>,
+	pub uploader: Option<[u8; 32]>,
+	pub remover: Option<[u8; 32]>,
+	/// For `called` with a known input, `data` is the input and these are derived from it.
+	pub selector: Option<[u8; 4]>,
+	pub message: Option<String>,
+	/// JSON.
+	pub args: Option<String>,
+	/// Stored in the `event_topics` table.
+	pub topics: Vec<[u8; 32]>,
 }
 
 impl From<Event> for DBEvent {
 	fn from(event: Event) -> Self {
-		let contract_account_id = event.contract_account_id.0;
-		let block_num = event.block_num;
-		let event_index = event.event_index;
-		let extrinsic_index = event.extrinsic_index;
+		let mut db_event = Self {
+			contract_account_id: event.contract_account_id.map(|contract| contract.0),
+			block_num: event.block_num,
+			event_index: event.event_index,
+			extrinsic_index: event.extrinsic_index,
+			event_type: String::new(),
+			caller: None,
+			data: Vec::new(),
+			deployer: None,
+			beneficiary: None,
+			code_hash: None,
+			old_code_hash: None,
+			uploader: None,
+			remover: None,
+			selector: None,
+			message: None,
+			args: None,
+			topics: Vec::new(),
+		};
 		match event.event_type {
-			EventType::Emitted(details) => Self {
-				contract_account_id,
-				block_num,
-				event_index,
-				extrinsic_index,
-				event_type: "emitted".to_string(),
-				caller: None,
-				data: details.data,
+			EventType::Emitted(details) => {
+				db_event.event_type = "emitted".to_string();
+				db_event.data = details.data;
+				db_event.topics = details.topics.into_iter().map(|topic| topic.0).collect();
 			},
-			EventType::Called(details) => Self {
-				contract_account_id,
-				block_num,
-				event_index,
-				extrinsic_index,
-				event_type: "called".to_string(),
-				caller: Some(details.caller.0),
-				data: Vec::new(),
+			EventType::Called(details) => {
+				db_event.event_type = "called".to_string();
+				db_event.caller = match details.caller {
+					Origin::Signed(caller) => Some(caller.0),
+					Origin::Root => None,
+				};
+				if let Some(input) = details.input {
+					db_event.selector = Some(input.selector);
+					db_event.message = input.message;
+					db_event.args = input.args.map(|args| args.to_string());
+					db_event.data = input.data;
+				}
+			},
+			EventType::Instantiated(details) => {
+				db_event.event_type = "instantiated".to_string();
+				db_event.deployer = Some(details.deployer.0);
+				db_event.code_hash = details.code_hash.map(|code_hash| code_hash.0);
+			},
+			EventType::Terminated(details) => {
+				db_event.event_type = "terminated".to_string();
+				db_event.beneficiary = Some(details.beneficiary.0);
+			},
+			EventType::CodeStored(details) => {
+				db_event.event_type = "code_stored".to_string();
+				db_event.code_hash = Some(details.code_hash.0);
+				db_event.uploader = details.uploader.map(|uploader| uploader.0);
+			},
+			EventType::CodeRemoved(details) => {
+				db_event.event_type = "code_removed".to_string();
+				db_event.code_hash = Some(details.code_hash.0);
+				db_event.remover = details.remover.map(|remover| remover.0);
+			},
+			EventType::ContractCodeUpdated(details) => {
+				db_event.event_type = "contract_code_updated".to_string();
+				db_event.code_hash = Some(details.new_code_hash.0);
+				db_event.old_code_hash = Some(details.old_code_hash.0);
+			},
+			EventType::DelegateCalled(details) => {
+				db_event.event_type = "delegate_called".to_string();
+				db_event.code_hash = Some(details.code_hash.0);
 			},
 		}
+		db_event
 	}
 }
 
-impl From<DBEvent> for Event {
-	fn from(event: DBEvent) -> Self {
-		let contract_account_id = AccountId::from(event.contract_account_id);
+impl TryFrom<DBEvent> for Event {
+	type Error = DbError;
+
+	fn try_from(event: DBEvent) -> Result<Self, DbError> {
 		let block_num = event.block_num;
 		let event_index = event.event_index;
 		let extrinsic_index = event.extrinsic_index;
+		let invalid = |reason: String| DbError::InvalidEvent(block_num, event_index, reason);
+		// A column the event type requires, NULL only in a corrupt row.
+		let required = |value: Option<[u8; 32]>, column: &str| {
+			value.ok_or_else(|| invalid(format!("no {}", column)))
+		};
 		let event_type = match event.event_type.as_str() {
-			"emitted" => EventType::Emitted(EmittedDetails { data: event.data }),
-			"called" =>
-				EventType::Called(CalledDetails { caller: AccountId::from(event.caller.unwrap()) }),
-			_ => panic!("Unknown event type"),
+			"emitted" => EventType::Emitted(EmittedDetails {
+				data: event.data,
+				topics: event.topics.into_iter().map(H256).collect(),
+			}),
+			"called" => EventType::Called(CalledDetails {
+				caller: match event.caller {
+					Some(caller) => Origin::Signed(AccountId::from(caller)),
+					None => Origin::Root,
+				},
+				input: CallInput::new(event.data).map(|input| CallInput {
+					message: event.message,
+					args: event.args.and_then(|args| serde_json::from_str(&args).ok()),
+					..input
+				}),
+			}),
+			"instantiated" => EventType::Instantiated(InstantiatedDetails {
+				deployer: AccountId::from(required(event.deployer, "deployer")?),
+				code_hash: event.code_hash.map(H256),
+			}),
+			"terminated" => EventType::Terminated(TerminatedDetails {
+				beneficiary: AccountId::from(required(event.beneficiary, "beneficiary")?),
+			}),
+			"code_stored" => EventType::CodeStored(CodeStoredDetails {
+				code_hash: H256(required(event.code_hash, "code_hash")?),
+				uploader: event.uploader.map(AccountId::from),
+			}),
+			"code_removed" => EventType::CodeRemoved(CodeRemovedDetails {
+				code_hash: H256(required(event.code_hash, "code_hash")?),
+				remover: event.remover.map(AccountId::from),
+			}),
+			"contract_code_updated" => EventType::ContractCodeUpdated(CodeUpdatedDetails {
+				old_code_hash: H256(required(event.old_code_hash, "old_code_hash")?),
+				new_code_hash: H256(required(event.code_hash, "code_hash")?),
+			}),
+			"delegate_called" => EventType::DelegateCalled(CodeDetails {
+				code_hash: H256(required(event.code_hash, "code_hash")?),
+			}),
+			other => return Err(invalid(format!("unknown type {}", other))),
 		};
-		Event { contract_account_id, block_num, event_index, extrinsic_index, event_type }
+		Ok(Event {
+			contract_account_id: event.contract_account_id.map(AccountId::from),
+			block_num,
+			event_index,
+			extrinsic_index,
+			event_type,
+		})
 	}
 }
 
@@ -142,11 +475,63 @@ pub enum DbError {
 	IncorrectedBlockToInsert(u32, u32, u32),
 	#[error("Queried blocks ({0}, {1}) are not in the range [{2}, {3}]")]
 	BlocksNotInRange(u32, u32, u32, u32),
+	#[error("Invalid cursor: {0}")]
+	InvalidCursor(String),
+	#[error("Invalid event {1} of block {0}: {2}")]
+	InvalidEvent(u32, u32, String),
+	#[error("Queried times ({0}, {1}) are not in the range [{2}, {3}]")]
+	TimesNotInRange(u64, u64, u64, u64),
+	#[error("No blocks with timestamps are indexed")]
+	NoTimestampsIndexed,
+	#[error("Block {0} has no timestamp indexed")]
+	NoBlockTimestamp(u32),
+	#[error("Database schema version is {0}, expected {1}")]
+	OutdatedSchema(u32, u32),
+}
+
+/// Position of an event in the chain, used to resume a query right after the last returned event.
+/// It is passed around as an opaque hex string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EventCursor {
+	pub block_num: u32,
+	pub event_index: u32,
+}
+
+impl EventCursor {
+	pub fn after(event: &Event) -> Self {
+		Self { block_num: event.block_num, event_index: event.event_index }
+	}
+
+	pub fn encode(&self) -> String {
+		let mut bytes = [0u8; 8];
+		bytes[..4].copy_from_slice(&self.block_num.to_be_bytes());
+		bytes[4..].copy_from_slice(&self.event_index.to_be_bytes());
+		hex::encode(bytes)
+	}
+
+	pub fn decode(cursor: &str) -> Result<Self, DbError> {
+		let bytes = hex::decode(cursor).map_err(|_| DbError::InvalidCursor(cursor.to_string()))?;
+		let bytes: [u8; 8] =
+			bytes.try_into().map_err(|_| DbError::InvalidCursor(cursor.to_string()))?;
+		Ok(Self {
+			block_num: u32::from_be_bytes(bytes[..4].try_into().unwrap()),
+			event_index: u32::from_be_bytes(bytes[4..].try_into().unwrap()),
+		})
+	}
+
+	// Returns the (block_num, event_index) pair to compare against in SQL. Without a cursor nothing
+	// is skipped, hence -1.
+	fn sql_params(cursor: Option<&EventCursor>) -> (i64, i64) {
+		match cursor {
+			Some(c) => (c.block_num as i64, c.event_index as i64),
+			None => (-1, -1),
+		}
+	}
 }
 
-pub fn get_bounds() -> SqliteResult<(u32, u32)> {
+pub fn get_bounds(database_file: &str) -> SqliteResult<(u32, u32)> {
 	let conn = {
-		let c = Connection::open(Path::new(DATABASE_FILE));
+		let c = Connection::open(Path::new(database_file));
 		if let Err(e) = &c {
 			log::info!("Error opening connection in get_bounds: {:?}", e);
 		}
@@ -155,18 +540,18 @@ pub fn get_bounds() -> SqliteResult<(u32, u32)> {
 	get_bounds_with_conn(&conn)
 }
 
-pub fn get_connection() -> SqliteResult<Connection> {
-	let conn = Connection::open(Path::new(DATABASE_FILE));
+pub fn get_connection(database_file: &str) -> SqliteResult<Connection> {
+	let conn = Connection::open(Path::new(database_file));
 	if let Err(e) = &conn {
 		log::info!("Error opening connection in get_connection: {:?}", e);
 	}
 	conn
 }
 
-pub fn get_connection_with_backoff() -> Connection {
+pub fn get_connection_with_backoff(database_file: &str) -> Connection {
 	let mut sleep_secs = 0.001;
 	loop {
-		match get_connection() {
+		match get_connection(database_file) {
 			Ok(conn) => return conn,
 			Err(e) => {
 				log::info!("Error getting connection: {:?}", e);
@@ -186,27 +571,74 @@ pub fn get_bounds_with_conn(conn: &Connection) -> SqliteResult<(u32, u32)> {
 	})
 }
 
-pub fn init_db(block_num: u32) -> SqliteResult<()> {
-	let mut conn = Connection::open(Path::new(DATABASE_FILE))?;
+// Bumped with every migration in `init_db`, so that a database opened read-only can be checked
+// to have been migrated by a scraper.
+const SCHEMA_VERSION: u32 = 1;
+
+/// Checks that the database is at the current schema and returns its bounds.
+pub fn check_schema(conn: &Connection) -> Result<(u32, u32), DbError> {
+	let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+	if version != SCHEMA_VERSION {
+		return Err(DbError::OutdatedSchema(version, SCHEMA_VERSION));
+	}
+	Ok(get_bounds_with_conn(conn)?)
+}
+
+pub fn init_db(database_file: &str, block_num: u32) -> SqliteResult<()> {
+	let mut conn = Connection::open(Path::new(database_file))?;
+	init_db_with_conn(&mut conn, block_num)
+}
+
+fn init_db_with_conn(conn: &mut Connection, block_num: u32) -> SqliteResult<()> {
 	conn.pragma_update(None, "journal_mode", "WAL")?;
 	let tx = conn.transaction()?;
 
+	tx.execute(&format!("CREATE TABLE IF NOT EXISTS events {}", EVENTS_TABLE), [])?;
+	// Databases created before lifecycle events were indexed lack these columns.
+	for column in ["deployer", "beneficiary", "code_hash", "old_code_hash"] {
+		add_column_if_missing(&tx, "events", column, "BLOB")?;
+	}
+	// And these, before call inputs were.
+	for (column, column_type) in [("selector", "BLOB"), ("message", "TEXT"), ("args", "TEXT")] {
+		add_column_if_missing(&tx, "events", column, column_type)?;
+	}
+	// And these, before code events were stored without a contract.
+	for column in ["uploader", "remover"] {
+		add_column_if_missing(&tx, "events", column, "BLOB")?;
+	}
+	// Such databases require a contract, and hold the uploader or remover of a code in its place.
+	// The table is rebuilt before the indexes are created, as they go with the old one.
+	let contract_required: bool = tx.query_row(
+		"SELECT \"notnull\" FROM pragma_table_info('events') WHERE name = 'contract_account_id'",
+		[],
+		|row| row.get(0),
+	)?;
+	if contract_required {
+		tx.execute_batch(&format!(
+			"ALTER TABLE events RENAME TO events_old;
+			CREATE TABLE events {0};
+			INSERT INTO events ({1}) SELECT {1} FROM events_old;
+			DROP TABLE events_old;
+			UPDATE events SET uploader = contract_account_id, contract_account_id = NULL
+			WHERE event_type = 'code_stored';
+			UPDATE events SET remover = contract_account_id, contract_account_id = NULL
+			WHERE event_type = 'code_removed';",
+			EVENTS_TABLE, EVENTS_TABLE_COLUMNS
+		))?;
+	}
 	tx.execute(
-		"CREATE TABLE IF NOT EXISTS events (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            contract_account_id BLOB NOT NULL,
-            block_num INTEGER NOT NULL,
-            event_index INTEGER NOT NULL,
-            extrinsic_index INTEGER NOT NULL,
-			event_type TEXT NOT NULL,
-			caller BLOB,
-            data BLOB NOT NULL,
-			UNIQUE (block_num, event_index)
-        )",
+		"CREATE INDEX IF NOT EXISTS idx_contract_selector
+		ON events (contract_account_id, selector, block_num)",
+		[],
+	)?;
+	tx.execute(
+		"CREATE INDEX IF NOT EXISTS idx_contract_message
+		ON events (contract_account_id, message, block_num)",
 		[],
 	)?;
 
 	tx.execute("CREATE INDEX IF NOT EXISTS idx_block_num ON events (block_num)", [])?;
+	tx.execute("CREATE INDEX IF NOT EXISTS idx_caller ON events (caller, block_num)", [])?;
 	tx.execute(
 		"CREATE INDEX IF NOT EXISTS idx_contract_account_id ON events (contract_account_id)",
 		[],
@@ -216,6 +648,52 @@ pub fn init_db(block_num: u32) -> SqliteResult<()> {
         [],
     )?;
 
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS event_topics (
+			block_num INTEGER NOT NULL,
+			event_index INTEGER NOT NULL,
+			topic_index INTEGER NOT NULL,
+			topic BLOB NOT NULL,
+			PRIMARY KEY (block_num, event_index, topic_index)
+		)",
+		[],
+	)?;
+	tx.execute(
+		"CREATE INDEX IF NOT EXISTS idx_topic ON event_topics (topic, block_num, event_index)",
+		[],
+	)?;
+
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS blocks (
+			block_num INTEGER PRIMARY KEY,
+			hash BLOB NOT NULL,
+			parent_hash BLOB NOT NULL,
+			timestamp INTEGER,
+			event_count INTEGER NOT NULL
+		)",
+		[],
+	)?;
+	tx.execute("CREATE INDEX IF NOT EXISTS idx_timestamp ON blocks (timestamp)", [])?;
+	stats::init_stats(&tx)?;
+	tx.execute("CREATE INDEX IF NOT EXISTS idx_code_hash ON events (code_hash, block_num)", [])?;
+	registry::init_registry(&tx)?;
+
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS extrinsics (
+			block_num INTEGER NOT NULL,
+			extrinsic_index INTEGER NOT NULL,
+			hash BLOB NOT NULL,
+			signer BLOB,
+			pallet TEXT NOT NULL,
+			call TEXT NOT NULL,
+			success INTEGER NOT NULL,
+			fee TEXT,
+			PRIMARY KEY (block_num, extrinsic_index)
+		)",
+		[],
+	)?;
+	tx.execute("CREATE INDEX IF NOT EXISTS idx_extrinsic_hash ON extrinsics (hash)", [])?;
+
 	tx.execute(
 		"CREATE TABLE IF NOT EXISTS metadata (
             id INTEGER PRIMARY KEY,
@@ -231,20 +709,42 @@ pub fn init_db(block_num: u32) -> SqliteResult<()> {
          WHERE NOT EXISTS (SELECT 1 FROM metadata WHERE id = 1)",
 		params![block_num, block_num - 1],
 	)?;
+	tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
 
 	tx.commit()?;
+	stats::backfill_stats(conn)?;
 
 	Ok(())
 }
 
-pub fn insert_events_for_block(events: Vec<Event>, block_num: u32) -> Result<(), DbError> {
-	if !events.iter().all(|e| e.block_num == block_num) {
-		return Err(DbError::InconsistentBlockNumber);
+fn add_column_if_missing(
+	tx: &rusqlite::Transaction,
+	table: &str,
+	column: &str,
+	column_type: &str,
+) -> SqliteResult<()> {
+	let exists: bool = tx.query_row(
+		"SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+		params![table, column],
+		|row| row.get(0),
+	)?;
+	if !exists {
+		tx.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, column_type), [])?;
 	}
+	Ok(())
+}
+
+pub fn insert_events_for_block(
+	events: Vec<Event>,
+	extrinsics: Vec<Extrinsic>,
+	block: &BlockDetails,
+	conn: &mut Connection,
+) -> Result<(), DbError> {
+	let block_num = block.block_num;
+	check_block_num(&events, &extrinsics, block_num)?;
 
-	let mut conn = get_connection_with_backoff();
 	let (indexed_from, indexed_to) = {
-		let maybe_bounds = get_bounds_with_conn(&conn);
+		let maybe_bounds = get_bounds_with_conn(conn);
 		if let Err(e) = &maybe_bounds {
 			log::info!("Error getting bounds in insert: {:?}", e);
 		}
@@ -257,6 +757,77 @@ pub fn insert_events_for_block(events: Vec<Event>, block_num: u32) -> Result<(),
 
 	let tx = conn.transaction()?;
 
+	insert_block_rows(&tx, events, extrinsics, block)?;
+
+	if block_num < indexed_from {
+		tx.execute("UPDATE metadata SET indexed_from = ?1 WHERE id = 1", params![block_num])?;
+	}
+
+	if block_num > indexed_to {
+		tx.execute("UPDATE metadata SET indexed_to = ?1 WHERE id = 1", params![block_num])?;
+	}
+
+	tx.commit()?;
+
+	Ok(())
+}
+
+fn check_block_num(
+	events: &[Event],
+	extrinsics: &[Extrinsic],
+	block_num: u32,
+) -> Result<(), DbError> {
+	if !events.iter().all(|e| e.block_num == block_num) ||
+		!extrinsics.iter().all(|e| e.block_num == block_num)
+	{
+		return Err(DbError::InconsistentBlockNumber);
+	}
+	Ok(())
+}
+
+fn insert_block_rows(
+	tx: &rusqlite::Transaction,
+	events: Vec<Event>,
+	extrinsics: Vec<Extrinsic>,
+	block: &BlockDetails,
+) -> Result<(), DbError> {
+	tx.execute(
+		"INSERT INTO blocks (block_num, hash, parent_hash, timestamp, event_count)
+		VALUES (?1, ?2, ?3, ?4, ?5)",
+		params![
+			block.block_num,
+			&block.hash.0[..],
+			&block.parent_hash.0[..],
+			block.timestamp,
+			events.len()
+		],
+	)?;
+
+	for extrinsic in extrinsics {
+		tx.execute(
+			"INSERT INTO extrinsics (
+				block_num,
+				extrinsic_index,
+				hash,
+				signer,
+				pallet,
+				call,
+				success,
+				fee
+			) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+			params![
+				extrinsic.block_num,
+				extrinsic.extrinsic_index,
+				&extrinsic.hash.0[..],
+				extrinsic.signer.map(|signer| signer.0),
+				extrinsic.pallet,
+				extrinsic.call,
+				extrinsic.success,
+				extrinsic.fee.map(|fee| fee.to_string()),
+			],
+		)?;
+	}
+
 	for event_non_raw in events {
 		let event: DBEvent = event_non_raw.into();
 		tx.execute(
@@ -267,81 +838,189 @@ pub fn insert_events_for_block(events: Vec<Event>, block_num: u32) -> Result<(),
                 extrinsic_index, 
 				event_type,
 				caller,
-                data
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                data,
+				deployer,
+				beneficiary,
+				code_hash,
+				old_code_hash,
+				selector,
+				message,
+				args,
+				uploader,
+				remover
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
 			params![
-				&event.contract_account_id[..],
+				event.contract_account_id,
 				event.block_num,
 				event.event_index,
 				event.extrinsic_index,
 				event.event_type,
 				event.caller,
 				&event.data,
+				event.deployer,
+				event.beneficiary,
+				event.code_hash,
+				event.old_code_hash,
+				event.selector,
+				event.message,
+				event.args,
+				event.uploader,
+				event.remover,
 			],
 		)?;
+		for (topic_index, topic) in event.topics.iter().enumerate() {
+			tx.execute(
+				"INSERT INTO event_topics (block_num, event_index, topic_index, topic)
+				VALUES (?1, ?2, ?3, ?4)",
+				params![event.block_num, event.event_index, topic_index, &topic[..]],
+			)?;
+		}
 	}
+	stats::update_stats(tx, block.block_num, block.block_num, 1)?;
+	let touched = registry::touched(tx, block.block_num, block.block_num)?;
+	registry::refresh(tx, &touched)?;
+	Ok(())
+}
 
-	if block_num < indexed_from {
-		tx.execute("UPDATE metadata SET indexed_from = ?1 WHERE id = 1", params![block_num])?;
-	}
+/// Replaces everything stored about an already indexed block in one transaction.
+pub fn replace_block(
+	events: Vec<Event>,
+	extrinsics: Vec<Extrinsic>,
+	block: &BlockDetails,
+	conn: &mut Connection,
+) -> Result<(), DbError> {
+	let block_num = block.block_num;
+	check_block_num(&events, &extrinsics, block_num)?;
 
-	if block_num > indexed_to {
-		tx.execute("UPDATE metadata SET indexed_to = ?1 WHERE id = 1", params![block_num])?;
+	let (indexed_from, indexed_to) = get_bounds_with_conn(conn)?;
+	if !(block_num >= indexed_from && block_num <= indexed_to) {
+		return Err(DbError::BlocksNotInRange(indexed_from, indexed_to, block_num, block_num));
 	}
 
+	let tx = conn.transaction()?;
+	stats::update_stats(&tx, block_num, block_num, -1)?;
+	// Contracts and codes whose lifecycle events are dropped with the block.
+	let touched = registry::touched(&tx, block_num, block_num)?;
+	for table in ["events", "event_topics", "extrinsics", "blocks"] {
+		tx.execute(&format!("DELETE FROM {} WHERE block_num = ?1", table), params![block_num])?;
+	}
+	insert_block_rows(&tx, events, extrinsics, block)?;
+	registry::refresh(&tx, &touched)?;
 	tx.commit()?;
 
 	Ok(())
 }
 
-pub fn get_events_by_contract(
+/// Fails with `DbError::BlocksNotInRange` unless `block_start..=block_stop` is indexed.
+pub fn check_indexed_range(
 	block_start: u32,
 	block_stop: u32,
-	contract_address: &AccountId,
 	conn: &Connection,
-) -> Result<QueryResult<Vec<Event>>, DbError> {
-	let (indexed_from, indexed_to) = get_bounds_with_conn(&conn)?;
+) -> Result<(), DbError> {
+	let (indexed_from, indexed_to) = get_bounds_with_conn(conn)?;
 	if !(block_start >= indexed_from && block_stop <= indexed_to) {
 		return Err(DbError::BlocksNotInRange(indexed_from, indexed_to, block_start, block_stop));
 	}
+	Ok(())
+}
+
+pub fn get_events_by_contract(
+	block_start: u32,
+	block_stop: u32,
+	contract_address: &AccountId,
+	topic: Option<&H256>,
+	cursor: Option<&EventCursor>,
+	conn: &Connection,
+) -> Result<QueryResult<Vec<Event>>, DbError> {
+	check_indexed_range(block_start, block_stop, conn)?;
 
-	let mut stmt = conn.prepare(
-		"SELECT contract_account_id, block_num, event_index, extrinsic_index, event_type, caller, data 
+	let mut stmt = conn.prepare(&format!(
+		"SELECT {} 
          FROM events 
          WHERE block_num BETWEEN ?1 AND ?2 
          AND contract_account_id = ?3
+         AND (block_num > ?4 OR (block_num = ?4 AND event_index > ?5))
+         AND {}
          ORDER BY block_num ASC, event_index ASC",
-	)?;
+		EVENT_COLUMNS,
+		topic_filter(6)
+	))?;
 
 	let contract_account_id = contract_address.0;
+	let (cursor_block, cursor_event) = EventCursor::sql_params(cursor);
 
-	let mut rows = stmt.query(params![block_start, block_stop, &contract_account_id[..]])?;
+	let mut rows = stmt.query(params![
+		block_start,
+		block_stop,
+		&contract_account_id[..],
+		cursor_block,
+		cursor_event,
+		topic.map(|t| t.0.to_vec())
+	])?;
+	events_from_rows(&mut rows, conn)
+}
 
-	let mut events = Vec::new();
+// SQL condition that holds for events having the topic bound at `?{param}`, or for all events if
+// it is NULL.
+fn topic_filter(param: usize) -> String {
+	format!(
+		"(?{0} IS NULL OR (block_num, event_index) IN
+			(SELECT block_num, event_index FROM event_topics WHERE topic = ?{0}))",
+		param
+	)
+}
+
+fn events_from_rows(
+	rows: &mut rusqlite::Rows,
+	conn: &Connection,
+) -> Result<QueryResult<Vec<Event>>, DbError> {
+	let mut topics_stmt = conn.prepare(TOPICS_QUERY)?;
+	let mut events: Vec<Event> = Vec::new();
 	let mut total_size = 0;
 	while let Some(row) = rows.next()? {
 		if total_size > MAX_TOTAL_RESULT_SIZE {
-			return Ok(QueryResult { data: events, is_complete: false });
+			let next_cursor = events.last().map(|e| EventCursor::after(e).encode());
+			return Ok(QueryResult { data: events, is_complete: false, next_cursor });
 		}
-		let event = event_from_row(&row)?;
+		let mut event = event_from_row(row)?;
+		load_topics(&mut topics_stmt, &mut event)?;
 		total_size += event.size();
 		events.push(event);
 	}
+	Ok(QueryResult { data: events, is_complete: true, next_cursor: None })
+}
 
-	return Ok(QueryResult { data: events, is_complete: true });
+// `topics_stmt` is a prepared `TOPICS_QUERY`.
+fn load_topics(topics_stmt: &mut rusqlite::Statement, event: &mut Event) -> SqliteResult<()> {
+	if let EventType::Emitted(details) = &mut event.event_type {
+		details.topics = topics_stmt
+			.query_map(params![event.block_num, event.event_index], |row| {
+				row.get::<_, [u8; 32]>(0)
+			})?
+			.map(|topic| topic.map(H256))
+			.collect::<SqliteResult<_>>()?;
+	}
+	Ok(())
 }
 
-fn event_from_row(row: &rusqlite::Row) -> rusqlite::Result<Event> {
-	let contract_account_id: Vec<u8> = row.get(0)?;
-	let contract_account_id: [u8; 32] = contract_account_id.as_slice().try_into().unwrap();
+fn event_from_row(row: &rusqlite::Row) -> Result<Event, DbError> {
+	let contract_account_id: Option<[u8; 32]> = row.get(0)?;
 	let block_num: u32 = row.get(1)?;
 	let event_index: u32 = row.get(2)?;
 	let extrinsic_index: u32 = row.get(3)?;
 	let event_type: String = row.get(4)?;
 	let caller: Option<[u8; 32]> = row.get(5)?;
 	let data: Vec<u8> = row.get(6)?;
+	let deployer: Option<[u8; 32]> = row.get(7)?;
+	let beneficiary: Option<[u8; 32]> = row.get(8)?;
+	let code_hash: Option<[u8; 32]> = row.get(9)?;
+	let old_code_hash: Option<[u8; 32]> = row.get(10)?;
+	let message: Option<String> = row.get(11)?;
+	let args: Option<String> = row.get(12)?;
+	let uploader: Option<[u8; 32]> = row.get(13)?;
+	let remover: Option<[u8; 32]> = row.get(14)?;
 
-	Ok(DBEvent {
+	DBEvent {
 		contract_account_id,
 		block_num,
 		event_index,
@@ -349,39 +1028,327 @@ fn event_from_row(row: &rusqlite::Row) -> rusqlite::Result<Event> {
 		event_type,
 		caller,
 		data,
+		deployer,
+		beneficiary,
+		code_hash,
+		old_code_hash,
+		uploader,
+		remover,
+		// Only needed to query.
+		selector: None,
+		message,
+		args,
+		topics: Vec::new(),
 	}
-	.into())
+	.try_into()
 }
 
 pub fn get_events_by_range(
 	block_start: u32,
 	block_stop: u32,
+	topic: Option<&H256>,
+	cursor: Option<&EventCursor>,
 	conn: &Connection,
 ) -> Result<QueryResult<Vec<Event>>, DbError> {
-	let (indexed_from, indexed_to) = get_bounds_with_conn(&conn)?;
-	if !(block_start >= indexed_from && block_stop <= indexed_to) {
-		return Err(DbError::BlocksNotInRange(indexed_from, indexed_to, block_start, block_stop));
-	}
+	check_indexed_range(block_start, block_stop, conn)?;
 
-	let mut stmt = conn.prepare(
-		"SELECT contract_account_id, block_num, event_index, extrinsic_index, event_type, caller, data 
+	let mut stmt = conn.prepare(&format!(
+		"SELECT {} 
          FROM events 
          WHERE block_num BETWEEN ?1 AND ?2 
+         AND (block_num > ?3 OR (block_num = ?3 AND event_index > ?4))
+         AND {}
+         ORDER BY block_num ASC, event_index ASC",
+		EVENT_COLUMNS,
+		topic_filter(5)
+	))?;
+
+	let (cursor_block, cursor_event) = EventCursor::sql_params(cursor);
+	let mut rows = stmt.query(params![
+		block_start,
+		block_stop,
+		cursor_block,
+		cursor_event,
+		topic.map(|t| t.0.to_vec())
+	])?;
+	events_from_rows(&mut rows, conn)
+}
+
+/// `Called` events of `block_start..=block_stop` with a known input, optionally only to
+/// `contract_address`, with `selector` or with the ink! message named `message`.
+pub fn get_calls(
+	block_start: u32,
+	block_stop: u32,
+	contract_address: Option<&AccountId>,
+	selector: Option<&[u8; 4]>,
+	message: Option<&str>,
+	cursor: Option<&EventCursor>,
+	conn: &Connection,
+) -> Result<QueryResult<Vec<Event>>, DbError> {
+	check_indexed_range(block_start, block_stop, conn)?;
+
+	let mut stmt = conn.prepare(&calls_query(
+		contract_address.is_some(),
+		selector.is_some(),
+		message.is_some(),
+	))?;
+
+	let (cursor_block, cursor_event) = EventCursor::sql_params(cursor);
+	let mut rows = stmt.query(params![
+		block_start,
+		block_stop,
+		contract_address.map(|c| c.0.to_vec()),
+		selector.map(|s| s.to_vec()),
+		message,
+		cursor_block,
+		cursor_event
+	])?;
+	events_from_rows(&mut rows, conn)
+}
+
+// Spelled out per combination of filters, as with `for_each_event`, so that `idx_contract_selector`
+// or `idx_contract_message` is used when the contract is given.
+pub(crate) fn calls_query(by_contract: bool, by_selector: bool, by_message: bool) -> String {
+	let contract_filter = if by_contract { "contract_account_id = ?3" } else { "?3 IS NULL" };
+	let selector_filter = if by_selector { "selector = ?4" } else { "?4 IS NULL" };
+	let message_filter = if by_message { "message = ?5" } else { "?5 IS NULL" };
+	format!(
+		"SELECT {}
+         FROM events
+         WHERE block_num BETWEEN ?1 AND ?2
+         AND event_type = 'called' AND selector IS NOT NULL
+         AND {}
+         AND {}
+         AND {}
+         AND (block_num > ?6 OR (block_num = ?6 AND event_index > ?7))
+         ORDER BY block_num ASC, event_index ASC",
+		EVENT_COLUMNS, contract_filter, selector_filter, message_filter
+	)
+}
+
+/// Passes the events of `block_start..=block_stop` to `on_event` one by one, in chain order and
+/// without any result size limit, until `on_event` returns false. The filters are the ones of
+/// `get_events_by_contract` and `get_events_by_range`. The range is not checked against the indexed
+/// bounds, see `check_indexed_range`.
+pub fn for_each_event(
+	block_start: u32,
+	block_stop: u32,
+	contract_address: Option<&AccountId>,
+	topic: Option<&H256>,
+	conn: &Connection,
+	mut on_event: impl FnMut(Event) -> bool,
+) -> Result<(), DbError> {
+	// Spelled out per case, so that the index on the contract is used when there is one.
+	let contract_filter =
+		if contract_address.is_some() { "contract_account_id = ?3" } else { "?3 IS NULL" };
+	let mut stmt = conn.prepare(&format!(
+		"SELECT {}
+         FROM events
+         WHERE block_num BETWEEN ?1 AND ?2
+         AND {}
+         AND {}
          ORDER BY block_num ASC, event_index ASC",
+		EVENT_COLUMNS,
+		contract_filter,
+		topic_filter(4)
+	))?;
+	let mut topics_stmt = conn.prepare(TOPICS_QUERY)?;
+	let mut rows = stmt.query(params![
+		block_start,
+		block_stop,
+		contract_address.map(|c| c.0.to_vec()),
+		topic.map(|t| t.0.to_vec())
+	])?;
+	while let Some(row) = rows.next()? {
+		let mut event = event_from_row(row)?;
+		load_topics(&mut topics_stmt, &mut event)?;
+		if !on_event(event) {
+			break;
+		}
+	}
+	Ok(())
+}
+
+/// Timestamp of `block_num` in milliseconds since the Unix epoch, `None` if the block was indexed
+/// by a version that does not record blocks.
+pub fn get_block_timestamp(block_num: u32, conn: &Connection) -> SqliteResult<Option<u64>> {
+	conn.query_row(
+		"SELECT MAX(timestamp) FROM blocks WHERE block_num = ?1",
+		params![block_num],
+		|row| row.get(0),
+	)
+}
+
+/// Translates a time range (in milliseconds since the Unix epoch) to the range of blocks with
+/// timestamps in it. The range is empty (start > stop) if no block falls in it. Only blocks indexed
+/// by a version that stores them in the `blocks` table are taken into account.
+pub fn get_block_range_by_time(
+	time_start: u64,
+	time_stop: u64,
+	conn: &Connection,
+) -> Result<(u32, u32), DbError> {
+	let (min_time, max_time): (Option<u64>, Option<u64>) =
+		conn.query_row("SELECT MIN(timestamp), MAX(timestamp) FROM blocks", [], |row| {
+			Ok((row.get(0)?, row.get(1)?))
+		})?;
+	let (min_time, max_time) = match (min_time, max_time) {
+		(Some(min_time), Some(max_time)) => (min_time, max_time),
+		_ => return Err(DbError::NoTimestampsIndexed),
+	};
+	if !(time_start >= min_time && time_stop <= max_time) {
+		return Err(DbError::TimesNotInRange(time_start, time_stop, min_time, max_time));
+	}
+
+	let block_start: Option<u32> = conn.query_row(
+		"SELECT MIN(block_num) FROM blocks WHERE timestamp >= ?1",
+		params![time_start],
+		|row| row.get(0),
+	)?;
+	let block_stop: Option<u32> = conn.query_row(
+		"SELECT MAX(block_num) FROM blocks WHERE timestamp <= ?1",
+		params![time_stop],
+		|row| row.get(0),
 	)?;
+	// Both exist, as the times are within [min_time, max_time].
+	Ok((block_start.unwrap(), block_stop.unwrap()))
+}
+
+/// The first block with a timestamp at or after `time` (in milliseconds since the Unix epoch), or
+/// `None` if all are older.
+pub fn get_first_block_since(time: u64, conn: &Connection) -> SqliteResult<Option<u32>> {
+	conn.query_row(
+		"SELECT MIN(block_num) FROM blocks WHERE timestamp >= ?1",
+		params![time],
+		|row| row.get(0),
+	)
+}
 
-	let mut rows = stmt.query(params![block_start, block_stop])?;
+const EXTRINSIC_COLUMNS: &str =
+	"block_num, extrinsic_index, hash, signer, pallet, call, success, fee";
 
+fn extrinsic_from_row(row: &rusqlite::Row) -> rusqlite::Result<Extrinsic> {
+	let block_num: u32 = row.get(0)?;
+	let extrinsic_index: u32 = row.get(1)?;
+	let hash: [u8; 32] = row.get(2)?;
+	let signer: Option<[u8; 32]> = row.get(3)?;
+	let pallet: String = row.get(4)?;
+	let call: String = row.get(5)?;
+	let success: bool = row.get(6)?;
+	let fee: Option<String> = row.get(7)?;
+	Ok(Extrinsic {
+		block_num,
+		extrinsic_index,
+		hash: H256(hash),
+		signer: signer.map(AccountId::from),
+		pallet,
+		call,
+		success,
+		fee: fee
+			.map(|fee| fee.parse())
+			.transpose()
+			.map_err(|e| FromSqlConversionFailure(7, Type::Text, Box::new(e)))?,
+	})
+}
+
+fn extrinsic_events(
+	block_num: u32,
+	extrinsic_index: u32,
+	conn: &Connection,
+) -> Result<Vec<Event>, DbError> {
+	let mut stmt = conn.prepare(&format!(
+		"SELECT {}
+         FROM events
+         WHERE block_num = ?1 AND extrinsic_index = ?2
+         ORDER BY event_index ASC",
+		EVENT_COLUMNS
+	))?;
+	let mut topics_stmt = conn.prepare(TOPICS_QUERY)?;
+	let mut rows = stmt.query(params![block_num, extrinsic_index])?;
 	let mut events = Vec::new();
-	let mut total_size = 0;
 	while let Some(row) = rows.next()? {
-		if total_size > MAX_TOTAL_RESULT_SIZE {
-			return Ok(QueryResult { data: events, is_complete: false });
-		}
-		let event = event_from_row(&row)?;
-		total_size += event.size();
+		let mut event = event_from_row(row)?;
+		load_topics(&mut topics_stmt, &mut event)?;
 		events.push(event);
 	}
+	Ok(events)
+}
+
+fn with_events(extrinsic: Extrinsic, conn: &Connection) -> Result<ExtrinsicWithEvents, DbError> {
+	let events = extrinsic_events(extrinsic.block_num, extrinsic.extrinsic_index, conn)?;
+	Ok(ExtrinsicWithEvents { extrinsic, events })
+}
+
+/// Returns `None` if the extrinsic is not indexed, which is also the case for all extrinsics in
+/// blocks indexed by a version that did not store them.
+pub fn get_extrinsic(
+	block_num: u32,
+	extrinsic_index: u32,
+	conn: &Connection,
+) -> Result<Option<ExtrinsicWithEvents>, DbError> {
+	let mut stmt = conn.prepare(&format!(
+		"SELECT {} FROM extrinsics WHERE block_num = ?1 AND extrinsic_index = ?2",
+		EXTRINSIC_COLUMNS
+	))?;
+	let mut rows = stmt.query(params![block_num, extrinsic_index])?;
+	match rows.next()? {
+		Some(row) => Ok(Some(with_events(extrinsic_from_row(row)?, conn)?)),
+		None => Ok(None),
+	}
+}
 
-	Ok(QueryResult { data: events, is_complete: true })
+/// Extrinsic hashes are not guaranteed to be unique, hence possibly many results.
+pub fn get_extrinsics_by_hash(
+	hash: &H256,
+	conn: &Connection,
+) -> Result<Vec<ExtrinsicWithEvents>, DbError> {
+	let mut stmt = conn.prepare(&format!(
+		"SELECT {} FROM extrinsics WHERE hash = ?1 ORDER BY block_num ASC",
+		EXTRINSIC_COLUMNS
+	))?;
+	let extrinsics = stmt
+		.query_map(params![&hash.0[..]], extrinsic_from_row)?
+		.collect::<SqliteResult<Vec<_>>>()?;
+	extrinsics.into_iter().map(|extrinsic| with_events(extrinsic, conn)).collect()
+}
+
+/// What is stored about a block, to compare it against the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredBlock {
+	pub block_num: u32,
+	/// `None` if the block has no row in `blocks`, e.g. it was indexed by a version that did not
+	/// record blocks.
+	pub hash: Option<H256>,
+	/// As recorded in `blocks` when the block was inserted.
+	pub recorded_event_count: Option<u32>,
+	/// Rows actually present in `events`.
+	pub event_count: u32,
+}
+
+pub fn get_stored_blocks(
+	block_start: u32,
+	block_stop: u32,
+	conn: &Connection,
+) -> Result<Vec<StoredBlock>, DbError> {
+	let (indexed_from, indexed_to) = get_bounds_with_conn(conn)?;
+	if !(block_start >= indexed_from && block_stop <= indexed_to) {
+		return Err(DbError::BlocksNotInRange(indexed_from, indexed_to, block_start, block_stop));
+	}
+
+	let mut blocks_stmt =
+		conn.prepare("SELECT hash, event_count FROM blocks WHERE block_num = ?1")?;
+	let mut events_stmt = conn.prepare("SELECT COUNT(*) FROM events WHERE block_num = ?1")?;
+	let mut res = Vec::new();
+	for block_num in block_start..=block_stop {
+		let mut rows = blocks_stmt.query(params![block_num])?;
+		let (hash, recorded_event_count) = match rows.next()? {
+			Some(row) => {
+				let hash: [u8; 32] = row.get(0)?;
+				(Some(H256(hash)), Some(row.get(1)?))
+			},
+			None => (None, None),
+		};
+		let event_count = events_stmt.query_row(params![block_num], |row| row.get(0))?;
+		res.push(StoredBlock { block_num, hash, recorded_event_count, event_count });
+	}
+	Ok(res)
 }
@@ -0,0 +1,383 @@
+use std::collections::BTreeSet;
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult, Transaction};
+use serde::{Deserialize, Serialize};
+use subxt::utils::H256;
+use utoipa::ToSchema;
+
+use super::DbError;
+use crate::AccountId;
+
+const CONTRACT_EVENT_TYPES: &str = "('instantiated', 'terminated', 'contract_code_updated')";
+const CODE_EVENT_TYPES: &str = "('code_stored', 'code_removed')";
+
+/// Creates the registry tables. If they are new, they are filled from the events already stored.
+pub(super) fn init_registry(tx: &Transaction) -> SqliteResult<()> {
+	let exists: bool = tx.query_row(
+		"SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'contracts')",
+		[],
+		|row| row.get(0),
+	)?;
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS contracts (
+			contract_account_id BLOB PRIMARY KEY,
+			deployer BLOB,
+			instantiated_block INTEGER,
+			code_hash BLOB,
+			terminated_block INTEGER,
+			beneficiary BLOB
+		)",
+		[],
+	)?;
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS contract_code_hashes (
+			contract_account_id BLOB NOT NULL,
+			code_hash BLOB NOT NULL,
+			since_block INTEGER,
+			until_block INTEGER
+		)",
+		[],
+	)?;
+	tx.execute(
+		"CREATE INDEX IF NOT EXISTS idx_code_hashes_contract
+		ON contract_code_hashes (contract_account_id)",
+		[],
+	)?;
+	tx.execute(
+		"CREATE INDEX IF NOT EXISTS idx_code_hashes_code ON contract_code_hashes (code_hash)",
+		[],
+	)?;
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS codes (
+			code_hash BLOB PRIMARY KEY,
+			uploader BLOB,
+			uploaded_block INTEGER,
+			removed_block INTEGER
+		)",
+		[],
+	)?;
+	if !exists {
+		let touched = touched(tx, 0, u32::MAX)?;
+		refresh(tx, &touched)?;
+	}
+	Ok(())
+}
+
+/// The contracts and codes with lifecycle events in `block_from..=block_to`.
+pub(super) struct Touched {
+	contracts: BTreeSet<[u8; 32]>,
+	codes: BTreeSet<[u8; 32]>,
+}
+
+pub(super) fn touched(tx: &Transaction, block_from: u32, block_to: u32) -> SqliteResult<Touched> {
+	let keys = |query: String| -> SqliteResult<BTreeSet<[u8; 32]>> {
+		let mut stmt = tx.prepare(&query)?;
+		let rows = stmt.query_map(params![block_from, block_to], |row| row.get(0))?;
+		rows.collect()
+	};
+	Ok(Touched {
+		contracts: keys(format!(
+			"SELECT DISTINCT contract_account_id FROM events
+			WHERE block_num BETWEEN ?1 AND ?2 AND event_type IN {}",
+			CONTRACT_EVENT_TYPES
+		))?,
+		codes: keys(format!(
+			"SELECT DISTINCT code_hash FROM events
+			WHERE block_num BETWEEN ?1 AND ?2 AND event_type IN {}",
+			CODE_EVENT_TYPES
+		))?,
+	})
+}
+
+/// Recomputes the registry rows of `touched` from the stored events.
+pub(super) fn refresh(tx: &Transaction, touched: &Touched) -> SqliteResult<()> {
+	for contract in &touched.contracts {
+		refresh_contract(tx, contract)?;
+	}
+	for code_hash in &touched.codes {
+		refresh_code(tx, code_hash)?;
+	}
+	Ok(())
+}
+
+#[derive(Default)]
+struct CodePeriod {
+	code_hash: Option<[u8; 32]>,
+	since_block: Option<u32>,
+	until_block: Option<u32>,
+}
+
+fn refresh_contract(tx: &Transaction, contract: &[u8; 32]) -> SqliteResult<()> {
+	tx.execute("DELETE FROM contracts WHERE contract_account_id = ?1", params![&contract[..]])?;
+	tx.execute(
+		"DELETE FROM contract_code_hashes WHERE contract_account_id = ?1",
+		params![&contract[..]],
+	)?;
+
+	let mut stmt = tx.prepare(&format!(
+		"SELECT event_type, block_num, deployer, beneficiary, code_hash, old_code_hash
+		FROM events
+		WHERE contract_account_id = ?1 AND event_type IN {}
+		ORDER BY block_num ASC, event_index ASC",
+		CONTRACT_EVENT_TYPES
+	))?;
+	let mut rows = stmt.query(params![&contract[..]])?;
+	let mut found = false;
+	let (mut deployer, mut instantiated_block, mut terminated_block, mut beneficiary) =
+		(None::<[u8; 32]>, None::<u32>, None::<u32>, None::<[u8; 32]>);
+	let mut periods: Vec<CodePeriod> = Vec::new();
+	while let Some(row) = rows.next()? {
+		found = true;
+		let event_type: String = row.get(0)?;
+		let block_num: u32 = row.get(1)?;
+		let open = periods.last_mut().filter(|period| period.until_block.is_none());
+		match event_type.as_str() {
+			"instantiated" => {
+				if let Some(period) = open {
+					period.until_block = Some(block_num);
+				}
+				periods.push(CodePeriod {
+					code_hash: row.get(4)?,
+					since_block: Some(block_num),
+					until_block: None,
+				});
+				deployer = row.get(2)?;
+				instantiated_block = Some(block_num);
+				(terminated_block, beneficiary) = (None, None);
+			},
+			"terminated" => {
+				if let Some(period) = open {
+					period.until_block = Some(block_num);
+				}
+				terminated_block = Some(block_num);
+				beneficiary = row.get(3)?;
+			},
+			_ => {
+				// `contract_code_updated`, which also tells the code the contract ran until then,
+				// more precisely than the code read at instantiation, from the end of its block.
+				let old_code_hash: [u8; 32] = row.get(5)?;
+				match open {
+					Some(period) => {
+						period.code_hash = Some(old_code_hash);
+						period.until_block = Some(block_num);
+					},
+					None => periods.push(CodePeriod {
+						code_hash: Some(old_code_hash),
+						until_block: Some(block_num),
+						..Default::default()
+					}),
+				}
+				periods.push(CodePeriod {
+					code_hash: row.get(4)?,
+					since_block: Some(block_num),
+					until_block: None,
+				});
+			},
+		}
+	}
+	if !found {
+		return Ok(());
+	}
+
+	let code_hash = periods
+		.last()
+		.filter(|period| period.until_block.is_none())
+		.and_then(|period| period.code_hash);
+	tx.execute(
+		"INSERT INTO contracts (
+			contract_account_id,
+			deployer,
+			instantiated_block,
+			code_hash,
+			terminated_block,
+			beneficiary
+		) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+		params![
+			&contract[..],
+			deployer,
+			instantiated_block,
+			code_hash,
+			terminated_block,
+			beneficiary
+		],
+	)?;
+	for period in periods {
+		// Periods of an unknown code, instantiated without a later code update, are left out.
+		if let Some(code_hash) = period.code_hash {
+			tx.execute(
+				"INSERT INTO contract_code_hashes (
+					contract_account_id,
+					code_hash,
+					since_block,
+					until_block
+				) VALUES (?1, ?2, ?3, ?4)",
+				params![&contract[..], code_hash, period.since_block, period.until_block],
+			)?;
+		}
+	}
+	Ok(())
+}
+
+fn refresh_code(tx: &Transaction, code_hash: &[u8; 32]) -> SqliteResult<()> {
+	tx.execute("DELETE FROM codes WHERE code_hash = ?1", params![&code_hash[..]])?;
+
+	let mut stmt = tx.prepare(&format!(
+		"SELECT event_type, block_num, uploader
+		FROM events
+		WHERE code_hash = ?1 AND event_type IN {}
+		ORDER BY block_num ASC, event_index ASC",
+		CODE_EVENT_TYPES
+	))?;
+	let mut rows = stmt.query(params![&code_hash[..]])?;
+	let mut found = false;
+	let (mut uploader, mut uploaded_block, mut removed_block) =
+		(None::<[u8; 32]>, None::<u32>, None::<u32>);
+	while let Some(row) = rows.next()? {
+		found = true;
+		let block_num: u32 = row.get(1)?;
+		if row.get::<_, String>(0)? == "code_stored" {
+			// Removed code can be uploaded again.
+			uploader = row.get(2)?;
+			uploaded_block = Some(block_num);
+			removed_block = None;
+		} else {
+			removed_block = Some(block_num);
+		}
+	}
+	if found {
+		tx.execute(
+			"INSERT INTO codes (code_hash, uploader, uploaded_block, removed_block)
+			VALUES (?1, ?2, ?3, ?4)",
+			params![&code_hash[..], uploader, uploaded_block, removed_block],
+		)?;
+	}
+	Ok(())
+}
+
+/// A period in which a contract ran a code. Open ends are before the indexed range (`since_block`)
+/// or the code the contract still runs (`until_block`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct CodeHashPeriod {
+	#[schema(value_type = String)]
+	pub code_hash: H256,
+	pub since_block: Option<u32>,
+	pub until_block: Option<u32>,
+}
+
+/// The lifecycle of a contract, from the indexed events. Fields of events outside the indexed
+/// range are `None`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct ContractRecord {
+	pub contract_account_id: AccountId,
+	pub deployer: Option<AccountId>,
+	pub instantiated_block: Option<u32>,
+	/// The code the contract currently runs, `None` once it is terminated or if it is unknown.
+	#[schema(value_type = Option<String>)]
+	pub code_hash: Option<H256>,
+	pub terminated_block: Option<u32>,
+	pub beneficiary: Option<AccountId>,
+	/// The codes the contract ran, oldest first.
+	pub code_history: Vec<CodeHashPeriod>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct CodeRecord {
+	#[schema(value_type = String)]
+	pub code_hash: H256,
+	/// `None` if the code was uploaded before the indexed range.
+	pub uploader: Option<AccountId>,
+	pub uploaded_block: Option<u32>,
+	pub removed_block: Option<u32>,
+	/// The contracts currently running the code.
+	pub contracts: Vec<AccountId>,
+	/// The contracts that ran the code before, and run another code or are terminated now.
+	pub previous_contracts: Vec<AccountId>,
+}
+
+/// The registry record of `contract`, `None` if no lifecycle event of it is indexed.
+pub fn get_contract(
+	contract: &AccountId,
+	conn: &Connection,
+) -> Result<Option<ContractRecord>, DbError> {
+	let record = conn
+		.query_row(
+			"SELECT deployer, instantiated_block, code_hash, terminated_block, beneficiary
+			FROM contracts WHERE contract_account_id = ?1",
+			params![&contract.0[..]],
+			|row| {
+				Ok(ContractRecord {
+					contract_account_id: contract.clone(),
+					deployer: row.get::<_, Option<[u8; 32]>>(0)?.map(AccountId::from),
+					instantiated_block: row.get(1)?,
+					code_hash: row.get::<_, Option<[u8; 32]>>(2)?.map(H256),
+					terminated_block: row.get(3)?,
+					beneficiary: row.get::<_, Option<[u8; 32]>>(4)?.map(AccountId::from),
+					code_history: Vec::new(),
+				})
+			},
+		)
+		.optional()?;
+	let Some(mut record) = record else {
+		return Ok(None);
+	};
+	let mut stmt = conn.prepare(
+		"SELECT code_hash, since_block, until_block FROM contract_code_hashes
+		WHERE contract_account_id = ?1
+		ORDER BY rowid ASC",
+	)?;
+	let mut rows = stmt.query(params![&contract.0[..]])?;
+	while let Some(row) = rows.next()? {
+		record.code_history.push(CodeHashPeriod {
+			code_hash: H256(row.get(0)?),
+			since_block: row.get(1)?,
+			until_block: row.get(2)?,
+		});
+	}
+	Ok(Some(record))
+}
+
+/// The registry record of `code_hash`, `None` if neither its upload nor a contract running it is
+/// indexed.
+pub fn get_code(code_hash: &H256, conn: &Connection) -> Result<Option<CodeRecord>, DbError> {
+	let upload: Option<(Option<AccountId>, Option<u32>, Option<u32>)> = conn
+		.query_row(
+			"SELECT uploader, uploaded_block, removed_block FROM codes WHERE code_hash = ?1",
+			params![&code_hash.0[..]],
+			|row| {
+				Ok((
+					row.get::<_, Option<[u8; 32]>>(0)?.map(AccountId::from),
+					row.get(1)?,
+					row.get(2)?,
+				))
+			},
+		)
+		.optional()?;
+	let mut stmt = conn.prepare(
+		"SELECT contract_account_id, MAX(until_block IS NULL) FROM contract_code_hashes
+		WHERE code_hash = ?1
+		GROUP BY contract_account_id
+		ORDER BY contract_account_id ASC",
+	)?;
+	let mut rows = stmt.query(params![&code_hash.0[..]])?;
+	let (mut contracts, mut previous_contracts) = (Vec::new(), Vec::new());
+	while let Some(row) = rows.next()? {
+		let contract = AccountId::from(row.get::<_, [u8; 32]>(0)?);
+		if row.get::<_, bool>(1)? {
+			contracts.push(contract);
+		} else {
+			previous_contracts.push(contract);
+		}
+	}
+	if upload.is_none() && contracts.is_empty() && previous_contracts.is_empty() {
+		return Ok(None);
+	}
+	let (uploader, uploaded_block, removed_block) = upload.unwrap_or_default();
+	Ok(Some(CodeRecord {
+		code_hash: *code_hash,
+		uploader,
+		uploaded_block,
+		removed_block,
+		contracts,
+		previous_contracts,
+	}))
+}
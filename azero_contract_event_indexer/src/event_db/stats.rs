@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult, Transaction};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{check_indexed_range, DbError};
+use crate::AccountId;
+
+const DAY_MILLIS: u64 = 86_400_000;
+
+// Blocks aggregated per transaction when filling new tables from the events already stored.
+const BACKFILL_BATCH_BLOCKS: i64 = 100_000;
+
+/// Creates the aggregate tables. If they are new, the events already stored are left for
+/// `backfill_stats` to add.
+pub(super) fn init_stats(tx: &Transaction) -> SqliteResult<()> {
+	let exists: bool = tx.query_row(
+		"SELECT EXISTS (SELECT 1 FROM sqlite_master
+			WHERE type = 'table' AND name = 'contract_daily_stats')",
+		[],
+		|row| row.get(0),
+	)?;
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS contract_daily_stats (
+			contract_account_id BLOB NOT NULL,
+			day INTEGER NOT NULL,
+			calls INTEGER NOT NULL,
+			emitted INTEGER NOT NULL,
+			PRIMARY KEY (contract_account_id, day)
+		)",
+		[],
+	)?;
+	tx.execute("CREATE INDEX IF NOT EXISTS idx_stats_day ON contract_daily_stats (day)", [])?;
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS contract_daily_callers (
+			contract_account_id BLOB NOT NULL,
+			day INTEGER NOT NULL,
+			caller BLOB NOT NULL,
+			calls INTEGER NOT NULL,
+			PRIMARY KEY (contract_account_id, day, caller)
+		)",
+		[],
+	)?;
+	tx.execute("CREATE INDEX IF NOT EXISTS idx_callers_day ON contract_daily_callers (day)", [])?;
+	// The blocks left to add, `next_block..=last_block`, while the tables are being filled.
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS stats_backfill (
+			id INTEGER PRIMARY KEY,
+			next_block INTEGER NOT NULL,
+			last_block INTEGER NOT NULL
+		)",
+		[],
+	)?;
+	if !exists {
+		tx.execute(
+			"INSERT INTO stats_backfill (id, next_block, last_block)
+			SELECT 1, COALESCE(MIN(block_num), 0), COALESCE(MAX(block_num), -1) FROM events",
+			[],
+		)?;
+	}
+	Ok(())
+}
+
+/// Adds the blocks left by `init_stats` to the aggregates, a batch per transaction so that the
+/// database is not locked for the whole backfill. An interrupted backfill resumes where it stopped.
+pub(super) fn backfill_stats(conn: &mut Connection) -> SqliteResult<()> {
+	loop {
+		let tx = conn.transaction()?;
+		let Some((next_block, last_block)) = tx
+			.query_row(
+				"SELECT next_block, last_block FROM stats_backfill WHERE id = 1",
+				[],
+				|row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+			)
+			.optional()?
+		else {
+			return Ok(());
+		};
+		if next_block > last_block {
+			tx.execute("DELETE FROM stats_backfill", [])?;
+			return tx.commit();
+		}
+		let batch_end = last_block.min(next_block + BACKFILL_BATCH_BLOCKS - 1);
+		log::info!("Adding blocks {}-{} of {} to the stats", next_block, batch_end, last_block);
+		update_stats(&tx, next_block as u32, batch_end as u32, 1)?;
+		tx.execute("UPDATE stats_backfill SET next_block = ?1", params![batch_end + 1])?;
+		tx.commit()?;
+	}
+}
+
+/// Adds (`sign` 1) or removes (`sign` -1) the stored events of `block_from..=block_to` to the
+/// aggregates. Days are UTC days of the block timestamps, events of blocks without a timestamp
+/// are not counted.
+pub(super) fn update_stats(
+	tx: &Transaction,
+	block_from: u32,
+	block_to: u32,
+	sign: i64,
+) -> SqliteResult<()> {
+	tx.execute(
+		"INSERT INTO contract_daily_stats (contract_account_id, day, calls, emitted)
+		SELECT e.contract_account_id, b.timestamp / ?3,
+			?4 * SUM(e.event_type = 'called'), ?4 * SUM(e.event_type = 'emitted')
+		FROM events e JOIN blocks b ON b.block_num = e.block_num
+		WHERE e.block_num BETWEEN ?1 AND ?2 AND e.event_type IN ('called', 'emitted')
+			AND b.timestamp IS NOT NULL
+		GROUP BY e.contract_account_id, b.timestamp / ?3
+		ON CONFLICT (contract_account_id, day) DO UPDATE
+		SET calls = calls + excluded.calls, emitted = emitted + excluded.emitted",
+		params![block_from, block_to, DAY_MILLIS, sign],
+	)?;
+	tx.execute(
+		"INSERT INTO contract_daily_callers (contract_account_id, day, caller, calls)
+		SELECT e.contract_account_id, b.timestamp / ?3, e.caller, ?4 * COUNT(*)
+		FROM events e JOIN blocks b ON b.block_num = e.block_num
+		WHERE e.block_num BETWEEN ?1 AND ?2 AND e.event_type = 'called' AND e.caller IS NOT NULL
+			AND b.timestamp IS NOT NULL
+		GROUP BY e.contract_account_id, b.timestamp / ?3, e.caller
+		ON CONFLICT (contract_account_id, day, caller) DO UPDATE
+		SET calls = calls + excluded.calls",
+		params![block_from, block_to, DAY_MILLIS, sign],
+	)?;
+	if sign < 0 {
+		let days = "SELECT timestamp / ?3 FROM blocks WHERE block_num BETWEEN ?1 AND ?2";
+		tx.execute(
+			&format!(
+				"DELETE FROM contract_daily_stats
+				WHERE day IN ({}) AND calls = 0 AND emitted = 0",
+				days
+			),
+			params![block_from, block_to, DAY_MILLIS],
+		)?;
+		tx.execute(
+			&format!("DELETE FROM contract_daily_callers WHERE day IN ({}) AND calls = 0", days),
+			params![block_from, block_to, DAY_MILLIS],
+		)?;
+	}
+	Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsPeriod {
+	/// UTC days.
+	#[default]
+	Day,
+	/// Weeks starting on Monday.
+	Week,
+}
+
+impl StatsPeriod {
+	// Periods are `(day + offset) / length`, counting days since the Unix epoch, a Thursday.
+	fn offset_and_length(&self) -> (i64, i64) {
+		match self {
+			StatsPeriod::Day => (0, 1),
+			StatsPeriod::Week => (3, 7),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ContractActivity {
+	/// Start of the period, in milliseconds since the Unix epoch.
+	pub period_start: u64,
+	/// `Called` events of the contract, including calls made by other contracts.
+	pub calls: u64,
+	/// `ContractEmitted` events of the contract.
+	pub emitted: u64,
+	/// Distinct callers of the contract, root calls aside.
+	pub unique_callers: u64,
+}
+
+/// Activity of `contract` per period, for the periods overlapping `time_start..=time_stop`. Periods
+/// without any activity are left out.
+pub fn get_contract_activity(
+	contract: &AccountId,
+	time_start: u64,
+	time_stop: u64,
+	period: StatsPeriod,
+	conn: &Connection,
+) -> Result<Vec<ContractActivity>, DbError> {
+	let (offset, length) = period.offset_and_length();
+	let first_period = ((time_start / DAY_MILLIS) as i64 + offset) / length;
+	let last_period = ((time_stop / DAY_MILLIS) as i64 + offset) / length;
+	let day_start = first_period * length - offset;
+	let day_stop = (last_period + 1) * length - offset - 1;
+
+	let mut activity: BTreeMap<i64, ContractActivity> = BTreeMap::new();
+	let mut stmt = conn.prepare(
+		"SELECT (day + ?4) / ?5 AS period, SUM(calls), SUM(emitted)
+		FROM contract_daily_stats
+		WHERE contract_account_id = ?1 AND day BETWEEN ?2 AND ?3
+		GROUP BY period",
+	)?;
+	let mut rows = stmt.query(params![&contract.0[..], day_start, day_stop, offset, length])?;
+	while let Some(row) = rows.next()? {
+		let period_index: i64 = row.get(0)?;
+		activity.insert(
+			period_index,
+			ContractActivity {
+				period_start: (period_index * length - offset) as u64 * DAY_MILLIS,
+				calls: row.get(1)?,
+				emitted: row.get(2)?,
+				unique_callers: 0,
+			},
+		);
+	}
+	let mut stmt = conn.prepare(
+		"SELECT (day + ?4) / ?5 AS period, COUNT(DISTINCT caller)
+		FROM contract_daily_callers
+		WHERE contract_account_id = ?1 AND day BETWEEN ?2 AND ?3
+		GROUP BY period",
+	)?;
+	let mut rows = stmt.query(params![&contract.0[..], day_start, day_stop, offset, length])?;
+	while let Some(row) = rows.next()? {
+		let period_index: i64 = row.get(0)?;
+		if let Some(activity) = activity.get_mut(&period_index) {
+			activity.unique_callers = row.get(1)?;
+		}
+	}
+	Ok(activity.into_values().filter(|a| a.calls > 0 || a.emitted > 0).collect())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsMetric {
+	#[default]
+	Calls,
+	Emitted,
+	UniqueCallers,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ContractRank {
+	pub contract_account_id: AccountId,
+	pub value: u64,
+}
+
+fn block_day(block_num: u32, conn: &Connection) -> SqliteResult<Option<u64>> {
+	conn.query_row(
+		"SELECT timestamp / ?2 FROM blocks WHERE block_num = ?1",
+		params![block_num, DAY_MILLIS],
+		|row| row.get(0),
+	)
+	.optional()
+	.map(Option::flatten)
+}
+
+// Splits `block_start..=block_stop` into the days it fully covers, which the aggregates answer,
+// and the blocks before and after them, which are read from the events. If the aggregates of those
+// days would not match the window, e.g. as blocks of the window have no timestamp, the whole window
+// is read from the events.
+fn split_window(
+	block_start: u32,
+	block_stop: u32,
+	conn: &Connection,
+) -> Result<((i64, i64), (u32, u32), (u32, u32)), DbError> {
+	let first_day = block_day(block_start, conn)?.ok_or(DbError::NoBlockTimestamp(block_start))?;
+	let last_day = block_day(block_stop, conn)?.ok_or(DbError::NoBlockTimestamp(block_stop))?;
+	// A day is fully covered unless blocks of it are stored outside the window.
+	let mut first_full = first_day as i64;
+	if block_start > 0 && block_day(block_start - 1, conn)? == Some(first_day) {
+		first_full += 1;
+	}
+	let mut last_full = last_day as i64;
+	if block_day(block_stop.saturating_add(1), conn)? == Some(last_day) {
+		last_full -= 1;
+	}
+	// Empty ranges are `(1, 0)`.
+	let events_only = ((1, 0), (block_start, block_stop), (1, 0));
+	if first_full > last_full {
+		return Ok(events_only);
+	}
+	let (first_full_block, last_full_block, timestamped_blocks): (Option<u32>, Option<u32>, u32) =
+		conn.query_row(
+			"SELECT MIN(block_num), MAX(block_num), COUNT(*) FROM blocks
+			WHERE timestamp >= ?1 AND timestamp < ?2",
+			params![first_full as u64 * DAY_MILLIS, (last_full + 1) as u64 * DAY_MILLIS],
+			|row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+		)?;
+	let (Some(first_full_block), Some(last_full_block)) = (first_full_block, last_full_block)
+	else {
+		return Ok(events_only);
+	};
+	// The aggregates count only blocks with a timestamp, and count blocks of the days that are
+	// outside the window.
+	if first_full_block < block_start ||
+		last_full_block > block_stop ||
+		timestamped_blocks != last_full_block - first_full_block + 1
+	{
+		return Ok(events_only);
+	}
+	let left =
+		if first_full_block > block_start { (block_start, first_full_block - 1) } else { (1, 0) };
+	let right =
+		if last_full_block < block_stop { (last_full_block + 1, block_stop) } else { (1, 0) };
+	Ok(((first_full, last_full), left, right))
+}
+
+/// The `limit` contracts with the highest `metric` in `block_start..=block_stop`. Whole days in the
+/// window are read from the aggregates, only the blocks of the days at its edges from the events.
+pub fn get_top_contracts(
+	block_start: u32,
+	block_stop: u32,
+	metric: StatsMetric,
+	limit: u32,
+	conn: &Connection,
+) -> Result<Vec<ContractRank>, DbError> {
+	check_indexed_range(block_start, block_stop, conn)?;
+	let ((day_start, day_stop), (left_start, left_stop), (right_start, right_stop)) =
+		split_window(block_start, block_stop, conn)?;
+	let edges = "(e.block_num BETWEEN ?3 AND ?4 OR e.block_num BETWEEN ?5 AND ?6)";
+	let query = match metric {
+		StatsMetric::Calls | StatsMetric::Emitted => {
+			let (column, event_type) = match metric {
+				StatsMetric::Calls => ("calls", "called"),
+				_ => ("emitted", "emitted"),
+			};
+			format!(
+				"SELECT contract_account_id, SUM(n) AS value FROM (
+					SELECT contract_account_id, {column} AS n FROM contract_daily_stats
+					WHERE day BETWEEN ?1 AND ?2
+					UNION ALL
+					SELECT e.contract_account_id, COUNT(*) AS n FROM events e
+					WHERE e.event_type = '{event_type}' AND {edges}
+					GROUP BY e.contract_account_id
+				)
+				GROUP BY contract_account_id
+				HAVING value > 0
+				ORDER BY value DESC, contract_account_id ASC
+				LIMIT ?7"
+			)
+		},
+		StatsMetric::UniqueCallers => format!(
+			"SELECT contract_account_id, COUNT(DISTINCT caller) AS value FROM (
+				SELECT contract_account_id, caller FROM contract_daily_callers
+				WHERE day BETWEEN ?1 AND ?2
+				UNION ALL
+				SELECT e.contract_account_id, e.caller FROM events e
+				WHERE e.event_type = 'called' AND e.caller IS NOT NULL AND {edges}
+			)
+			GROUP BY contract_account_id
+			ORDER BY value DESC, contract_account_id ASC
+			LIMIT ?7"
+		),
+	};
+	let mut stmt = conn.prepare(&query)?;
+	let mut rows = stmt.query(params![
+		day_start,
+		day_stop,
+		left_start,
+		left_stop,
+		right_start,
+		right_stop,
+		limit
+	])?;
+	let mut ranks = Vec::new();
+	while let Some(row) = rows.next()? {
+		let contract: [u8; 32] = row.get(0)?;
+		ranks.push(ContractRank {
+			contract_account_id: AccountId::from(contract),
+			value: row.get(1)?,
+		});
+	}
+	Ok(ranks)
+}
@@ -0,0 +1,951 @@
+use rusqlite::{params, Connection};
+
+use super::{
+	activity::{get_account_activity, AccountActivity},
+	calls_query, for_each_event, get_block_range_by_time, get_block_timestamp, get_calls,
+	get_events_by_range, get_extrinsic, get_extrinsics_by_hash, get_first_block_since,
+	get_stored_blocks, init_db_with_conn, insert_events_for_block,
+	registry::{get_code, get_contract, CodeHashPeriod, CodeRecord, ContractRecord},
+	replace_block,
+	stats::{
+		get_contract_activity, get_top_contracts, ContractActivity, ContractRank, StatsMetric,
+		StatsPeriod,
+	},
+	BlockDetails, CallInput, CalledDetails, CodeDetails, CodeRemovedDetails, CodeStoredDetails,
+	CodeUpdatedDetails, DBEvent, DbError, Event, EventCursor, EventType, Extrinsic,
+	InstantiatedDetails, Origin, TerminatedDetails, MAX_TOTAL_RESULT_SIZE,
+};
+use crate::AccountId;
+use subxt::utils::H256;
+
+// An empty database with blocks `from..=to` marked as indexed.
+fn indexed_db(from: u32, to: u32) -> Connection {
+	let mut conn = Connection::open_in_memory().unwrap();
+	init_db_with_conn(&mut conn, from).unwrap();
+	conn.execute("UPDATE metadata SET indexed_to = ?1 WHERE id = 1", params![to])
+		.unwrap();
+	conn
+}
+
+fn insert_emitted(conn: &Connection, block_num: u32, event_index: u32, data_len: usize) {
+	conn.execute(
+		"INSERT INTO events (contract_account_id, block_num, event_index, extrinsic_index,
+			event_type, caller, data)
+		VALUES (?1, ?2, ?3, 0, 'emitted', NULL, ?4)",
+		params![&[1u8; 32][..], block_num, event_index, vec![0u8; data_len]],
+	)
+	.unwrap();
+}
+
+fn insert_topic(
+	conn: &Connection,
+	block_num: u32,
+	event_index: u32,
+	topic_index: u32,
+	topic: H256,
+) {
+	conn.execute(
+		"INSERT INTO event_topics (block_num, event_index, topic_index, topic)
+		VALUES (?1, ?2, ?3, ?4)",
+		params![block_num, event_index, topic_index, &topic.0[..]],
+	)
+	.unwrap();
+}
+
+fn insert_block(conn: &Connection, block_num: u32, timestamp: u64) {
+	conn.execute(
+		"INSERT INTO blocks (block_num, hash, parent_hash, timestamp, event_count)
+		VALUES (?1, ?2, ?3, ?4, 0)",
+		params![block_num, &[3u8; 32][..], &[4u8; 32][..], timestamp],
+	)
+	.unwrap();
+}
+
+#[test]
+fn cursor_round_trips() {
+	for cursor in [
+		EventCursor { block_num: 0, event_index: 0 },
+		EventCursor { block_num: 84_122_189, event_index: 7 },
+		EventCursor { block_num: u32::MAX, event_index: u32::MAX },
+	] {
+		assert_eq!(EventCursor::decode(&cursor.encode()).unwrap(), cursor);
+	}
+}
+
+#[test]
+fn cursor_rejects_malformed_strings() {
+	for cursor in ["", "zz", "0000000100000002ff", "00000001"] {
+		assert!(EventCursor::decode(cursor).is_err(), "{} was accepted", cursor);
+	}
+}
+
+#[test]
+fn paging_with_the_cursor_returns_every_event_once() {
+	let conn = indexed_db(100, 103);
+	// A third of the result size limit per event, so a page holds a few of them.
+	let data_len = MAX_TOTAL_RESULT_SIZE / 3;
+	let mut expected = Vec::new();
+	for block_num in 100..=103 {
+		for event_index in [1, 4, 5] {
+			insert_emitted(&conn, block_num, event_index, data_len);
+			expected.push((block_num, event_index));
+		}
+	}
+
+	let (mut received, mut pages, mut cursor) = (Vec::new(), 0, None);
+	loop {
+		let page = get_events_by_range(100, 103, None, cursor.as_ref(), &conn).unwrap();
+		pages += 1;
+		received.extend(page.data.iter().map(|e| (e.block_num, e.event_index)));
+		if page.is_complete {
+			assert_eq!(page.next_cursor, None);
+			break;
+		}
+		cursor = Some(EventCursor::decode(&page.next_cursor.unwrap()).unwrap());
+	}
+	assert!(pages > 1, "the events fit in one page");
+	assert_eq!(received, expected);
+}
+
+#[test]
+fn called_events_keep_their_origin() {
+	for caller in [Origin::Root, Origin::Signed(AccountId::from([2u8; 32]))] {
+		let event = Event::new_called(AccountId::from([1u8; 32]), 100, 3, 1, caller);
+		let db_event = DBEvent::from(event.clone());
+		assert_eq!(Event::try_from(db_event).unwrap(), event);
+	}
+}
+
+#[test]
+fn lifecycle_events_go_to_their_columns() {
+	let mut conn = indexed_db(100, 99);
+	let (contract, account) = (AccountId::from([1u8; 32]), AccountId::from([2u8; 32]));
+	let (code, other_code) = (H256([8u8; 32]), H256([9u8; 32]));
+	let events = vec![
+		Event::new(
+			contract.clone(),
+			100,
+			0,
+			1,
+			EventType::Instantiated(InstantiatedDetails {
+				deployer: account.clone(),
+				code_hash: Some(code),
+			}),
+		),
+		Event::new(
+			contract.clone(),
+			100,
+			1,
+			1,
+			EventType::Terminated(TerminatedDetails { beneficiary: account.clone() }),
+		),
+		Event::new_code(
+			100,
+			2,
+			1,
+			EventType::CodeStored(CodeStoredDetails {
+				code_hash: code,
+				uploader: Some(account.clone()),
+			}),
+		),
+		Event::new_code(
+			100,
+			3,
+			1,
+			EventType::CodeRemoved(CodeRemovedDetails {
+				code_hash: code,
+				remover: Some(account.clone()),
+			}),
+		),
+		Event::new(
+			contract.clone(),
+			100,
+			4,
+			1,
+			EventType::ContractCodeUpdated(CodeUpdatedDetails {
+				old_code_hash: code,
+				new_code_hash: other_code,
+			}),
+		),
+		Event::new(
+			contract.clone(),
+			100,
+			5,
+			1,
+			EventType::DelegateCalled(CodeDetails { code_hash: code }),
+		),
+	];
+	insert_events_for_block(events.clone(), Vec::new(), &block_details(100, 1000), &mut conn)
+		.unwrap();
+
+	type Row = (
+		String,
+		Option<[u8; 32]>,
+		Option<[u8; 32]>,
+		Option<[u8; 32]>,
+		Option<[u8; 32]>,
+		Option<[u8; 32]>,
+		Option<[u8; 32]>,
+		Option<[u8; 32]>,
+	);
+	let mut stmt = conn
+		.prepare(
+			"SELECT event_type, contract_account_id, deployer, beneficiary, code_hash,
+				old_code_hash, uploader, remover
+			FROM events ORDER BY event_index",
+		)
+		.unwrap();
+	let rows: Vec<Row> = stmt
+		.query_map([], |row| {
+			Ok((
+				row.get(0)?,
+				row.get(1)?,
+				row.get(2)?,
+				row.get(3)?,
+				row.get(4)?,
+				row.get(5)?,
+				row.get(6)?,
+				row.get(7)?,
+			))
+		})
+		.unwrap()
+		.collect::<Result<_, _>>()
+		.unwrap();
+	let (c, a, h, o) = (Some([1u8; 32]), Some([2u8; 32]), Some([8u8; 32]), Some([9u8; 32]));
+	assert_eq!(
+		rows,
+		vec![
+			("instantiated".to_string(), c, a, None, h, None, None, None),
+			("terminated".to_string(), c, None, a, None, None, None, None),
+			("code_stored".to_string(), None, None, None, h, None, a, None),
+			("code_removed".to_string(), None, None, None, h, None, None, a),
+			("contract_code_updated".to_string(), c, None, None, o, h, None, None),
+			("delegate_called".to_string(), c, None, None, h, None, None, None),
+		]
+	);
+	assert_eq!(get_events_by_range(100, 100, None, None, &conn).unwrap().data, events);
+}
+
+#[test]
+fn code_events_without_an_uploader_or_remover_are_stored() {
+	let mut conn = indexed_db(100, 99);
+	let code = H256([8u8; 32]);
+	// As in runtime 68, whose events do not name them.
+	let events = vec![
+		Event::new_code(
+			100,
+			0,
+			1,
+			EventType::CodeStored(CodeStoredDetails { code_hash: code, uploader: None }),
+		),
+		Event::new_code(
+			100,
+			1,
+			1,
+			EventType::CodeRemoved(CodeRemovedDetails { code_hash: code, remover: None }),
+		),
+	];
+	insert_events_for_block(events.clone(), Vec::new(), &block_details(100, 1000), &mut conn)
+		.unwrap();
+
+	assert_eq!(get_events_by_range(100, 100, None, None, &conn).unwrap().data, events);
+	assert_eq!(
+		get_code(&code, &conn).unwrap(),
+		Some(CodeRecord {
+			code_hash: code,
+			uploader: None,
+			uploaded_block: Some(100),
+			removed_block: Some(100),
+			contracts: vec![],
+			previous_contracts: vec![],
+		})
+	);
+}
+
+#[test]
+fn code_events_of_old_databases_are_moved_off_the_contract_column() {
+	let mut conn = Connection::open_in_memory().unwrap();
+	// The table as it was when the uploader and the remover were stored as the contract.
+	conn.execute_batch(
+		"CREATE TABLE events (
+			id INTEGER PRIMARY KEY AUTOINCREMENT,
+			contract_account_id BLOB NOT NULL,
+			block_num INTEGER NOT NULL,
+			event_index INTEGER NOT NULL,
+			extrinsic_index INTEGER NOT NULL,
+			event_type TEXT NOT NULL,
+			caller BLOB,
+			data BLOB NOT NULL,
+			deployer BLOB,
+			beneficiary BLOB,
+			code_hash BLOB,
+			old_code_hash BLOB,
+			selector BLOB,
+			message TEXT,
+			args TEXT,
+			UNIQUE (block_num, event_index)
+		);
+		CREATE INDEX idx_contract_block_num ON events (contract_account_id, block_num);",
+	)
+	.unwrap();
+	conn.execute(
+		"INSERT INTO events (contract_account_id, block_num, event_index, extrinsic_index,
+			event_type, data, code_hash)
+		VALUES (?1, 100, 0, 1, 'code_stored', x'', ?2), (?1, 100, 1, 1, 'code_removed', x'', ?2)",
+		params![&[2u8; 32][..], &[8u8; 32][..]],
+	)
+	.unwrap();
+	insert_emitted(&conn, 100, 2, 4);
+	init_db_with_conn(&mut conn, 100).unwrap();
+	conn.execute("UPDATE metadata SET indexed_from = 100, indexed_to = 100 WHERE id = 1", [])
+		.unwrap();
+
+	let (account, code) = (AccountId::from([2u8; 32]), H256([8u8; 32]));
+	let events = get_events_by_range(100, 100, None, None, &conn).unwrap().data;
+	assert_eq!(
+		events,
+		vec![
+			Event::new_code(
+				100,
+				0,
+				1,
+				EventType::CodeStored(CodeStoredDetails {
+					code_hash: code,
+					uploader: Some(account.clone())
+				}),
+			),
+			Event::new_code(
+				100,
+				1,
+				1,
+				EventType::CodeRemoved(CodeRemovedDetails {
+					code_hash: code,
+					remover: Some(account)
+				}),
+			),
+			Event::new_emitted(AccountId::from([1u8; 32]), 100, 2, 0, vec![0; 4], vec![]),
+		]
+	);
+	// The indexes of the old table are created again for the new one.
+	let indexed: bool = conn
+		.query_row(
+			"SELECT COUNT(*) > 0 FROM sqlite_master
+			WHERE type = 'index' AND name = 'idx_contract_block_num' AND tbl_name = 'events'",
+			[],
+			|row| row.get(0),
+		)
+		.unwrap();
+	assert!(indexed);
+}
+
+#[test]
+fn rows_of_unknown_event_types_are_an_error() {
+	let conn = indexed_db(100, 100);
+	conn.execute(
+		"INSERT INTO events (contract_account_id, block_num, event_index, extrinsic_index,
+			event_type, data)
+		VALUES (?1, 100, 0, 1, 'unknown', x'')",
+		params![&[1u8; 32][..]],
+	)
+	.unwrap();
+	assert!(matches!(
+		get_events_by_range(100, 100, None, None, &conn),
+		Err(DbError::InvalidEvent(100, 0, _))
+	));
+}
+
+#[test]
+fn calls_are_found_by_selector_and_message() {
+	let mut conn = indexed_db(100, 99);
+	let caller = Origin::Signed(AccountId::from([2u8; 32]));
+	let call = |event_index, data: Vec<u8>, message: Option<&str>| {
+		let input = CallInput::new(data).map(|input| CallInput {
+			message: message.map(str::to_string),
+			args: message.map(|_| serde_json::json!({ "value": 5 })),
+			..input
+		});
+		Event::new(
+			AccountId::from([1u8; 32]),
+			100,
+			event_index,
+			1,
+			EventType::Called(CalledDetails { caller: caller.clone(), input }),
+		)
+	};
+	let events = vec![
+		call(0, vec![], None),
+		call(1, vec![0xdb, 0x20, 0xf9, 0xf5, 5], Some("PSP22::transfer")),
+		call(2, vec![1, 2, 3, 4], None),
+	];
+	let block = BlockDetails {
+		block_num: 100,
+		hash: H256([1u8; 32]),
+		parent_hash: H256([0u8; 32]),
+		timestamp: Some(0),
+	};
+	insert_events_for_block(events.clone(), Vec::new(), &block, &mut conn).unwrap();
+
+	// Calls without a known input are left out.
+	let all = get_calls(100, 100, None, None, None, None, &conn).unwrap();
+	assert_eq!(all.data, events[1..]);
+	let by_selector =
+		get_calls(100, 100, None, Some(&[0xdb, 0x20, 0xf9, 0xf5]), None, None, &conn).unwrap();
+	assert_eq!(by_selector.data, events[1..2]);
+	let by_message = get_calls(
+		100,
+		100,
+		Some(&AccountId::from([1u8; 32])),
+		None,
+		Some("PSP22::transfer"),
+		None,
+		&conn,
+	)
+	.unwrap();
+	assert_eq!(by_message.data, events[1..2]);
+	let elsewhere =
+		get_calls(100, 100, Some(&AccountId::from([3u8; 32])), None, None, None, &conn).unwrap();
+	assert!(elsewhere.data.is_empty());
+}
+
+#[test]
+fn calls_of_a_contract_are_found_through_the_selector_and_message_indexes() {
+	let conn = indexed_db(100, 99);
+	let plan = |query: String| {
+		let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", query)).unwrap();
+		let details = stmt
+			.query_map([], |row| row.get::<_, String>(3))
+			.unwrap()
+			.collect::<Result<Vec<_>, _>>()
+			.unwrap();
+		details.join("\n")
+	};
+	assert!(plan(calls_query(true, true, false)).contains("idx_contract_selector"));
+	assert!(plan(calls_query(true, false, true)).contains("idx_contract_message"));
+}
+
+#[test]
+fn filtering_by_topic_returns_only_events_with_it() {
+	let conn = indexed_db(100, 101);
+	let (signature, to, other) = (H256([7u8; 32]), H256([8u8; 32]), H256([9u8; 32]));
+	for (block_num, event_index, topics) in
+		[(100, 0, vec![signature, to]), (100, 1, vec![signature, other]), (101, 0, vec![to])]
+	{
+		insert_emitted(&conn, block_num, event_index, 4);
+		for (topic_index, topic) in topics.into_iter().enumerate() {
+			insert_topic(&conn, block_num, event_index, topic_index as u32, topic);
+		}
+	}
+	insert_emitted(&conn, 101, 1, 4);
+
+	let events = get_events_by_range(100, 101, Some(&to), None, &conn).unwrap().data;
+	let found: Vec<_> = events.iter().map(|e| (e.block_num, e.event_index)).collect();
+	assert_eq!(found, vec![(100, 0), (101, 0)]);
+	match &events[0].event_type {
+		EventType::Emitted(details) => assert_eq!(details.topics, vec![signature, to]),
+		other => panic!("Unexpected event type {:?}", other),
+	}
+
+	let all = get_events_by_range(100, 101, None, None, &conn).unwrap().data;
+	assert_eq!(all.len(), 4);
+}
+
+#[test]
+fn time_range_maps_to_blocks_with_timestamps_in_it() {
+	let conn = indexed_db(100, 103);
+	for (block_num, timestamp) in [(100, 1000), (101, 2000), (102, 3000), (103, 4000)] {
+		insert_block(&conn, block_num, timestamp);
+	}
+
+	assert_eq!(get_block_range_by_time(1000, 4000, &conn).unwrap(), (100, 103));
+	assert_eq!(get_block_range_by_time(1500, 3000, &conn).unwrap(), (101, 102));
+	let (start, stop) = get_block_range_by_time(2100, 2900, &conn).unwrap();
+	assert!(start > stop, "no block is in the range");
+	assert!(get_block_range_by_time(500, 2000, &conn).is_err());
+	assert!(get_block_range_by_time(1000, 4500, &conn).is_err());
+}
+
+#[test]
+fn first_block_since_a_time_is_found() {
+	let conn = indexed_db(100, 102);
+	for (block_num, timestamp) in [(100, 1000), (101, 2000), (102, 3000)] {
+		insert_block(&conn, block_num, timestamp);
+	}
+
+	assert_eq!(get_first_block_since(500, &conn).unwrap(), Some(100));
+	assert_eq!(get_first_block_since(1500, &conn).unwrap(), Some(101));
+	assert_eq!(get_first_block_since(2000, &conn).unwrap(), Some(101));
+	assert_eq!(get_first_block_since(3500, &conn).unwrap(), None);
+}
+
+#[test]
+fn blocks_without_a_timestamp_are_stored_but_not_timed() {
+	let mut conn = indexed_db(100, 99);
+	let contract = AccountId::from([1u8; 32]);
+	let called = |block_num| {
+		Event::new_called(
+			contract.clone(),
+			block_num,
+			0,
+			0,
+			Origin::Signed(AccountId::from([9u8; 32])),
+		)
+	};
+	let genesis = BlockDetails { timestamp: None, ..block_details(100, 0) };
+	insert_events_for_block(vec![called(100)], Vec::new(), &genesis, &mut conn).unwrap();
+	insert_events_for_block(vec![called(101)], Vec::new(), &block_details(101, DAY), &mut conn)
+		.unwrap();
+
+	assert_eq!(get_stored_blocks(100, 101, &conn).unwrap().len(), 2);
+	assert_eq!(get_block_timestamp(100, &conn).unwrap(), None);
+	assert_eq!(get_block_timestamp(101, &conn).unwrap(), Some(DAY));
+	assert_eq!(get_block_range_by_time(DAY, DAY, &conn).unwrap(), (101, 101));
+	// Only the call in the block with a timestamp falls on a day.
+	let activity = get_contract_activity(&contract, 0, 2 * DAY, StatsPeriod::Day, &conn).unwrap();
+	assert_eq!(
+		activity,
+		vec![ContractActivity { period_start: DAY, calls: 1, emitted: 0, unique_callers: 1 }]
+	);
+}
+
+#[test]
+fn extrinsic_comes_with_its_events() {
+	let conn = indexed_db(100, 100);
+	let hash = H256([5u8; 32]);
+	conn.execute(
+		"INSERT INTO extrinsics (block_num, extrinsic_index, hash, signer, pallet, call, success,
+			fee)
+		VALUES (100, 0, ?1, ?2, 'Contracts', 'call', 1, ?3)",
+		params![&hash.0[..], &[2u8; 32][..], u128::MAX.to_string()],
+	)
+	.unwrap();
+	conn.execute(
+		"INSERT INTO events (contract_account_id, block_num, event_index, extrinsic_index,
+			event_type, caller, data)
+		VALUES (?1, 100, 2, 1, 'emitted', NULL, ?2)",
+		params![&[1u8; 32][..], vec![0u8; 4]],
+	)
+	.unwrap();
+	for event_index in [0, 1] {
+		insert_emitted(&conn, 100, event_index, 4);
+	}
+
+	let found = get_extrinsic(100, 0, &conn).unwrap().unwrap();
+	assert_eq!(found.extrinsic.signer, Some(AccountId::from([2u8; 32])));
+	assert_eq!(found.extrinsic.fee, Some(u128::MAX));
+	assert!(found.extrinsic.success);
+	let events: Vec<_> = found.events.iter().map(|e| e.event_index).collect();
+	assert_eq!(events, vec![0, 1]);
+
+	assert_eq!(get_extrinsics_by_hash(&hash, &conn).unwrap(), vec![found]);
+	assert!(get_extrinsic(100, 1, &conn).unwrap().is_none());
+}
+
+#[test]
+fn extrinsic_with_an_invalid_fee_is_an_error() {
+	let conn = indexed_db(100, 100);
+	conn.execute(
+		"INSERT INTO extrinsics (block_num, extrinsic_index, hash, signer, pallet, call, success,
+			fee)
+		VALUES (100, 0, ?1, NULL, 'Contracts', 'call', 1, 'lots')",
+		params![&[5u8; 32][..]],
+	)
+	.unwrap();
+
+	assert!(matches!(get_extrinsic(100, 0, &conn), Err(DbError::DatabaseError(_))));
+}
+
+#[test]
+fn stored_blocks_report_recorded_and_actual_event_counts() {
+	let conn = indexed_db(100, 101);
+	insert_block(&conn, 100, 1000);
+	insert_emitted(&conn, 100, 0, 4);
+	insert_emitted(&conn, 101, 0, 4);
+
+	let stored = get_stored_blocks(100, 101, &conn).unwrap();
+	assert_eq!(stored[0].hash, Some(H256([3u8; 32])));
+	assert_eq!((stored[0].recorded_event_count, stored[0].event_count), (Some(0), 1));
+	assert_eq!(stored[1].hash, None);
+	assert_eq!((stored[1].recorded_event_count, stored[1].event_count), (None, 1));
+	assert!(get_stored_blocks(99, 101, &conn).is_err());
+}
+
+#[test]
+fn for_each_event_is_not_limited_and_can_stop_early() {
+	let conn = indexed_db(100, 103);
+	let data_len = MAX_TOTAL_RESULT_SIZE / 3;
+	for block_num in 100..=103 {
+		for event_index in [1, 4, 5] {
+			insert_emitted(&conn, block_num, event_index, data_len);
+		}
+	}
+	let mut positions = Vec::new();
+	for_each_event(101, 103, None, None, &conn, |event| {
+		positions.push((event.block_num, event.event_index));
+		true
+	})
+	.unwrap();
+	assert_eq!(positions.len(), 9);
+	assert_eq!(positions.first(), Some(&(101, 1)));
+	assert_eq!(positions.last(), Some(&(103, 5)));
+
+	let mut count = 0;
+	for_each_event(100, 103, Some(&AccountId::from([1u8; 32])), None, &conn, |_| {
+		count += 1;
+		count < 2
+	})
+	.unwrap();
+	assert_eq!(count, 2);
+
+	let mut count = 0;
+	for_each_event(100, 103, Some(&AccountId::from([2u8; 32])), None, &conn, |_| {
+		count += 1;
+		true
+	})
+	.unwrap();
+	assert_eq!(count, 0);
+}
+
+const DAY: u64 = 86_400_000;
+
+fn block_details(block_num: u32, timestamp: u64) -> BlockDetails {
+	BlockDetails {
+		block_num,
+		hash: H256([block_num as u8; 32]),
+		parent_hash: H256([block_num as u8 - 1; 32]),
+		timestamp: Some(timestamp),
+	}
+}
+
+fn rank(contract: u8, value: u64) -> ContractRank {
+	ContractRank { contract_account_id: AccountId::from([contract; 32]), value }
+}
+
+#[test]
+fn stats_follow_inserted_and_replaced_blocks() {
+	let mut conn = indexed_db(100, 99);
+	let called = |contract: u8, caller: u8, block_num, event_index| {
+		Event::new_called(
+			AccountId::from([contract; 32]),
+			block_num,
+			event_index,
+			0,
+			Origin::Signed(AccountId::from([caller; 32])),
+		)
+	};
+	let emitted = |contract: u8, block_num, event_index| {
+		Event::new_emitted(
+			AccountId::from([contract; 32]),
+			block_num,
+			event_index,
+			0,
+			vec![],
+			vec![],
+		)
+	};
+	// Block 100 is on day 20000, 101 and 102 on the next day and 103 on the one after.
+	let blocks = [
+		(100, 20001 * DAY - 1000, vec![called(1, 9, 100, 0), emitted(1, 100, 1)]),
+		(101, 20001 * DAY, vec![called(1, 8, 101, 0), called(2, 9, 101, 1)]),
+		(
+			102,
+			20001 * DAY + 1000,
+			vec![called(1, 9, 102, 0), called(1, 7, 102, 1), emitted(2, 102, 2)],
+		),
+		(103, 20002 * DAY, vec![called(2, 8, 103, 0)]),
+	];
+	for (block_num, timestamp, events) in blocks {
+		insert_events_for_block(
+			events,
+			Vec::new(),
+			&block_details(block_num, timestamp),
+			&mut conn,
+		)
+		.unwrap();
+	}
+
+	let top = |block_start, block_stop, metric, conn: &Connection| {
+		get_top_contracts(block_start, block_stop, metric, 20, conn).unwrap()
+	};
+	// Whole days only.
+	assert_eq!(top(100, 103, StatsMetric::UniqueCallers, &conn), vec![rank(1, 3), rank(2, 2)]);
+	// Block 101 is outside the window, so only block 102 of its day counts.
+	assert_eq!(top(102, 103, StatsMetric::Calls, &conn), vec![rank(1, 2), rank(2, 1)]);
+	assert_eq!(top(101, 101, StatsMetric::Calls, &conn), vec![rank(1, 1), rank(2, 1)]);
+	assert_eq!(top(100, 103, StatsMetric::Emitted, &conn), vec![rank(1, 1), rank(2, 1)]);
+	assert!(get_top_contracts(100, 104, StatsMetric::Calls, 20, &conn).is_err());
+
+	let activity = |period, conn: &Connection| {
+		get_contract_activity(&AccountId::from([1u8; 32]), 20000 * DAY, 20002 * DAY, period, conn)
+			.unwrap()
+	};
+	assert_eq!(
+		activity(StatsPeriod::Day, &conn),
+		vec![
+			ContractActivity { period_start: 20000 * DAY, calls: 1, emitted: 1, unique_callers: 1 },
+			ContractActivity { period_start: 20001 * DAY, calls: 3, emitted: 0, unique_callers: 3 },
+		]
+	);
+	// Day 20000 is a Friday, its week starts on Monday, day 19996.
+	assert_eq!(
+		activity(StatsPeriod::Week, &conn),
+		vec![ContractActivity {
+			period_start: 19996 * DAY,
+			calls: 4,
+			emitted: 1,
+			unique_callers: 3
+		}]
+	);
+
+	replace_block(
+		vec![called(1, 9, 102, 0)],
+		Vec::new(),
+		&block_details(102, 20001 * DAY + 1000),
+		&mut conn,
+	)
+	.unwrap();
+	assert_eq!(top(100, 103, StatsMetric::Emitted, &conn), vec![rank(1, 1)]);
+	assert_eq!(top(101, 102, StatsMetric::UniqueCallers, &conn), vec![rank(1, 2), rank(2, 1)]);
+
+	// Recreated tables are filled from the stored events.
+	conn.execute_batch("DROP TABLE contract_daily_stats; DROP TABLE contract_daily_callers;")
+		.unwrap();
+	init_db_with_conn(&mut conn, 100).unwrap();
+	assert_eq!(top(100, 103, StatsMetric::Calls, &conn), vec![rank(1, 3), rank(2, 2)]);
+	assert_eq!(top(100, 103, StatsMetric::UniqueCallers, &conn), vec![rank(1, 2), rank(2, 2)]);
+	let backfilling: u32 = conn
+		.query_row("SELECT COUNT(*) FROM stats_backfill", [], |row| row.get(0))
+		.unwrap();
+	assert_eq!(backfilling, 0);
+
+	// An interrupted backfill adds only the blocks it had left.
+	conn.execute_batch(
+		"DELETE FROM contract_daily_stats; DELETE FROM contract_daily_callers;
+		INSERT INTO stats_backfill (id, next_block, last_block) VALUES (1, 102, 103);",
+	)
+	.unwrap();
+	init_db_with_conn(&mut conn, 100).unwrap();
+	assert_eq!(top(100, 103, StatsMetric::Calls, &conn), vec![rank(1, 1), rank(2, 1)]);
+}
+
+#[test]
+fn top_contracts_read_days_with_untimestamped_blocks_from_the_events() {
+	let mut conn = indexed_db(100, 99);
+	let called = |contract: u8, block_num| {
+		Event::new_called(AccountId::from([contract; 32]), block_num, 0, 0, Origin::Root)
+	};
+	// Day 20001 is covered by blocks 100-102, but block 101 has no timestamp, so the aggregates
+	// of the day lack its event.
+	let blocks = [
+		(100, Some(20001 * DAY), called(1, 100)),
+		(101, None, called(2, 101)),
+		(102, Some(20001 * DAY + 2000), called(1, 102)),
+		(103, Some(20002 * DAY), called(3, 103)),
+	];
+	for (block_num, timestamp, event) in blocks {
+		let block = BlockDetails { timestamp, ..block_details(block_num, 0) };
+		insert_events_for_block(vec![event], Vec::new(), &block, &mut conn).unwrap();
+	}
+	assert_eq!(
+		get_top_contracts(100, 102, StatsMetric::Calls, 20, &conn).unwrap(),
+		vec![rank(1, 2), rank(2, 1)]
+	);
+}
+
+fn period(code_hash: u8, since_block: Option<u32>, until_block: Option<u32>) -> CodeHashPeriod {
+	CodeHashPeriod { code_hash: H256([code_hash; 32]), since_block, until_block }
+}
+
+#[test]
+fn registry_follows_lifecycle_events() {
+	let mut conn = indexed_db(100, 99);
+	let account = |id: u8| AccountId::from([id; 32]);
+	let event = |contract: u8, block_num, event_index, event_type| {
+		Event::new(account(contract), block_num, event_index, 0, event_type)
+	};
+	let (code_a, code_b) = (H256([0xa; 32]), H256([0xb; 32]));
+	let updated = CodeUpdatedDetails { old_code_hash: code_a, new_code_hash: code_b };
+	// Account 5 uploads code A and deploys contracts 1 and 2 with it, the code of contract 2 is
+	// not known from its event. Both are updated to code B, then contract 2 is terminated and code
+	// A removed.
+	let blocks = [
+		vec![
+			Event::new_code(
+				100,
+				0,
+				0,
+				EventType::CodeStored(CodeStoredDetails {
+					code_hash: code_a,
+					uploader: Some(account(5)),
+				}),
+			),
+			event(
+				1,
+				100,
+				1,
+				EventType::Instantiated(InstantiatedDetails {
+					deployer: account(5),
+					code_hash: Some(code_a),
+				}),
+			),
+			event(
+				2,
+				100,
+				2,
+				EventType::Instantiated(InstantiatedDetails {
+					deployer: account(5),
+					code_hash: None,
+				}),
+			),
+		],
+		vec![
+			event(1, 101, 0, EventType::ContractCodeUpdated(updated.clone())),
+			event(2, 101, 1, EventType::ContractCodeUpdated(updated)),
+		],
+		vec![
+			event(2, 102, 0, EventType::Terminated(TerminatedDetails { beneficiary: account(5) })),
+			Event::new_code(
+				102,
+				1,
+				0,
+				EventType::CodeRemoved(CodeRemovedDetails {
+					code_hash: code_a,
+					remover: Some(account(5)),
+				}),
+			),
+		],
+	];
+	for (block_num, events) in (100..).zip(blocks) {
+		insert_events_for_block(events, Vec::new(), &block_details(block_num, 1000), &mut conn)
+			.unwrap();
+	}
+
+	let contract_1 = ContractRecord {
+		contract_account_id: account(1),
+		deployer: Some(account(5)),
+		instantiated_block: Some(100),
+		code_hash: Some(code_b),
+		terminated_block: None,
+		beneficiary: None,
+		code_history: vec![period(0xa, Some(100), Some(101)), period(0xb, Some(101), None)],
+	};
+	assert_eq!(get_contract(&account(1), &conn).unwrap(), Some(contract_1.clone()));
+	// The code contract 2 was deployed with is known from its update.
+	assert_eq!(
+		get_contract(&account(2), &conn).unwrap(),
+		Some(ContractRecord {
+			contract_account_id: account(2),
+			deployer: Some(account(5)),
+			instantiated_block: Some(100),
+			code_hash: None,
+			terminated_block: Some(102),
+			beneficiary: Some(account(5)),
+			code_history: vec![
+				period(0xa, Some(100), Some(101)),
+				period(0xb, Some(101), Some(102))
+			],
+		})
+	);
+	assert_eq!(get_contract(&account(3), &conn).unwrap(), None);
+	assert_eq!(
+		get_code(&code_a, &conn).unwrap(),
+		Some(CodeRecord {
+			code_hash: code_a,
+			uploader: Some(account(5)),
+			uploaded_block: Some(100),
+			removed_block: Some(102),
+			contracts: vec![],
+			previous_contracts: vec![account(1), account(2)],
+		})
+	);
+	let code_b_record = CodeRecord {
+		code_hash: code_b,
+		uploader: None,
+		uploaded_block: None,
+		removed_block: None,
+		contracts: vec![account(1)],
+		previous_contracts: vec![account(2)],
+	};
+	assert_eq!(get_code(&code_b, &conn).unwrap(), Some(code_b_record));
+	assert_eq!(get_code(&H256([0xc; 32]), &conn).unwrap(), None);
+
+	// Without block 102, contract 2 still runs code B and code A is not removed.
+	replace_block(Vec::new(), Vec::new(), &block_details(102, 1000), &mut conn).unwrap();
+	let contract_2 = get_contract(&account(2), &conn).unwrap().unwrap();
+	assert_eq!((contract_2.code_hash, contract_2.terminated_block), (Some(code_b), None));
+	assert_eq!(get_code(&code_a, &conn).unwrap().unwrap().removed_block, None);
+	assert_eq!(get_code(&code_b, &conn).unwrap().unwrap().contracts, vec![account(1), account(2)]);
+
+	// Recreated tables are filled from the stored events.
+	conn.execute_batch("DROP TABLE contracts; DROP TABLE contract_code_hashes; DROP TABLE codes;")
+		.unwrap();
+	init_db_with_conn(&mut conn, 100).unwrap();
+	assert_eq!(get_contract(&account(1), &conn).unwrap(), Some(contract_1));
+	assert_eq!(get_code(&code_a, &conn).unwrap().unwrap().uploaded_block, Some(100));
+}
+
+#[test]
+fn account_activity_lists_extrinsics_calling_contracts() {
+	let mut conn = indexed_db(100, 99);
+	let account = |id: u8| AccountId::from([id; 32]);
+	let called = |contract: u8, caller: u8, block_num, event_index, extrinsic_index| {
+		Event::new_called(
+			account(contract),
+			block_num,
+			event_index,
+			extrinsic_index,
+			Origin::Signed(account(caller)),
+		)
+	};
+	// Account 9 calls router 1, which calls pair 2, while account 8 calls router 1 directly.
+	let routed = vec![
+		Event::new_emitted(account(2), 100, 0, 1, vec![], vec![]),
+		called(2, 1, 100, 1, 1),
+		called(1, 9, 100, 2, 1),
+	];
+	let extrinsic = Extrinsic {
+		block_num: 100,
+		extrinsic_index: 1,
+		hash: H256([1u8; 32]),
+		signer: Some(account(9)),
+		pallet: "Contracts".to_string(),
+		call: "call".to_string(),
+		success: true,
+		fee: Some(100),
+	};
+	let blocks = [
+		(
+			routed.iter().cloned().chain([called(1, 8, 100, 3, 2)]).collect(),
+			vec![extrinsic.clone()],
+		),
+		(vec![called(3, 9, 101, 0, 0), called(3, 9, 101, 1, 1)], vec![]),
+		(vec![called(1, 8, 102, 0, 0)], vec![]),
+	];
+	for (block_num, (events, extrinsics)) in (100..).zip(blocks) {
+		insert_events_for_block(events, extrinsics, &block_details(block_num, 1000), &mut conn)
+			.unwrap();
+	}
+
+	let found = |page: &[AccountActivity]| -> Vec<(u32, u32)> {
+		page.iter()
+			.map(|activity| (activity.block_num, activity.extrinsic_index))
+			.collect()
+	};
+	let all = get_account_activity(&account(9), None, None, 100, &conn).unwrap();
+	assert!(all.is_complete);
+	assert_eq!(found(&all.data), vec![(100, 1), (101, 0), (101, 1)]);
+	assert_eq!(all.data[0].extrinsic, Some(extrinsic));
+	assert_eq!(all.data[0].contracts, vec![account(2), account(1)]);
+	assert_eq!(all.data[0].events, routed);
+	assert_eq!(all.data[1].extrinsic, None);
+
+	// Pages end with a whole block.
+	let first = get_account_activity(&account(9), None, None, 1, &conn).unwrap();
+	assert_eq!((found(&first.data), first.next_block_start), (vec![(100, 1)], Some(101)));
+	let second = get_account_activity(&account(9), Some(101), None, 1, &conn).unwrap();
+	assert_eq!(found(&second.data), vec![(101, 0), (101, 1)]);
+	assert!(second.is_complete);
+
+	let in_range = get_account_activity(&account(8), None, Some(101), 100, &conn).unwrap();
+	assert_eq!(found(&in_range.data), vec![(100, 2)]);
+	assert!(get_account_activity(&account(8), None, Some(103), 100, &conn).is_err());
+}
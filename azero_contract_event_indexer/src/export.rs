@@ -0,0 +1,159 @@
+use std::fmt::Write;
+
+use futures::{stream, Stream};
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use serde::{Deserialize, Serialize};
+use subxt::utils::H256;
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+
+use crate::{
+	event_db::{check_indexed_range, for_each_event, DbError, Event},
+	AccountId,
+};
+
+// Events are sent in chunks of about this many bytes.
+const CHUNK_SIZE: usize = 64 * 1024;
+// How many chunks the database reader can get ahead of the client.
+const CHUNKS_IN_FLIGHT: usize = 4;
+const CSV_HEADER: &str =
+	"contract_account_id,block_num,event_index,extrinsic_index,event_type,details\n";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+	/// One JSON `Event` per line.
+	#[default]
+	Ndjson,
+	/// One event per row, with the details of its type as a JSON column.
+	Csv,
+}
+
+impl ExportFormat {
+	pub fn content_type(&self) -> &'static str {
+		match self {
+			ExportFormat::Ndjson => "application/x-ndjson",
+			ExportFormat::Csv => "text/csv",
+		}
+	}
+
+	fn header(&self) -> &'static str {
+		match self {
+			ExportFormat::Ndjson => "",
+			ExportFormat::Csv => CSV_HEADER,
+		}
+	}
+
+	fn write_event(&self, event: &Event, out: &mut String) {
+		match self {
+			ExportFormat::Ndjson => {
+				out.push_str(&serde_json::to_string(event).unwrap());
+				out.push('\n');
+			},
+			ExportFormat::Csv => {
+				// `{"<type>": <details>}`, only the details go in the column.
+				let details = match serde_json::to_value(&event.event_type).unwrap() {
+					serde_json::Value::Object(map) =>
+						map.into_iter().next().map(|(_, details)| details).unwrap_or_default(),
+					other => other,
+				};
+				// Code events have no contract, their column is left empty.
+				let contract = event.contract_account_id.as_ref().map(ToString::to_string);
+				let _ = writeln!(
+					out,
+					"{},{},{},{},{},{}",
+					contract.unwrap_or_default(),
+					event.block_num,
+					event.event_index,
+					event.extrinsic_index,
+					event.event_type.name(),
+					csv_field(&details.to_string())
+				);
+			},
+		}
+	}
+}
+
+fn csv_field(value: &str) -> String {
+	format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportQuery {
+	pub block_start: u32,
+	pub block_stop: u32,
+	pub contract_address: Option<AccountId>,
+	pub topic: Option<H256>,
+}
+
+/// All events matching `query`, formatted as `format` and split in chunks. The events are read
+/// from `conn` on a blocking thread, which stays at most `CHUNKS_IN_FLIGHT` chunks ahead of the
+/// consumer and stops once the stream is dropped. An error ends the stream.
+pub fn export_stream(
+	query: ExportQuery,
+	format: ExportFormat,
+	conn: PooledConnection<SqliteConnectionManager>,
+) -> Result<impl Stream<Item = Result<String, DbError>>, DbError> {
+	check_indexed_range(query.block_start, query.block_stop, &conn)?;
+	let (sender, receiver) = mpsc::channel(CHUNKS_IN_FLIGHT);
+	tokio::task::spawn_blocking(move || {
+		let mut chunk = format.header().to_string();
+		let result = for_each_event(
+			query.block_start,
+			query.block_stop,
+			query.contract_address.as_ref(),
+			query.topic.as_ref(),
+			&conn,
+			|event| {
+				format.write_event(&event, &mut chunk);
+				chunk.len() < CHUNK_SIZE ||
+					sender.blocking_send(Ok(std::mem::take(&mut chunk))).is_ok()
+			},
+		);
+		// Fails if the consumer is gone, nothing to do then.
+		let _ = sender.blocking_send(result.map(|()| chunk));
+	});
+	Ok(stream::unfold(receiver, |mut receiver| async move {
+		receiver.recv().await.map(|chunk| (chunk, receiver))
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::ExportFormat;
+	use crate::{
+		event_db::{CodeStoredDetails, Event, EventType},
+		AccountId,
+	};
+	use subxt::utils::H256;
+
+	#[test]
+	fn csv_rows_quote_details() {
+		let uploader = AccountId::from([1u8; 32]);
+		let event = Event::new_code(
+			7,
+			2,
+			1,
+			EventType::CodeStored(CodeStoredDetails {
+				code_hash: H256([9u8; 32]),
+				uploader: Some(uploader.clone()),
+			}),
+		);
+		let mut out = String::new();
+		ExportFormat::Csv.write_event(&event, &mut out);
+		assert_eq!(
+			out,
+			format!(
+				",7,2,1,CodeStored,\"{{\"\"code_hash\"\":\"\"0x{}\"\",\"\"uploader\"\":\"\"{}\"\"}}\"\n",
+				"09".repeat(32),
+				uploader
+			)
+		);
+
+		let mut out = String::new();
+		ExportFormat::Ndjson.write_event(&event, &mut out);
+		assert_eq!(serde_json::from_str::<Event>(out.trim_end()).unwrap(), event);
+		assert!(out.ends_with("}\n"));
+	}
+}
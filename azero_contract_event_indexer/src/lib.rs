@@ -1,5 +1,9 @@
 use azero_config::BlockHeader;
+use azero_universal::RpcPool;
+use block_source::BlockSource;
+use config::IndexerContext;
 use event_db::Event;
+use live::NewEvents;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -8,48 +12,63 @@ pub type RpcClient = azero_config::RpcClient;
 pub type BlockHash = azero_config::BlockHash;
 pub type AccountId = azero_config::AccountId;
 
+pub mod api;
+pub mod audit;
+pub mod block_source;
+pub mod call_decoder;
+pub mod call_tree;
+pub mod client;
+pub mod code_hashes;
+pub mod config;
+pub mod contract_filter;
 pub mod event_db;
+pub mod export;
+pub mod live;
+pub mod metrics;
+pub mod scrape_limits;
 pub mod scraper;
+pub mod snapshot;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct QueryResult<T> {
 	pub data: T,
 	pub is_complete: bool,
+	pub next_cursor: Option<String>,
 }
 
+// This type is separate because of issues with utoipa and generating a Schema with generics
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 pub struct QueryResultEvents {
 	pub data: Vec<Event>,
+	/// Whether the result is complete or it is a partial result (full result didn't fit within the
+	/// limit)
 	pub is_complete: bool,
+	/// Present if the result is partial. Pass it back as `cursor` with the same query to get the
+	/// events that come right after the last returned one.
+	pub next_cursor: Option<String>,
+}
+
+impl From<QueryResult<Vec<Event>>> for QueryResultEvents {
+	fn from(result: QueryResult<Vec<Event>>) -> QueryResultEvents {
+		QueryResultEvents {
+			data: result.data,
+			is_complete: result.is_complete,
+			next_cursor: result.next_cursor,
+		}
+	}
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct Bounds {
 	pub min_block: u32,
 	pub max_block: u32,
-}
-
-//const ENDPOINTS : [&str; 3] = ["wss://aleph-zero-rpc.dwellir.com",
-// "wss://aleph-zero.api.onfinality.io/public-ws", "wss://ws.azero.dev"];
-const ENDPOINTS: [&str; 1] = ["wss://ws.azero.dev"]; //"wss://ws.azero.dev", ,
-
-pub fn random_endpoint() -> &'static str {
-	let index = rand::random::<usize>() % ENDPOINTS.len();
-	ENDPOINTS[index]
-}
-
-pub async fn get_rpc_client() -> anyhow::Result<RpcClient> {
-	let endpoint = random_endpoint();
-	RpcClient::from_url(endpoint)
-		.await
-		.map_err(|e| anyhow::anyhow!("Failed to connect to endpoint {}: {}", endpoint, e))
-}
-
-pub async fn get_client() -> anyhow::Result<Client> {
-	let endpoint = random_endpoint();
-	Client::from_url(endpoint)
-		.await
-		.map_err(|e| anyhow::anyhow!("Failed to connect to endpoint {}: {}", endpoint, e))
+	/// Timestamp of `max_block` in milliseconds since the Unix epoch, if it is known.
+	#[serde(default)]
+	pub max_block_timestamp: Option<u64>,
+	/// Whether `max_block` is older than the staleness threshold of the API, i.e. no scraper seems
+	/// to be advancing the bounds. Also set if the timestamp of `max_block` is unknown.
+	#[serde(default)]
+	pub is_stale: bool,
 }
 
 pub async fn get_current_best_finalized_header(client: &Client) -> anyhow::Result<BlockHeader> {
@@ -57,15 +76,22 @@ pub async fn get_current_best_finalized_header(client: &Client) -> anyhow::Resul
 	Ok(current_block.header().clone())
 }
 
-async fn get_finalized_block_num() -> anyhow::Result<u32> {
-	let client = get_client().await?;
-	let header = get_current_best_finalized_header(&client).await?;
-	Ok(header.number)
+async fn get_finalized_block_num(rpc_pool: &RpcPool) -> anyhow::Result<u32> {
+	rpc_pool
+		.run(|connection| async move {
+			let header = get_current_best_finalized_header(&connection.client).await?;
+			Ok(header.number)
+		})
+		.await
 }
 
-pub async fn start_indexer() -> ! {
+pub async fn start_indexer(
+	context: IndexerContext,
+	new_events: NewEvents,
+	source: BlockSource,
+) -> ! {
 	let current_num = loop {
-		match get_finalized_block_num().await {
+		match source.finalized_block_num().await {
 			Ok(num) => break num,
 			Err(e) => {
 				log::error!("Error getting finalized block number: {}", e);
@@ -73,6 +99,10 @@ pub async fn start_indexer() -> ! {
 			},
 		}
 	};
-	event_db::init_db(current_num).unwrap();
-	scraper::scrape().await
+	if let Some(snapshot) = &context.restore_from {
+		snapshot::restore(snapshot, &context.database_file).unwrap();
+	}
+	event_db::init_db(&context.database_file, current_num).unwrap();
+	tokio::spawn(snapshot::run_schedule(context.snapshots.clone()));
+	scraper::scrape(context, new_events, source).await
 }
@@ -0,0 +1,360 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use futures::{stream, Stream};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::{
+	event_db::{get_bounds_with_conn, get_events_by_range, DbError, Event, EventCursor, Origin},
+	AccountId,
+};
+
+/// How many blocks the scraper can get ahead of a subscriber before the subscriber has to catch up
+/// from the database.
+pub const NEW_EVENTS_CAPACITY: usize = 1000;
+
+/// Events of a newly indexed block at the tip of the chain. Blocks indexed while backfilling are
+/// not sent.
+pub type NewEvents = broadcast::Sender<Vec<Event>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+	pub contract_address: Option<AccountId>,
+	/// Only `Called` events with this signed caller.
+	pub caller: Option<AccountId>,
+	/// Name of the event type, e.g. `Emitted` or `Called`.
+	pub event_type: Option<String>,
+}
+
+impl EventFilter {
+	pub fn matches(&self, event: &Event) -> bool {
+		if let Some(contract_address) = &self.contract_address {
+			if event.contract_account_id.as_ref() != Some(contract_address) {
+				return false;
+			}
+		}
+		if let Some(caller) = &self.caller {
+			match event.event_type.caller() {
+				Some(Origin::Signed(c)) if c == caller => {},
+				_ => return false,
+			}
+		}
+		if let Some(event_type) = &self.event_type {
+			if event.event_type.name() != event_type {
+				return false;
+			}
+		}
+		true
+	}
+}
+
+struct StreamState {
+	new_events: broadcast::Receiver<Vec<Event>>,
+	filter: EventFilter,
+	// Position of the last event seen, whether it matched the filter or not.
+	position: EventCursor,
+	catching_up: bool,
+	pending: VecDeque<Event>,
+	db_pool: Arc<Mutex<Pool<SqliteConnectionManager>>>,
+}
+
+impl StreamState {
+	fn see(&mut self, events: Vec<Event>) {
+		for event in events {
+			let position = EventCursor::after(&event);
+			if position <= self.position {
+				continue;
+			}
+			self.position = position;
+			if self.filter.matches(&event) {
+				self.pending.push_back(event);
+			}
+		}
+	}
+
+	async fn catch_up(&mut self) -> Result<(), DbError> {
+		let conn = {
+			let pool = self.db_pool.lock().await;
+			pool.get().unwrap()
+		};
+		let (_, indexed_to) = get_bounds_with_conn(&conn)?;
+		if self.position.block_num > indexed_to {
+			self.catching_up = false;
+			return Ok(());
+		}
+		let page = get_events_by_range(
+			self.position.block_num,
+			indexed_to,
+			None,
+			Some(&self.position),
+			&conn,
+		)?;
+		if page.is_complete {
+			self.catching_up = false;
+		}
+		self.see(page.data);
+		Ok(())
+	}
+}
+
+/// Streams events matching `filter` as they get indexed. With a `cursor`, events indexed after it
+/// are read from the database first, so a client can resume where it disconnected. Ends with an
+/// error if the database can't be read.
+pub async fn new_events_stream(
+	new_events: &NewEvents,
+	filter: EventFilter,
+	cursor: Option<EventCursor>,
+	db_pool: Arc<Mutex<Pool<SqliteConnectionManager>>>,
+) -> Result<impl Stream<Item = Result<Event, DbError>>, DbError> {
+	// Subscribe before reading the bounds, so that no block is missed in between.
+	let receiver = new_events.subscribe();
+	let (catching_up, position) = match cursor {
+		Some(cursor) => (true, cursor),
+		None => {
+			let conn = {
+				let pool = db_pool.lock().await;
+				pool.get().unwrap()
+			};
+			let (_, indexed_to) = get_bounds_with_conn(&conn)?;
+			(false, EventCursor { block_num: indexed_to, event_index: u32::MAX })
+		},
+	};
+	let state = StreamState {
+		new_events: receiver,
+		filter,
+		position,
+		catching_up,
+		pending: VecDeque::new(),
+		db_pool,
+	};
+
+	Ok(stream::unfold(Some(state), |state| async move {
+		let mut state = state?;
+		loop {
+			if let Some(event) = state.pending.pop_front() {
+				return Some((Ok(event), Some(state)));
+			}
+			if state.catching_up {
+				if let Err(e) = state.catch_up().await {
+					return Some((Err(e), None));
+				}
+				continue;
+			}
+			match state.new_events.recv().await {
+				Ok(events) => state.see(events),
+				Err(broadcast::error::RecvError::Lagged(skipped)) => {
+					log::info!("Subscriber lagged by {} blocks, catching up from db", skipped);
+					state.catching_up = true;
+				},
+				Err(broadcast::error::RecvError::Closed) => return None,
+			}
+		}
+	}))
+}
+
+/// Feeds `new_events` from the database rather than from a scraper, for an API serving a database
+/// that another process indexes. Every `poll_interval` the events of newly indexed blocks are read
+/// and sent.
+pub async fn follow_db(
+	new_events: NewEvents,
+	db_pool: Arc<Mutex<Pool<SqliteConnectionManager>>>,
+	poll_interval: Duration,
+) {
+	let mut position = None;
+	loop {
+		if let Err(e) = send_indexed_events(&new_events, &db_pool, &mut position).await {
+			log::error!("Error following the database: {}", e);
+		}
+		tokio::time::sleep(poll_interval).await;
+	}
+}
+
+// Sends the events indexed after `position`, which starts at the highest indexed block.
+async fn send_indexed_events(
+	new_events: &NewEvents,
+	db_pool: &Mutex<Pool<SqliteConnectionManager>>,
+	position: &mut Option<EventCursor>,
+) -> Result<(), DbError> {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	let (indexed_from, indexed_to) = get_bounds_with_conn(&conn)?;
+	let start = match position {
+		Some(position) if position.block_num < indexed_to => *position,
+		Some(_) => return Ok(()),
+		None => {
+			*position = Some(EventCursor { block_num: indexed_to, event_index: u32::MAX });
+			return Ok(());
+		},
+	};
+	let mut cursor = start;
+	loop {
+		// Started on a database with nothing indexed yet, `start` is below `indexed_from`.
+		let block_start = start.block_num.max(indexed_from);
+		let page = get_events_by_range(block_start, indexed_to, None, Some(&cursor), &conn)?;
+		if let Some(last) = page.data.last() {
+			cursor = EventCursor::after(last);
+		}
+		if !page.data.is_empty() {
+			// Fails only if nobody is subscribed.
+			let _ = new_events.send(page.data);
+		}
+		if page.is_complete {
+			break;
+		}
+	}
+	*position = Some(EventCursor { block_num: indexed_to, event_index: u32::MAX });
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{follow_db, new_events_stream, send_indexed_events, EventFilter, NewEvents};
+	use crate::{
+		event_db::{self, BlockDetails, Event, EventCursor, Origin},
+		AccountId,
+	};
+	use futures::StreamExt;
+	use r2d2::Pool;
+	use r2d2_sqlite::SqliteConnectionManager;
+	use std::{sync::Arc, time::Duration};
+	use subxt::utils::H256;
+	use tokio::sync::{broadcast, Mutex};
+
+	type DbPool = Arc<Mutex<Pool<SqliteConnectionManager>>>;
+
+	// A database in `dir` indexed from block 100, with nothing indexed yet.
+	fn new_db(dir: &str) -> (String, DbPool) {
+		let dir = std::env::temp_dir().join(format!("{}_{}", dir, std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let database_file = dir.join("events.db").display().to_string();
+		event_db::init_db(&database_file, 100).unwrap();
+		let pool = Pool::new(SqliteConnectionManager::file(&database_file)).unwrap();
+		(database_file, Arc::new(Mutex::new(pool)))
+	}
+
+	fn emitted(block_num: u32, event_index: u32) -> Event {
+		Event::new_emitted(AccountId::from([1u8; 32]), block_num, event_index, 0, vec![], vec![])
+	}
+
+	fn index_block(database_file: &str, block_num: u32, events: Vec<Event>) {
+		let block = BlockDetails {
+			block_num,
+			hash: H256([block_num as u8; 32]),
+			parent_hash: H256([block_num as u8 - 1; 32]),
+			timestamp: Some(block_num as u64 * 1000),
+		};
+		let mut conn = event_db::get_connection(database_file).unwrap();
+		event_db::insert_events_for_block(events, vec![], &block, &mut conn).unwrap();
+	}
+
+	fn remove_db(database_file: &str) {
+		std::fs::remove_dir_all(std::path::Path::new(database_file).parent().unwrap()).unwrap();
+	}
+
+	#[test]
+	fn filter_matches_on_every_field() {
+		let (contract, other) = (AccountId::from([1u8; 32]), AccountId::from([2u8; 32]));
+		let called = Event::new_called(contract.clone(), 100, 1, 0, Origin::Signed(other.clone()));
+		let by_root = Event::new_called(contract.clone(), 100, 2, 0, Origin::Root);
+		assert!(EventFilter::default().matches(&emitted(100, 0)));
+
+		let filter = EventFilter { contract_address: Some(contract.clone()), ..Default::default() };
+		assert!(filter.matches(&emitted(100, 0)));
+		let filter = EventFilter { contract_address: Some(other.clone()), ..Default::default() };
+		assert!(!filter.matches(&emitted(100, 0)));
+
+		let filter = EventFilter { caller: Some(other.clone()), ..Default::default() };
+		assert!(filter.matches(&called));
+		assert!(!filter.matches(&by_root));
+		assert!(!filter.matches(&emitted(100, 0)));
+		let filter = EventFilter { caller: Some(contract.clone()), ..Default::default() };
+		assert!(!filter.matches(&called));
+
+		let filter = EventFilter { event_type: Some("Called".to_string()), ..Default::default() };
+		assert!(filter.matches(&called));
+		assert!(!filter.matches(&emitted(100, 0)));
+
+		let filter = EventFilter {
+			contract_address: Some(contract),
+			caller: Some(other),
+			event_type: Some("Emitted".to_string()),
+		};
+		assert!(!filter.matches(&called));
+	}
+
+	#[tokio::test]
+	async fn events_seen_while_catching_up_are_streamed_once() {
+		let (database_file, pool) = new_db("catch_up");
+		index_block(&database_file, 100, vec![emitted(100, 0), emitted(100, 1)]);
+		index_block(&database_file, 101, vec![emitted(101, 0)]);
+		let new_events: NewEvents = broadcast::channel(16).0;
+		let cursor = EventCursor { block_num: 100, event_index: 0 };
+		let stream = new_events_stream(&new_events, EventFilter::default(), Some(cursor), pool)
+			.await
+			.unwrap();
+		let mut stream = Box::pin(stream);
+
+		// Block 101 is sent live while the stream is reading it from the database, and block 102
+		// is indexed before the catch-up reaches the end.
+		new_events.send(vec![emitted(101, 0)]).unwrap();
+		index_block(&database_file, 102, vec![emitted(102, 0)]);
+		new_events.send(vec![emitted(102, 0)]).unwrap();
+		new_events.send(vec![emitted(103, 0)]).unwrap();
+
+		let mut streamed = Vec::new();
+		for _ in 0..4 {
+			streamed.push(stream.next().await.unwrap().unwrap());
+		}
+		assert_eq!(
+			streamed,
+			vec![emitted(100, 1), emitted(101, 0), emitted(102, 0), emitted(103, 0)]
+		);
+		let next = tokio::time::timeout(Duration::from_millis(100), stream.next()).await;
+		assert!(next.is_err(), "streamed {:?}", next);
+		remove_db(&database_file);
+	}
+
+	#[tokio::test]
+	async fn events_indexed_after_the_start_are_sent_once() {
+		let (database_file, pool) = new_db("send_indexed_events");
+		index_block(&database_file, 100, vec![emitted(100, 0)]);
+		let new_events: NewEvents = broadcast::channel(16).0;
+		let mut receiver = new_events.subscribe();
+
+		// Blocks indexed before the start are not sent.
+		let mut position = None;
+		send_indexed_events(&new_events, &pool, &mut position).await.unwrap();
+		assert!(receiver.try_recv().is_err());
+
+		index_block(&database_file, 101, vec![emitted(101, 0), emitted(101, 1)]);
+		index_block(&database_file, 102, vec![]);
+		index_block(&database_file, 103, vec![emitted(103, 2)]);
+		send_indexed_events(&new_events, &pool, &mut position).await.unwrap();
+		assert_eq!(
+			receiver.try_recv().unwrap(),
+			vec![emitted(101, 0), emitted(101, 1), emitted(103, 2)]
+		);
+
+		send_indexed_events(&new_events, &pool, &mut position).await.unwrap();
+		assert!(receiver.try_recv().is_err());
+		remove_db(&database_file);
+	}
+
+	#[tokio::test]
+	async fn followed_database_feeds_new_events() {
+		let (database_file, pool) = new_db("follow_db");
+		let new_events: NewEvents = broadcast::channel(16).0;
+		let mut receiver = new_events.subscribe();
+		tokio::spawn(follow_db(new_events, pool, Duration::from_millis(10)));
+		// Let the first poll find the start.
+		tokio::time::sleep(Duration::from_millis(100)).await;
+
+		index_block(&database_file, 100, vec![emitted(100, 0)]);
+		let events = tokio::time::timeout(Duration::from_secs(5), receiver.recv()).await.unwrap();
+		assert_eq!(events.unwrap(), vec![emitted(100, 0)]);
+		remove_db(&database_file);
+	}
+}
@@ -1,47 +1,129 @@
-use axum::{extract::Query, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use axum::{
+	body::Body,
+	extract::{MatchedPath, Path, Query, Request, State},
+	http::{header, StatusCode},
+	middleware::{self, Next},
+	response::{
+		sse::{self, KeepAlive, Sse},
+		IntoResponse,
+	},
+	routing::{get, post},
+	Json, Router,
+};
 use azero_config::AccountId;
 use azero_contract_event_indexer::{
+	api::{
+		BlocksNotInRange, GetAccountActivityParams, GetBlockByTimeParams, GetCallsParams,
+		GetContractActivityParams, GetEventsParams, GetExportParams, GetLiveEventsParams,
+		GetTopContractsParams, PostAuditParams,
+	},
+	audit::{start_audit, AuditReport, AuditState, BlockMismatch},
+	block_source::{record_fixtures, BlockSource},
+	call_tree::{get_call_tree, CallFrame, CallTreeNode, ExtrinsicCallTree},
+	config::{IndexerConfig, IndexerContext},
 	event_db::{
-		get_bounds_with_conn, get_events_by_contract, get_events_by_range, CalledDetails, DbError,
-		EmittedDetails, Event, EventType, DATABASE_FILE,
+		self,
+		activity::{get_account_activity, AccountActivity, AccountActivityPage},
+		get_block_range_by_time, get_block_timestamp, get_bounds_with_conn, get_calls,
+		get_events_by_contract, get_events_by_range, get_extrinsic, get_extrinsics_by_hash,
+		get_first_block_since,
+		registry::{get_code, get_contract, CodeHashPeriod, CodeRecord, ContractRecord},
+		stats::{
+			get_contract_activity, get_top_contracts, ContractActivity, ContractRank, StatsMetric,
+			StatsPeriod,
+		},
+		CallInput, CalledDetails, CodeDetails, CodeRemovedDetails, CodeStoredDetails,
+		CodeUpdatedDetails, DbError, EmittedDetails, Event, EventCursor, EventType, Extrinsic,
+		ExtrinsicWithEvents, InstantiatedDetails, Origin, TerminatedDetails,
 	},
+	export::{export_stream, ExportFormat, ExportQuery},
+	live::{follow_db, new_events_stream, EventFilter, NewEvents, NEW_EVENTS_CAPACITY},
+	metrics,
+	snapshot::{SnapshotError, SnapshotInfo, Snapshots},
 	start_indexer, Bounds, QueryResultEvents,
 };
 use azero_universal::AccountIdSchema;
-use chrono::Local;
+use chrono::{Local, Utc};
 use env_logger::{Builder, Target};
+use futures::StreamExt;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use serde::Deserialize;
-use std::{io::Write, sync::Arc};
-use tokio::sync::Mutex;
-use utoipa::{IntoParams, OpenApi};
+use rusqlite::{Connection, OpenFlags};
+use std::{
+	io::Write,
+	path::PathBuf,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use subxt::utils::H256;
+use tokio::sync::{broadcast, Mutex};
+use tower_http::compression::CompressionLayer;
+use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 #[derive(OpenApi)]
 #[openapi(
-	paths(handle_get_status, handle_get_events,),
+	paths(
+		handle_get_status,
+		handle_get_block_by_time,
+		handle_get_events,
+		handle_get_calls,
+		handle_get_export,
+		handle_get_live_events,
+		handle_get_extrinsic,
+		handle_get_call_tree,
+		handle_get_extrinsics_by_hash,
+		handle_get_top_contracts,
+		handle_get_contract_activity,
+		handle_get_contract,
+		handle_get_code,
+		handle_get_account_activity,
+		handle_post_audit,
+		handle_get_audit,
+		handle_post_snapshot,
+		handle_get_metrics
+	),
 	components(schemas(
 		Bounds,
+		BlocksNotInRange,
 		AccountIdSchema,
 		QueryResultEvents,
 		Event,
 		EventType,
 		EmittedDetails,
-		CalledDetails
+		CalledDetails,
+		CallInput,
+		Origin,
+		InstantiatedDetails,
+		TerminatedDetails,
+		CodeDetails,
+		CodeStoredDetails,
+		CodeRemovedDetails,
+		CodeUpdatedDetails,
+		Extrinsic,
+		ExtrinsicWithEvents,
+		ExtrinsicCallTree,
+		CallTreeNode,
+		CallFrame,
+		StatsMetric,
+		StatsPeriod,
+		ContractRank,
+		ContractActivity,
+		ContractRecord,
+		CodeHashPeriod,
+		CodeRecord,
+		AccountActivity,
+		AccountActivityPage,
+		AuditReport,
+		BlockMismatch,
+		ExportFormat,
+		SnapshotInfo
 	))
 )]
 pub struct UtoipaApi;
 
 type DbPool = Pool<SqliteConnectionManager>;
 
-#[derive(Debug, Deserialize, IntoParams)]
-struct GetEventsParams {
-	block_start: u32,
-	block_stop: u32,
-	contract_address: Option<AccountId>,
-}
-
 #[utoipa::path(
     get,
     path = "/events",
@@ -60,27 +142,522 @@ async fn handle_get_events(
 		let pool = db_pool.lock().await;
 		pool.get().unwrap()
 	};
+	let cursor = match params.cursor.as_deref().map(EventCursor::decode).transpose() {
+		Ok(cursor) => cursor,
+		Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+	};
+	let (block_start, block_stop) = match block_range(
+		params.block_start,
+		params.block_stop,
+		params.time_start,
+		params.time_stop,
+		&conn,
+	) {
+		Ok(range) => range,
+		Err(response) => return response,
+	};
 	let result = match params.contract_address {
-		Some(contract_address) =>
-			get_events_by_contract(params.block_start, params.block_stop, &contract_address, &conn),
-		None => get_events_by_range(params.block_start, params.block_stop, &conn),
+		Some(contract_address) => get_events_by_contract(
+			block_start,
+			block_stop,
+			&contract_address,
+			params.topic.as_ref(),
+			cursor.as_ref(),
+			&conn,
+		),
+		None => get_events_by_range(
+			block_start,
+			block_stop,
+			params.topic.as_ref(),
+			cursor.as_ref(),
+			&conn,
+		),
 	};
 
 	match result {
-		Ok(events) => Json(events).into_response(),
-		Err(DbError::BlocksNotInRange(start, stop, block_start, block_stop)) => (
+		Ok(events) => Json(QueryResultEvents::from(events)).into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/calls",
+    responses(
+        (status = 200, description = "`Called` events made by extrinsics, with their input", body = QueryResultEvents)
+    ),
+	params(
+		GetCallsParams
+	)
+)]
+async fn handle_get_calls(
+	Query(params): Query<GetCallsParams>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	let cursor = match params.cursor.as_deref().map(EventCursor::decode).transpose() {
+		Ok(cursor) => cursor,
+		Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+	};
+	let selector = match params.selector.as_deref().map(parse_selector) {
+		Some(None) => return (StatusCode::BAD_REQUEST, "Invalid selector").into_response(),
+		selector => selector.flatten(),
+	};
+	let (block_start, block_stop) = match block_range(
+		params.block_start,
+		params.block_stop,
+		params.time_start,
+		params.time_stop,
+		&conn,
+	) {
+		Ok(range) => range,
+		Err(response) => return response,
+	};
+	match get_calls(
+		block_start,
+		block_stop,
+		params.contract_address.as_ref(),
+		selector.as_ref(),
+		params.message.as_deref(),
+		cursor.as_ref(),
+		&conn,
+	) {
+		Ok(events) => Json(QueryResultEvents::from(events)).into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+fn parse_selector(selector: &str) -> Option<[u8; 4]> {
+	hex::decode(selector.strip_prefix("0x")?).ok()?.try_into().ok()
+}
+
+// The block range given either by block numbers or by times.
+fn block_range(
+	block_start: Option<u32>,
+	block_stop: Option<u32>,
+	time_start: Option<u64>,
+	time_stop: Option<u64>,
+	conn: &rusqlite::Connection,
+) -> Result<(u32, u32), axum::response::Response> {
+	match (block_start, block_stop, time_start, time_stop) {
+		(Some(block_start), Some(block_stop), None, None) => Ok((block_start, block_stop)),
+		(None, None, Some(time_start), Some(time_stop)) =>
+			get_block_range_by_time(time_start, time_stop, conn).map_err(db_error_response),
+		_ => Err((
+			StatusCode::BAD_REQUEST,
+			"Pass either block_start and block_stop, or time_start and time_stop",
+		)
+			.into_response()),
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/export",
+    responses(
+        (status = 200, description = "Every matching event, streamed as NDJSON or CSV. Compressed with gzip or zstd if the client accepts it.")
+    ),
+	params(
+		GetExportParams
+	)
+)]
+async fn handle_get_export(
+	Query(params): Query<GetExportParams>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	let (block_start, block_stop) = match block_range(
+		params.block_start,
+		params.block_stop,
+		params.time_start,
+		params.time_stop,
+		&conn,
+	) {
+		Ok(range) => range,
+		Err(response) => return response,
+	};
+	let query = ExportQuery {
+		block_start,
+		block_stop,
+		contract_address: params.contract_address,
+		topic: params.topic,
+	};
+	match export_stream(query, params.format, conn) {
+		Ok(chunks) =>
+			([(header::CONTENT_TYPE, params.format.content_type())], Body::from_stream(chunks))
+				.into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+fn db_error_response(e: DbError) -> axum::response::Response {
+	match e {
+		DbError::BlocksNotInRange(indexed_from, indexed_to, block_start, block_stop) => (
+			StatusCode::BAD_REQUEST,
+			Json(BlocksNotInRange { indexed_from, indexed_to, block_start, block_stop }),
+		)
+			.into_response(),
+		DbError::TimesNotInRange(time_start, time_stop, start, stop) => (
 			StatusCode::BAD_REQUEST,
 			format!(
-				"Blocks not in range, supported {}-{}, requested: {}-{}",
-				start, stop, block_start, block_stop
+				"Times not in range, supported {}-{}, requested: {}-{}",
+				start, stop, time_start, time_stop
 			),
 		)
 			.into_response(),
+		DbError::NoTimestampsIndexed | DbError::NoBlockTimestamp(_) =>
+			(StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+		e => (StatusCode::INTERNAL_SERVER_ERROR, format!("Internal server error: {}", e))
+			.into_response(),
+	}
+}
+
+const DEFAULT_TOP_LIMIT: u32 = 20;
+const MAX_TOP_LIMIT: u32 = 1000;
+
+#[utoipa::path(
+    get,
+    path = "/stats/top",
+    responses(
+        (status = 200, description = "Contracts ranked by the metric over the window", body = Vec<ContractRank>)
+    ),
+	params(
+		GetTopContractsParams
+	)
+)]
+async fn handle_get_top_contracts(
+	Query(params): Query<GetTopContractsParams>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	let (block_start, block_stop) = match block_range(
+		params.block_start,
+		params.block_stop,
+		params.time_start,
+		params.time_stop,
+		&conn,
+	) {
+		Ok(range) => range,
+		Err(response) => return response,
+	};
+	let limit = params.limit.unwrap_or(DEFAULT_TOP_LIMIT).min(MAX_TOP_LIMIT);
+	match get_top_contracts(block_start, block_stop, params.metric, limit, &conn) {
+		Ok(ranks) => Json(ranks).into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/contract/{address}",
+    responses(
+        (status = 200, description = "Activity of the contract per period, periods without any are left out", body = Vec<ContractActivity>)
+    ),
+	params(
+		("address" = String, Path, description = "Contract address"),
+		GetContractActivityParams
+	)
+)]
+async fn handle_get_contract_activity(
+	Path(address): Path<AccountId>,
+	Query(params): Query<GetContractActivityParams>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	match get_contract_activity(&address, params.time_start, params.time_stop, params.period, &conn)
+	{
+		Ok(activity) => Json(activity).into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/contracts/{address}",
+    responses(
+        (status = 200, description = "Deployment, code history and termination of the contract", body = ContractRecord),
+        (status = 404, description = "No lifecycle event of the contract is indexed")
+    ),
+	params(
+		("address" = String, Path, description = "Contract address")
+	)
+)]
+async fn handle_get_contract(
+	Path(address): Path<AccountId>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	match get_contract(&address, &conn) {
+		Ok(Some(contract)) => Json(contract).into_response(),
+		Ok(None) => (StatusCode::NOT_FOUND, "Contract not found").into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/codes/{hash}",
+    responses(
+        (status = 200, description = "Upload and removal of the code, and the contracts running it", body = CodeRecord),
+        (status = 404, description = "Neither the upload of the code nor a contract running it is indexed")
+    ),
+	params(
+		("hash" = String, Path, description = "Code hash, hex encoded with the `0x` prefix")
+	)
+)]
+async fn handle_get_code(Path(hash): Path<H256>, db_pool: Arc<Mutex<DbPool>>) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	match get_code(&hash, &conn) {
+		Ok(Some(code)) => Json(code).into_response(),
+		Ok(None) => (StatusCode::NOT_FOUND, "Code not found").into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+const DEFAULT_ACTIVITY_LIMIT: u32 = 100;
+const MAX_ACTIVITY_LIMIT: u32 = 1000;
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{address}/activity",
+    responses(
+        (status = 200, description = "Extrinsics in which the account called a contract, with the contracts touched and the events emitted", body = AccountActivityPage)
+    ),
+	params(
+		("address" = String, Path, description = "Account address"),
+		GetAccountActivityParams
+	)
+)]
+async fn handle_get_account_activity(
+	Path(address): Path<AccountId>,
+	Query(params): Query<GetAccountActivityParams>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	let limit = params.limit.unwrap_or(DEFAULT_ACTIVITY_LIMIT).clamp(1, MAX_ACTIVITY_LIMIT);
+	match get_account_activity(&address, params.block_start, params.block_stop, limit, &conn) {
+		Ok(page) => Json(page).into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/events/live",
+    responses(
+        (status = 200, description = "Server-sent events, one per indexed `Event`, with the cursor of the event as id")
+    ),
+	params(
+		GetLiveEventsParams
+	)
+)]
+async fn handle_get_live_events(
+	Query(params): Query<GetLiveEventsParams>,
+	new_events: NewEvents,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let cursor = match params.cursor.as_deref().map(EventCursor::decode).transpose() {
+		Ok(cursor) => cursor,
+		Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+	};
+	let filter = EventFilter {
+		contract_address: params.contract_address,
+		caller: params.caller,
+		event_type: params.event_type,
+	};
+	let events = match new_events_stream(&new_events, filter, cursor, db_pool).await {
+		Ok(events) => events,
+		Err(e) => return db_error_response(e),
+	};
+	let sse_events = events.map(|event| match event {
+		Ok(event) =>
+			sse::Event::default().id(EventCursor::after(&event).encode()).json_data(&event),
+		Err(e) => Ok(sse::Event::default().event("error").data(e.to_string())),
+	});
+	Sse::new(sse_events).keep_alive(KeepAlive::default()).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/extrinsic/{block}/{index}",
+    responses(
+        (status = 200, description = "JSON file", body = ExtrinsicWithEvents),
+        (status = 404, description = "Extrinsic not indexed")
+    ),
+	params(
+		("block" = u32, Path, description = "Block number"),
+		("index" = u32, Path, description = "Index of the extrinsic in the block")
+	)
+)]
+async fn handle_get_extrinsic(
+	Path((block_num, extrinsic_index)): Path<(u32, u32)>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	match get_extrinsic(block_num, extrinsic_index, &conn) {
+		Ok(Some(extrinsic)) => Json(extrinsic).into_response(),
+		Ok(None) => (StatusCode::NOT_FOUND, "Extrinsic not found").into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/extrinsic/{block}/{index}/call_tree",
+    responses(
+        (status = 200, description = "Which contract called which in the extrinsic, and the events emitted in every call", body = ExtrinsicCallTree),
+        (status = 404, description = "Extrinsic not indexed")
+    ),
+	params(
+		("block" = u32, Path, description = "Block number"),
+		("index" = u32, Path, description = "Index of the extrinsic in the block")
+	)
+)]
+async fn handle_get_call_tree(
+	Path((block_num, extrinsic_index)): Path<(u32, u32)>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	match get_call_tree(block_num, extrinsic_index, &conn) {
+		Ok(Some(call_tree)) => Json(call_tree).into_response(),
+		Ok(None) => (StatusCode::NOT_FOUND, "Extrinsic not found").into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/extrinsic_by_hash/{hash}",
+    responses(
+        (status = 200, description = "JSON file", body = Vec<ExtrinsicWithEvents>)
+    ),
+	params(
+		("hash" = String, Path, description = "Extrinsic hash, hex encoded with the `0x` prefix")
+	)
+)]
+async fn handle_get_extrinsics_by_hash(
+	Path(hash): Path<H256>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	match get_extrinsics_by_hash(&hash, &conn) {
+		Ok(extrinsics) => Json(extrinsics).into_response(),
+		Err(e) => db_error_response(e),
+	}
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/audit",
+    responses(
+        (status = 202, description = "Audit started, poll GET /admin/audit for the report"),
+        (status = 401, description = "Missing or wrong admin token"),
+        (status = 403, description = "Repair requested from a read-only API"),
+        (status = 409, description = "Another audit is running")
+    ),
+	params(
+		PostAuditParams
+	)
+)]
+async fn handle_post_audit(
+	Query(params): Query<PostAuditParams>,
+	context: IndexerContext,
+	source: BlockSource,
+	audit_state: AuditState,
+	read_only: bool,
+) -> impl IntoResponse {
+	if params.block_start > params.block_stop {
+		return (StatusCode::BAD_REQUEST, "block_start is greater than block_stop").into_response();
+	}
+	if params.repair && read_only {
+		return (StatusCode::FORBIDDEN, "The database is read-only, repair from the scraper")
+			.into_response();
+	}
+	if start_audit(
+		context,
+		source,
+		params.block_start,
+		params.block_stop,
+		params.repair,
+		audit_state,
+	) {
+		StatusCode::ACCEPTED.into_response()
+	} else {
+		(StatusCode::CONFLICT, "Another audit is running").into_response()
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    responses(
+        (status = 200, description = "Report of the running or last audit, null if there was none", body = Option<AuditReport>),
+        (status = 401, description = "Missing or wrong admin token")
+    ),
+)]
+async fn handle_get_audit(audit_state: AuditState) -> impl IntoResponse {
+	let report = audit_state.lock().clone();
+	Json(report)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/snapshot",
+    responses(
+        (status = 201, description = "Snapshot taken", body = SnapshotInfo),
+        (status = 401, description = "Missing or wrong admin token"),
+        (status = 409, description = "Another snapshot is being taken")
+    ),
+)]
+async fn handle_post_snapshot(snapshots: Arc<Snapshots>) -> impl IntoResponse {
+	match tokio::task::spawn_blocking(move || snapshots.take()).await.unwrap() {
+		Ok(info) => (StatusCode::CREATED, Json(info)).into_response(),
+		Err(SnapshotError::Busy) =>
+			(StatusCode::CONFLICT, SnapshotError::Busy.to_string()).into_response(),
 		Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Internal server error: {}", e))
 			.into_response(),
 	}
 }
 
+// Without a timestamp for the last indexed block, e.g. before the first block is indexed, nothing
+// shows the data to be fresh.
+fn is_stale(max_block_timestamp: Option<u64>, now: u64, stale_after: Duration) -> bool {
+	match max_block_timestamp {
+		Some(timestamp) => now.saturating_sub(timestamp) > stale_after.as_millis() as u64,
+		None => true,
+	}
+}
+
 #[utoipa::path(
     get,
     path = "/status",
@@ -88,21 +665,321 @@ async fn handle_get_events(
         (status = 200, description = "JSON file", body = Bounds)
     ),
 )]
-async fn handle_get_status(db_pool: Arc<Mutex<DbPool>>) -> impl IntoResponse {
+async fn handle_get_status(
+	db_pool: Arc<Mutex<DbPool>>,
+	stale_after: Duration,
+) -> impl IntoResponse {
 	let conn = {
 		let pool = db_pool.lock().await;
 		pool.get().unwrap()
 	};
-	match get_bounds_with_conn(&conn) {
-		Ok(bounds) => {
-			let bounds = Bounds { min_block: bounds.0, max_block: bounds.1 };
-			Json(bounds).into_response()
+	let bounds = get_bounds_with_conn(&conn).and_then(|(min_block, max_block)| {
+		Ok((min_block, max_block, get_block_timestamp(max_block, &conn)?))
+	});
+	match bounds {
+		Ok((min_block, max_block, max_block_timestamp)) => {
+			let now = Utc::now().timestamp_millis() as u64;
+			let is_stale = is_stale(max_block_timestamp, now, stale_after);
+			Json(Bounds { min_block, max_block, max_block_timestamp, is_stale }).into_response()
 		},
 		Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Internal server error: {}", e))
 			.into_response(),
 	}
 }
 
+#[utoipa::path(
+    get,
+    path = "/blocks/by_time",
+    responses(
+        (status = 200, description = "The first indexed block with a timestamp at or after `time`", body = u32),
+        (status = 404, description = "All indexed blocks are older")
+    ),
+	params(
+		GetBlockByTimeParams
+	)
+)]
+async fn handle_get_block_by_time(
+	Query(params): Query<GetBlockByTimeParams>,
+	db_pool: Arc<Mutex<DbPool>>,
+) -> impl IntoResponse {
+	let conn = {
+		let pool = db_pool.lock().await;
+		pool.get().unwrap()
+	};
+	match get_first_block_since(params.time, &conn) {
+		Ok(Some(block_num)) => Json(block_num).into_response(),
+		Ok(None) => (StatusCode::NOT_FOUND, "No block indexed since then").into_response(),
+		Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Internal server error: {}", e))
+			.into_response(),
+	}
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Indexer metrics of every network, and API metrics, in the Prometheus text format")
+    ),
+)]
+async fn handle_get_metrics(contexts: Arc<Vec<IndexerContext>>) -> impl IntoResponse {
+	([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics::render(&contexts))
+}
+
+// Lets through requests with `Authorization: Bearer <token>`.
+async fn require_admin_token(
+	State(token): State<Arc<str>>,
+	request: Request,
+	next: Next,
+) -> axum::response::Response {
+	let expected = format!("Bearer {}", token);
+	let authorization = request.headers().get(header::AUTHORIZATION);
+	if !authorization.is_some_and(|value| constant_time_eq(value.as_bytes(), expected.as_bytes())) {
+		return (StatusCode::UNAUTHORIZED, "Missing or wrong admin token").into_response();
+	}
+	next.run(request).await
+}
+
+// Compares without returning early, so the time taken does not tell how much of the token matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	if a.len() != b.len() {
+		return false;
+	}
+	a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn track_request_latency(request: Request, next: Next) -> axum::response::Response {
+	let route = request
+		.extensions()
+		.get::<MatchedPath>()
+		.map(|path| path.as_str().to_string())
+		.unwrap_or_default();
+	let start = Instant::now();
+	let response = next.run(request).await;
+	metrics::observe_api_request(&route, start.elapsed());
+	response
+}
+
+// The API reports data as stale if the last indexed block is older than this.
+const DEFAULT_STALE_AFTER_SECS: u64 = 120;
+
+/// What the process runs, given as the first command line argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+	/// `all`, the default: the scraper and the API, sharing the database.
+	All,
+	/// `scraper`: only the scraper, with nothing but `/metrics` served.
+	Scraper,
+	/// `api`: only the API, on a database indexed by another process, opened read-only.
+	Api,
+}
+
+impl Mode {
+	fn from_args() -> Self {
+		let arg = std::env::args().nth(1);
+		Self::from_arg(arg.as_deref()).unwrap_or_else(|| {
+			eprintln!("Unknown mode {}", arg.unwrap_or_default());
+			eprintln!("Usage: azero_contract_event_indexer [all|scraper|api]");
+			std::process::exit(2);
+		})
+	}
+
+	fn from_arg(arg: Option<&str>) -> Option<Self> {
+		match arg {
+			None | Some("all") => Some(Mode::All),
+			Some("scraper") => Some(Mode::Scraper),
+			Some("api") => Some(Mode::Api),
+			Some(_) => None,
+		}
+	}
+}
+
+// Nothing migrates the database in API-only mode, so it must have been by a scraper already.
+fn check_api_db(database_file: &str) -> Result<(u32, u32), String> {
+	let conn = Connection::open_with_flags(
+		database_file,
+		OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+	)
+	.map_err(|e| format!("Cannot open database {}: {}", database_file, e))?;
+	event_db::check_schema(&conn).map_err(|e| {
+		format!("Database {} is not ready, run the scraper on it first: {}", database_file, e)
+	})
+}
+
+fn env_block_num(name: &str) -> u32 {
+	std::env::var(name)
+		.unwrap_or_else(|_| panic!("{} must be set", name))
+		.parse()
+		.unwrap_or_else(|_| panic!("{} must be a block number", name))
+}
+
+// The network named by `RECORD_NETWORK`, which may be left out if only one is indexed.
+fn recorded_context<'a>(contexts: &'a [IndexerContext], name: Option<&str>) -> &'a IndexerContext {
+	match name {
+		Some(name) => contexts
+			.iter()
+			.find(|context| context.network.name() == name)
+			.unwrap_or_else(|| panic!("RECORD_NETWORK {} is not indexed", name)),
+		None if contexts.len() == 1 => &contexts[0],
+		None => panic!("RECORD_NETWORK must be set if several networks are indexed"),
+	}
+}
+
+// With `read_only`, the database is indexed by another process: it is opened read-only and new
+// events are picked up from it. The `/admin` routes are served only with an `admin_token`, which
+// requests must carry.
+fn network_router(
+	context: &IndexerContext,
+	new_events: NewEvents,
+	source: BlockSource,
+	read_only: bool,
+	stale_after: Duration,
+	admin_token: Option<Arc<str>>,
+) -> Router {
+	let mut manager = SqliteConnectionManager::file(&context.database_file);
+	if read_only {
+		manager = manager.with_flags(
+			OpenFlags::SQLITE_OPEN_READ_ONLY |
+				OpenFlags::SQLITE_OPEN_URI |
+				OpenFlags::SQLITE_OPEN_NO_MUTEX,
+		);
+	}
+	let pool = Pool::builder()
+		.build(manager)
+		.unwrap_or_else(|e| panic!("Failed to open database {}: {}", context.database_file, e));
+	let shared_pool = Arc::new(Mutex::new(pool));
+	let audit_state = AuditState::default();
+	if read_only {
+		tokio::spawn(follow_db(
+			new_events.clone(),
+			Arc::clone(&shared_pool),
+			Duration::from_secs(1),
+		));
+	}
+
+	let router = Router::new()
+		.route(
+			"/events",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |query| handle_get_events(query, pool)
+			}),
+		)
+		.route(
+			"/calls",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |query| handle_get_calls(query, pool)
+			}),
+		)
+		.route(
+			"/export",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |query| handle_get_export(query, pool)
+			})
+			.layer(CompressionLayer::new()),
+		)
+		.route(
+			"/events/live",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |query| handle_get_live_events(query, new_events, pool)
+			}),
+		)
+		.route(
+			"/extrinsic/:block/:index",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |path| handle_get_extrinsic(path, pool)
+			}),
+		)
+		.route(
+			"/extrinsic/:block/:index/call_tree",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |path| handle_get_call_tree(path, pool)
+			}),
+		)
+		.route(
+			"/extrinsic_by_hash/:hash",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |path| handle_get_extrinsics_by_hash(path, pool)
+			}),
+		)
+		.route(
+			"/stats/top",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |query| handle_get_top_contracts(query, pool)
+			}),
+		)
+		.route(
+			"/stats/contract/:address",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |path, query| handle_get_contract_activity(path, query, pool)
+			}),
+		)
+		.route(
+			"/contracts/:address",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |path| handle_get_contract(path, pool)
+			}),
+		)
+		.route(
+			"/accounts/:address/activity",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |path, query| handle_get_account_activity(path, query, pool)
+			}),
+		)
+		.route(
+			"/codes/:hash",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |path| handle_get_code(path, pool)
+			}),
+		)
+		.route(
+			"/blocks/by_time",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move |query| handle_get_block_by_time(query, pool)
+			}),
+		)
+		.route(
+			"/status",
+			get({
+				let pool = Arc::clone(&shared_pool);
+				move || handle_get_status(pool, stale_after)
+			}),
+		);
+	let Some(admin_token) = admin_token else {
+		return router;
+	};
+	let admin_router = Router::new()
+		.route(
+			"/admin/audit",
+			post({
+				let context = context.clone();
+				let audit_state = audit_state.clone();
+				move |query| handle_post_audit(query, context, source, audit_state, read_only)
+			})
+			.get(move || handle_get_audit(audit_state)),
+		)
+		.route(
+			"/admin/snapshot",
+			post({
+				let snapshots = context.snapshots.clone();
+				move || handle_post_snapshot(snapshots)
+			}),
+		)
+		.route_layer(middleware::from_fn_with_state(admin_token, require_admin_token));
+	router.merge(admin_router)
+}
+
 #[tokio::main]
 async fn main() {
 	Builder::new()
@@ -120,30 +997,105 @@ async fn main() {
 		})
 		.init();
 
-	tokio::spawn(async {
-		start_indexer().await;
-	});
-	tokio::time::sleep(std::time::Duration::from_secs(1)).await; // wait for indexer to start
+	let mode = Mode::from_args();
+	log::info!("Running {:?}", mode);
+	let contexts = IndexerConfig::into_contexts(IndexerConfig::from_env().unwrap()).unwrap();
+	for context in &contexts {
+		log::info!(
+			"Indexing {}: database {}, endpoints {}, from block {}",
+			context.network.name(),
+			context.database_file,
+			context.rpc_pool.urls().join(","),
+			context.first_block
+		);
+	}
 
-	let manager = SqliteConnectionManager::file(DATABASE_FILE);
-	let pool = Pool::builder().build(manager).unwrap();
-	let shared_pool = Arc::new(Mutex::new(pool));
+	if let Ok(dir) = std::env::var("RECORD_FIXTURES_DIR") {
+		let context = recorded_context(&contexts, std::env::var("RECORD_NETWORK").ok().as_deref());
+		let block_start = env_block_num("RECORD_BLOCK_START");
+		let block_stop = env_block_num("RECORD_BLOCK_STOP");
+		let dir = PathBuf::from(dir);
+		record_fixtures(&context.rpc_pool, context.network, &dir, block_start, block_stop)
+			.await
+			.unwrap();
+		return;
+	}
+
+	if mode == Mode::Api {
+		for context in &contexts {
+			match check_api_db(&context.database_file) {
+				Ok((indexed_from, indexed_to)) => log::info!(
+					"Serving {} from blocks {} to {}",
+					context.network.name(),
+					indexed_from,
+					indexed_to
+				),
+				Err(e) => {
+					log::error!("{}", e);
+					std::process::exit(1);
+				},
+			}
+		}
+	}
+
+	let mut networks = Vec::new();
+	for context in &contexts {
+		let (new_events, _) = broadcast::channel(NEW_EVENTS_CAPACITY);
+		let source = BlockSource::from_env(context.network, context.rpc_pool.clone());
+		if mode != Mode::Api {
+			tokio::spawn({
+				let context = context.clone();
+				let new_events = new_events.clone();
+				let source = source.clone();
+				async move {
+					start_indexer(context, new_events, source).await;
+				}
+			});
+		}
+		networks.push((new_events, source));
+	}
+	if mode != Mode::Api {
+		tokio::time::sleep(Duration::from_secs(1)).await; // wait for indexer to start
+	}
 
-	let app = Router::new()
+	// Every network is served under its own prefix, the first one also without a prefix.
+	let mut app = Router::new();
+	if mode != Mode::Scraper {
+		let stale_after = Duration::from_secs(
+			std::env::var("STALE_AFTER_SECS")
+				.map(|secs| secs.parse().expect("STALE_AFTER_SECS must be a number"))
+				.unwrap_or(DEFAULT_STALE_AFTER_SECS),
+		);
+		let admin_token: Option<Arc<str>> = std::env::var("ADMIN_TOKEN").ok().map(Into::into);
+		if admin_token.is_none() {
+			log::info!("ADMIN_TOKEN is not set, the /admin routes are not served");
+		}
+		for (i, (context, (new_events, source))) in contexts.iter().zip(networks).enumerate() {
+			let router = network_router(
+				context,
+				new_events,
+				source,
+				mode == Mode::Api,
+				stale_after,
+				admin_token.clone(),
+			);
+			if i == 0 {
+				app = app.merge(router.clone());
+			}
+			app = app.nest(&format!("/{}", context.network.name()), router);
+		}
+	}
+	// Without a scraper in this process there are no indexer metrics to report.
+	let scraped_contexts = if mode == Mode::Api { Vec::new() } else { contexts };
+	let app = app
 		.route(
-			"/events",
+			"/metrics",
 			get({
-				let pool = Arc::clone(&shared_pool);
-				move |query| handle_get_events(query, pool)
-			}),
-		)
-		.route(
-			"/status",
-			get({
-				let pool = Arc::clone(&shared_pool);
-				move || handle_get_status(pool)
+				let contexts = Arc::new(scraped_contexts);
+				move || handle_get_metrics(contexts)
 			}),
 		)
+		.route_layer(middleware::from_fn(track_request_latency))
 		.merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", UtoipaApi::openapi()));
 
 	let addr = "0.0.0.0:3000";
@@ -151,3 +1103,178 @@ async fn main() {
 	log::info!("Server running at {}", addr);
 	axum::serve(listener, app.into_make_service()).await.unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		check_api_db, is_stale, network_router, require_admin_token, Mode, DEFAULT_STALE_AFTER_SECS,
+	};
+	use axum::{http::StatusCode, middleware, routing::get, Router};
+	use azero_config::AccountId;
+	use azero_contract_event_indexer::{
+		api::GetEventsParams,
+		block_source::BlockSource,
+		client::EventIndexerClient,
+		config::IndexerConfig,
+		event_db::{self, Event, EventType, InstantiatedDetails, Origin},
+		live::NEW_EVENTS_CAPACITY,
+		start_indexer,
+	};
+	use std::{path::Path, sync::Arc, time::Duration};
+	use subxt::utils::H256;
+	use tokio::sync::broadcast;
+
+	#[test]
+	fn mode_is_parsed_from_the_first_argument() {
+		assert_eq!(Mode::from_arg(None), Some(Mode::All));
+		assert_eq!(Mode::from_arg(Some("all")), Some(Mode::All));
+		assert_eq!(Mode::from_arg(Some("scraper")), Some(Mode::Scraper));
+		assert_eq!(Mode::from_arg(Some("api")), Some(Mode::Api));
+	}
+
+	#[test]
+	fn unknown_mode_is_rejected() {
+		assert_eq!(Mode::from_arg(Some("indexer")), None);
+	}
+
+	#[test]
+	fn api_needs_a_database_at_the_current_schema() {
+		let database_file = std::env::temp_dir()
+			.join(format!("api_db_{}.db", std::process::id()))
+			.to_str()
+			.unwrap()
+			.to_string();
+		let _ = std::fs::remove_file(&database_file);
+		assert!(check_api_db(&database_file).is_err());
+
+		rusqlite::Connection::open(&database_file).unwrap();
+		assert!(check_api_db(&database_file).unwrap_err().contains("schema version is 0"));
+
+		event_db::init_db(&database_file, 100).unwrap();
+		assert_eq!(check_api_db(&database_file), Ok((100, 99)));
+		std::fs::remove_file(&database_file).unwrap();
+	}
+
+	#[test]
+	fn data_is_stale_if_the_last_block_is_old_or_has_no_timestamp() {
+		let stale_after = Duration::from_secs(120);
+		let now = 1_700_000_000_000;
+		assert!(!is_stale(Some(now - 120_000), now, stale_after));
+		assert!(is_stale(Some(now - 120_001), now, stale_after));
+		// A block from a clock ahead of ours is not stale.
+		assert!(!is_stale(Some(now + 1000), now, stale_after));
+		assert!(is_stale(None, now, stale_after));
+	}
+
+	#[tokio::test]
+	async fn admin_routes_need_the_token() {
+		let token: Arc<str> = "secret".into();
+		let router = Router::new()
+			.route("/admin/audit", get(|| async { "report" }))
+			.route_layer(middleware::from_fn_with_state(token, require_admin_token));
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}/admin/audit", listener.local_addr().unwrap());
+		tokio::spawn(async move {
+			axum::serve(listener, router.into_make_service()).await.unwrap();
+		});
+
+		let client = reqwest::Client::new();
+		let status = |request: reqwest::RequestBuilder| async move {
+			request.send().await.unwrap().status().as_u16()
+		};
+		let unauthorized = StatusCode::UNAUTHORIZED.as_u16();
+		assert_eq!(status(client.get(&url)).await, unauthorized);
+		assert_eq!(status(client.get(&url).bearer_auth("public")).await, unauthorized);
+		assert_eq!(status(client.get(&url).bearer_auth("secreT")).await, unauthorized);
+		assert_eq!(status(client.get(&url).bearer_auth("secret")).await, 200);
+	}
+
+	#[tokio::test]
+	async fn recorded_blocks_are_indexed_and_served() {
+		let database_file = std::env::temp_dir()
+			.join(format!("e2e_{}.db", std::process::id()))
+			.to_str()
+			.unwrap()
+			.to_string();
+		let config = IndexerConfig {
+			database_file: Some(database_file.clone()),
+			rpc_endpoints: Some(vec!["ws://localhost:1".to_string()]),
+			first_block: Some(100),
+			..Default::default()
+		};
+		let context = IndexerConfig::into_contexts(vec![config]).unwrap().remove(0);
+		let fixtures_dir = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/e2e"));
+		let source =
+			BlockSource::new(Some(fixtures_dir), context.network, context.rpc_pool.clone());
+		let (new_events, _) = broadcast::channel(NEW_EVENTS_CAPACITY);
+		tokio::spawn({
+			let context = context.clone();
+			let new_events = new_events.clone();
+			let source = source.clone();
+			async move {
+				start_indexer(context, new_events, source).await;
+			}
+		});
+
+		// Block 102, the last fixture, is the finalized head: the scraper starts there and fills
+		// in the blocks below it down to `first_block` first.
+		let backfilled = async {
+			while event_db::get_bounds(&database_file).ok().map(|(from, _)| from) != Some(100) {
+				tokio::time::sleep(Duration::from_millis(50)).await;
+			}
+		};
+		tokio::time::timeout(Duration::from_secs(10), backfilled).await.unwrap();
+
+		let router = network_router(
+			&context,
+			new_events,
+			source,
+			false,
+			Duration::from_secs(DEFAULT_STALE_AFTER_SECS),
+			None,
+		);
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let client = EventIndexerClient::new(&format!("http://{}", listener.local_addr().unwrap()));
+		tokio::spawn(async move {
+			axum::serve(listener, router.into_make_service()).await.unwrap();
+		});
+
+		let (contract, caller, instantiated) =
+			(AccountId::from([1u8; 32]), AccountId::from([2u8; 32]), AccountId::from([3u8; 32]));
+		let params =
+			GetEventsParams { block_start: Some(100), block_stop: Some(101), ..Default::default() };
+		let events = client.events(&params).await.unwrap();
+		assert!(events.is_complete);
+		assert_eq!(
+			events.data,
+			vec![
+				Event::new_called(contract.clone(), 100, 2, 1, Origin::Signed(caller.clone())),
+				Event::new_emitted(
+					contract.clone(),
+					100,
+					3,
+					1,
+					vec![1, 2, 3],
+					vec![H256([7u8; 32])]
+				),
+				Event::new(
+					instantiated.clone(),
+					101,
+					4,
+					1,
+					EventType::Instantiated(InstantiatedDetails {
+						deployer: caller,
+						code_hash: Some(H256([9u8; 32])),
+					}),
+				),
+			]
+		);
+
+		let params = GetEventsParams { contract_address: Some(instantiated), ..params };
+		assert_eq!(client.events(&params).await.unwrap().data.len(), 1);
+
+		for suffix in ["", "-wal", "-shm"] {
+			let _ = std::fs::remove_file(format!("{}{}", database_file, suffix));
+		}
+	}
+}
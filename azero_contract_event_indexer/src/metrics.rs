@@ -0,0 +1,262 @@
+use std::{
+	collections::BTreeMap,
+	fmt::Write,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex,
+	},
+	time::{Duration, Instant},
+};
+
+use azero_universal::EndpointHealth;
+
+use crate::{config::IndexerContext, scrape_limits::ScrapeLimits};
+
+// Upper bounds of histogram buckets, in seconds.
+const BUCKETS: [f64; 10] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0, 5.0];
+
+#[derive(Default)]
+pub struct Histogram {
+	bucket_counts: [u64; BUCKETS.len()],
+	count: u64,
+	sum: f64,
+}
+
+impl Histogram {
+	fn observe(&mut self, value: f64) {
+		for (bound, bucket_count) in BUCKETS.iter().zip(self.bucket_counts.iter_mut()) {
+			if value <= *bound {
+				*bucket_count += 1;
+			}
+		}
+		self.count += 1;
+		self.sum += value;
+	}
+
+	fn render(&self, out: &mut String, name: &str, labels: &str) {
+		let sep = if labels.is_empty() { "" } else { "," };
+		for (bound, bucket_count) in BUCKETS.iter().zip(self.bucket_counts.iter()) {
+			let _ = writeln!(
+				out,
+				"{}_bucket{{{}{}le=\"{}\"}} {}",
+				name, labels, sep, bound, bucket_count
+			);
+		}
+		let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, sep, self.count);
+		let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+		let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+		let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+	}
+}
+
+/// Metrics of the indexer of one network.
+#[derive(Default)]
+pub struct Metrics {
+	indexed_from: AtomicU64,
+	indexed_to: AtomicU64,
+	finalized: AtomicU64,
+	// f64 bits.
+	blocks_per_second: AtomicU64,
+	pending_ranges: AtomicU64,
+	solved_ranges: AtomicU64,
+	limit_pending_left: AtomicU64,
+	limit_pending_right: AtomicU64,
+	limit_range_size: AtomicU64,
+	limit_max_solved: AtomicU64,
+	db_insert_seconds: Mutex<Histogram>,
+}
+
+/// Latency of API requests per route, shared by all networks.
+pub static API_REQUEST_SECONDS: Mutex<BTreeMap<String, Histogram>> = Mutex::new(BTreeMap::new());
+
+pub fn observe_api_request(route: &str, elapsed: Duration) {
+	API_REQUEST_SECONDS
+		.lock()
+		.unwrap()
+		.entry(route.to_string())
+		.or_default()
+		.observe(elapsed.as_secs_f64());
+}
+
+impl Metrics {
+	pub fn set_bounds(&self, indexed_from: u32, indexed_to: u32) {
+		self.indexed_from.store(indexed_from as u64, Ordering::Relaxed);
+		self.indexed_to.store(indexed_to as u64, Ordering::Relaxed);
+	}
+
+	pub fn set_finalized(&self, finalized: u32) {
+		self.finalized.store(finalized as u64, Ordering::Relaxed);
+	}
+
+	pub fn set_blocks_per_second(&self, rate: f64) {
+		self.blocks_per_second.store(rate.to_bits(), Ordering::Relaxed);
+	}
+
+	pub fn set_ranges(&self, pending: usize, solved: usize) {
+		self.pending_ranges.store(pending as u64, Ordering::Relaxed);
+		self.solved_ranges.store(solved as u64, Ordering::Relaxed);
+	}
+
+	pub fn set_scrape_limits(&self, limits: &ScrapeLimits) {
+		self.limit_pending_left.store(limits.num_pending_left as u64, Ordering::Relaxed);
+		self.limit_pending_right
+			.store(limits.num_pending_right as u64, Ordering::Relaxed);
+		self.limit_range_size.store(limits.range_size as u64, Ordering::Relaxed);
+		self.limit_max_solved.store(limits.max_solved as u64, Ordering::Relaxed);
+	}
+
+	pub fn observe_db_insert(&self, elapsed: Duration) {
+		self.db_insert_seconds.lock().unwrap().observe(elapsed.as_secs_f64());
+	}
+
+	fn gauges(&self) -> [(&'static str, &'static str, f64); 11] {
+		let indexed_to = self.indexed_to.load(Ordering::Relaxed);
+		let finalized = self.finalized.load(Ordering::Relaxed);
+		[
+			(
+				"indexer_indexed_from",
+				"Lowest indexed block",
+				self.indexed_from.load(Ordering::Relaxed) as f64,
+			),
+			("indexer_indexed_to", "Highest indexed block", indexed_to as f64),
+			("indexer_finalized_block", "Last seen finalized block", finalized as f64),
+			(
+				"indexer_blocks_behind_finalized",
+				"Finalized blocks not indexed yet",
+				finalized.saturating_sub(indexed_to) as f64,
+			),
+			(
+				"indexer_blocks_per_second",
+				"Indexing rate over the last checkpoint",
+				f64::from_bits(self.blocks_per_second.load(Ordering::Relaxed)),
+			),
+			(
+				"indexer_pending_ranges",
+				"Block ranges being scraped",
+				self.pending_ranges.load(Ordering::Relaxed) as f64,
+			),
+			(
+				"indexer_solved_ranges",
+				"Scraped block ranges waiting to be inserted",
+				self.solved_ranges.load(Ordering::Relaxed) as f64,
+			),
+			(
+				"indexer_scrape_limit_pending_left",
+				"Current limit of ranges being scraped below the indexed blocks",
+				self.limit_pending_left.load(Ordering::Relaxed) as f64,
+			),
+			(
+				"indexer_scrape_limit_pending_right",
+				"Current limit of ranges being scraped above the indexed blocks",
+				self.limit_pending_right.load(Ordering::Relaxed) as f64,
+			),
+			(
+				"indexer_scrape_range_size",
+				"Current size of scraped ranges",
+				self.limit_range_size.load(Ordering::Relaxed) as f64,
+			),
+			(
+				"indexer_scrape_limit_solved",
+				"Limit of scraped ranges waiting to be inserted",
+				self.limit_max_solved.load(Ordering::Relaxed) as f64,
+			),
+		]
+	}
+}
+
+/// Metrics of all `contexts` and of the API, in the Prometheus text format. Every indexer metric
+/// is labeled with its network.
+pub fn render(contexts: &[IndexerContext]) -> String {
+	let mut out = String::new();
+	let gauges: Vec<_> = contexts.iter().map(|context| context.metrics.gauges()).collect();
+	for i in 0..gauges.first().map_or(0, |gauges| gauges.len()) {
+		let (name, help, _) = gauges[0][i];
+		let _ = writeln!(out, "# HELP {} {}", name, help);
+		let _ = writeln!(out, "# TYPE {} gauge", name);
+		for (context, gauges) in contexts.iter().zip(&gauges) {
+			let _ =
+				writeln!(out, "{}{{network=\"{}\"}} {}", name, context.network.name(), gauges[i].2);
+		}
+	}
+
+	let endpoints: Vec<(&str, Vec<EndpointHealth>)> = contexts
+		.iter()
+		.map(|context| (context.network.name(), context.rpc_pool.health()))
+		.collect();
+	let _ = writeln!(out, "# HELP indexer_rpc_errors_total Failed RPC requests");
+	let _ = writeln!(out, "# TYPE indexer_rpc_errors_total counter");
+	for (network, endpoint) in endpoints.iter().flat_map(|(n, e)| e.iter().map(move |e| (n, e))) {
+		let _ = writeln!(
+			out,
+			"indexer_rpc_errors_total{{network=\"{}\",endpoint=\"{}\"}} {}",
+			network, endpoint.url, endpoint.failures
+		);
+	}
+	let _ = writeln!(
+		out,
+		"# HELP indexer_rpc_latency_seconds Moving average of the RPC request latency"
+	);
+	let _ = writeln!(out, "# TYPE indexer_rpc_latency_seconds gauge");
+	for (network, endpoint) in endpoints.iter().flat_map(|(n, e)| e.iter().map(move |e| (n, e))) {
+		if let Some(latency) = endpoint.latency {
+			let _ = writeln!(
+				out,
+				"indexer_rpc_latency_seconds{{network=\"{}\",endpoint=\"{}\"}} {}",
+				network,
+				endpoint.url,
+				latency.as_secs_f64()
+			);
+		}
+	}
+	let _ = writeln!(
+		out,
+		"# HELP indexer_rpc_backing_off Whether the endpoint is avoided after failures"
+	);
+	let _ = writeln!(out, "# TYPE indexer_rpc_backing_off gauge");
+	let now = Instant::now();
+	for (network, endpoint) in endpoints.iter().flat_map(|(n, e)| e.iter().map(move |e| (n, e))) {
+		let _ = writeln!(
+			out,
+			"indexer_rpc_backing_off{{network=\"{}\",endpoint=\"{}\"}} {}",
+			network,
+			endpoint.url,
+			endpoint.is_backing_off(now) as u8
+		);
+	}
+
+	let _ = writeln!(out, "# HELP indexer_db_insert_seconds Time to insert a block");
+	let _ = writeln!(out, "# TYPE indexer_db_insert_seconds histogram");
+	for context in contexts {
+		context.metrics.db_insert_seconds.lock().unwrap().render(
+			&mut out,
+			"indexer_db_insert_seconds",
+			&format!("network=\"{}\"", context.network.name()),
+		);
+	}
+
+	let _ = writeln!(out, "# HELP api_request_seconds Time to handle an API request");
+	let _ = writeln!(out, "# TYPE api_request_seconds histogram");
+	for (route, histogram) in API_REQUEST_SECONDS.lock().unwrap().iter() {
+		histogram.render(&mut out, "api_request_seconds", &format!("route=\"{}\"", route));
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Histogram;
+
+	#[test]
+	fn histogram_buckets_are_cumulative() {
+		let mut histogram = Histogram::default();
+		histogram.observe(0.003);
+		histogram.observe(0.3);
+		let mut out = String::new();
+		histogram.render(&mut out, "x", "route=\"/events\"");
+		assert!(out.contains("x_bucket{route=\"/events\",le=\"0.0025\"} 0\n"));
+		assert!(out.contains("x_bucket{route=\"/events\",le=\"0.005\"} 1\n"));
+		assert!(out.contains("x_bucket{route=\"/events\",le=\"1\"} 2\n"));
+		assert!(out.contains("x_bucket{route=\"/events\",le=\"+Inf\"} 2\n"));
+		assert!(out.contains("x_count{route=\"/events\"} 2\n"));
+	}
+}
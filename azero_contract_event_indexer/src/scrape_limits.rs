@@ -0,0 +1,215 @@
+use std::time::{Duration, Instant};
+
+// How often the limits are adjusted, based on the ranges finished in between.
+const ADJUST_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_RANGE_TIMEOUT_SECS: u64 = 60;
+
+/// How much the scraper fetches at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrapeLimits {
+	/// Ranges being scraped below the indexed blocks.
+	pub num_pending_left: usize,
+	/// Ranges being scraped above the indexed blocks.
+	pub num_pending_right: usize,
+	/// A range spans `range_size + 1` blocks.
+	pub range_size: u32,
+	/// No more ranges are scheduled while this many scraped ranges wait to be inserted.
+	pub max_solved: usize,
+}
+
+impl ScrapeLimits {
+	const INITIAL: ScrapeLimits = ScrapeLimits {
+		num_pending_left: 17,
+		num_pending_right: 13,
+		range_size: 6,
+		max_solved: 100,
+	};
+	const DEFAULT_CEILING: ScrapeLimits = ScrapeLimits {
+		num_pending_left: 64,
+		num_pending_right: 32,
+		range_size: 24,
+		max_solved: 400,
+	};
+
+	/// Ceilings set by `SCRAPE_MAX_PENDING_LEFT`, `SCRAPE_MAX_PENDING_RIGHT`,
+	/// `SCRAPE_MAX_RANGE_SIZE` and `SCRAPE_MAX_SOLVED`, defaults for the ones not set.
+	pub fn ceiling_from_env() -> Self {
+		let ceiling = Self::DEFAULT_CEILING;
+		ScrapeLimits {
+			num_pending_left: env_limit("SCRAPE_MAX_PENDING_LEFT", ceiling.num_pending_left),
+			num_pending_right: env_limit("SCRAPE_MAX_PENDING_RIGHT", ceiling.num_pending_right),
+			range_size: env_limit("SCRAPE_MAX_RANGE_SIZE", ceiling.range_size),
+			max_solved: env_limit("SCRAPE_MAX_SOLVED", ceiling.max_solved),
+		}
+	}
+}
+
+fn env_limit<T: std::str::FromStr + Ord + From<u8>>(name: &str, default: T) -> T {
+	match std::env::var(name) {
+		Ok(value) => value
+			.parse::<T>()
+			.unwrap_or_else(|_| panic!("{} must be a positive number", name))
+			.max(T::from(1)),
+		Err(_) => default,
+	}
+}
+
+/// Time after which a range that is still being scraped counts as failed, set by
+/// `SCRAPE_RANGE_TIMEOUT_SECS`.
+pub fn range_timeout_from_env() -> Duration {
+	Duration::from_secs(env_limit("SCRAPE_RANGE_TIMEOUT_SECS", DEFAULT_RANGE_TIMEOUT_SECS))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RangeOutcome {
+	Scraped(Duration),
+	Failed,
+	TimedOut,
+}
+
+/// Adjusts `ScrapeLimits` to how the node keeps up. Every `ADJUST_INTERVAL` the ranges finished
+/// in the meantime are looked at: after a timeout the number of pending ranges and the range size
+/// are halved, after an error only the number of pending ranges is. Ranges slower than a quarter
+/// of the timeout on average lower the number of pending ranges by one, otherwise both grow by
+/// one, up to the ceiling.
+pub struct AdaptiveLimits {
+	current: ScrapeLimits,
+	ceiling: ScrapeLimits,
+	slow_range: Duration,
+	window_start: Instant,
+	scraped: u32,
+	scraped_time: Duration,
+	failed: u32,
+	timed_out: u32,
+}
+
+impl AdaptiveLimits {
+	pub fn new(ceiling: ScrapeLimits, range_timeout: Duration, now: Instant) -> Self {
+		let initial = ScrapeLimits::INITIAL;
+		AdaptiveLimits {
+			current: ScrapeLimits {
+				num_pending_left: initial.num_pending_left.min(ceiling.num_pending_left),
+				num_pending_right: initial.num_pending_right.min(ceiling.num_pending_right),
+				range_size: initial.range_size.min(ceiling.range_size),
+				max_solved: ceiling.max_solved,
+			},
+			ceiling,
+			slow_range: range_timeout / 4,
+			window_start: now,
+			scraped: 0,
+			scraped_time: Duration::ZERO,
+			failed: 0,
+			timed_out: 0,
+		}
+	}
+
+	pub fn current(&self) -> ScrapeLimits {
+		self.current
+	}
+
+	pub fn record(&mut self, outcome: RangeOutcome) {
+		match outcome {
+			RangeOutcome::Scraped(elapsed) => {
+				self.scraped += 1;
+				self.scraped_time += elapsed;
+			},
+			RangeOutcome::Failed => self.failed += 1,
+			RangeOutcome::TimedOut => self.timed_out += 1,
+		}
+	}
+
+	/// Adjusts the limits if `ADJUST_INTERVAL` has passed. Returns whether they changed.
+	pub fn adjust(&mut self, now: Instant) -> bool {
+		if now.duration_since(self.window_start) < ADJUST_INTERVAL {
+			return false;
+		}
+		let previous = self.current;
+		let ceiling = self.ceiling;
+		let current = &mut self.current;
+		if self.timed_out > 0 {
+			current.num_pending_left = (current.num_pending_left / 2).max(1);
+			current.num_pending_right = (current.num_pending_right / 2).max(1);
+			current.range_size = (current.range_size / 2).max(1);
+		} else if self.failed > 0 {
+			current.num_pending_left = (current.num_pending_left / 2).max(1);
+			current.num_pending_right = (current.num_pending_right / 2).max(1);
+		} else if self.scraped > 0 && self.scraped_time / self.scraped > self.slow_range {
+			current.num_pending_left = (current.num_pending_left - 1).max(1);
+			current.num_pending_right = (current.num_pending_right - 1).max(1);
+		} else if self.scraped > 0 {
+			current.num_pending_left = (current.num_pending_left + 1).min(ceiling.num_pending_left);
+			current.num_pending_right =
+				(current.num_pending_right + 1).min(ceiling.num_pending_right);
+			current.range_size = (current.range_size + 1).min(ceiling.range_size);
+		}
+		self.window_start = now;
+		self.scraped = 0;
+		self.scraped_time = Duration::ZERO;
+		self.failed = 0;
+		self.timed_out = 0;
+		self.current != previous
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{AdaptiveLimits, RangeOutcome, ScrapeLimits, ADJUST_INTERVAL};
+	use std::time::{Duration, Instant};
+
+	fn limits(num_pending: usize, range_size: u32) -> ScrapeLimits {
+		ScrapeLimits {
+			num_pending_left: num_pending,
+			num_pending_right: num_pending,
+			range_size,
+			max_solved: 100,
+		}
+	}
+
+	#[test]
+	fn limits_grow_up_to_ceiling_and_back_off_on_failures() {
+		let mut now = Instant::now();
+		let mut adaptive = AdaptiveLimits::new(limits(14, 8), Duration::from_secs(60), now);
+		assert_eq!(adaptive.current(), ScrapeLimits { num_pending_left: 14, ..limits(13, 6) });
+
+		adaptive.record(RangeOutcome::Scraped(Duration::from_secs(1)));
+		assert!(!adaptive.adjust(now + ADJUST_INTERVAL / 2));
+		now += ADJUST_INTERVAL;
+		assert!(adaptive.adjust(now));
+		assert_eq!(adaptive.current(), limits(14, 7));
+
+		// Nothing finished, nothing to learn from.
+		now += ADJUST_INTERVAL;
+		assert!(!adaptive.adjust(now));
+
+		adaptive.record(RangeOutcome::Scraped(Duration::from_secs(1)));
+		now += ADJUST_INTERVAL;
+		adaptive.adjust(now);
+		adaptive.record(RangeOutcome::Scraped(Duration::from_secs(1)));
+		now += ADJUST_INTERVAL;
+		adaptive.adjust(now);
+		assert_eq!(adaptive.current(), limits(14, 8));
+
+		adaptive.record(RangeOutcome::Scraped(Duration::from_secs(1)));
+		adaptive.record(RangeOutcome::Failed);
+		now += ADJUST_INTERVAL;
+		adaptive.adjust(now);
+		assert_eq!(adaptive.current(), limits(7, 8));
+
+		adaptive.record(RangeOutcome::Scraped(Duration::from_secs(20)));
+		now += ADJUST_INTERVAL;
+		adaptive.adjust(now);
+		assert_eq!(adaptive.current(), limits(6, 8));
+
+		adaptive.record(RangeOutcome::TimedOut);
+		now += ADJUST_INTERVAL;
+		adaptive.adjust(now);
+		assert_eq!(adaptive.current(), limits(3, 4));
+
+		for _ in 0..5 {
+			adaptive.record(RangeOutcome::TimedOut);
+			now += ADJUST_INTERVAL;
+			adaptive.adjust(now);
+		}
+		assert_eq!(adaptive.current(), limits(1, 1));
+	}
+}
@@ -1,20 +1,49 @@
-use std::time::Instant;
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::Arc,
+	time::{Duration, Instant},
+};
 
-use azero_config::Config;
-use azero_universal::contract_events::{
-	backwards_compatible_into_contract_event, GenericContractEvent,
+use azero_config::{Block, Config};
+use azero_runtime_types::v_73 as azero;
+use azero_universal::{
+	contract_calls::{contract_calls, GenericContractCall},
+	contract_events::{self, into_contract_event, GenericContractEvent},
+	contract_info::backwards_compatible_get_contract_info_at,
+	runtime::{block_events, Runtime},
+	RpcPool,
 };
+use codec::Decode;
 use futures::channel::oneshot;
-use subxt::backend::legacy::LegacyRpcMethods;
+use serde::{Deserialize, Serialize};
+use subxt::{
+	backend::legacy::LegacyRpcMethods,
+	config::Hasher,
+	events::{Events, Phase},
+	utils::MultiAddress,
+};
 
-use crate::{event_db::Event, get_finalized_block_num, BlockHash, Client, RpcClient};
+use crate::{
+	block_source::BlockSource,
+	call_decoder::CallDecoder,
+	config::IndexerContext,
+	contract_filter::ContractFilter,
+	event_db::{
+		BlockDetails, CallInput, CalledDetails, CodeDetails, CodeRemovedDetails, CodeStoredDetails,
+		CodeUpdatedDetails, EmittedDetails, Event, EventType, Extrinsic, InstantiatedDetails,
+		Origin, TerminatedDetails,
+	},
+	live::NewEvents,
+	scrape_limits::{range_timeout_from_env, AdaptiveLimits, RangeOutcome, ScrapeLimits},
+	AccountId, BlockHash, Client, RpcClient,
+};
 
 use super::event_db;
 
 struct PendingRange {
 	num_from: u32,
 	num_to: u32,
-	result: oneshot::Receiver<BlockRangeResult>,
+	result: oneshot::Receiver<Result<BlockRangeResult, RangeOutcome>>,
 }
 
 struct SolvedRange {
@@ -23,15 +52,6 @@ struct SolvedRange {
 	result: BlockRangeResult,
 }
 
-// KInd of arbitrary choice for Aleph Zero mainnet. It contains all of Common and much more actually
-// (events since December 2023).
-const FIRST_BLOCK_OF_INTEREST: u32 = 65000000;
-
-const MAX_SOLVED: usize = 100;
-const NUM_PENDING_LEFT: usize = 17;
-const NUM_PENDING_RIGHT: usize = 13;
-const RANGE_SIZE: u32 = 6;
-
 pub async fn get_hash_from_number(
 	client: &RpcClient,
 	num: u32,
@@ -42,99 +62,374 @@ pub async fn get_hash_from_number(
 	Ok(block_hash)
 }
 
+/// Everything the indexer stores about a block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrapedBlock {
+	pub block: BlockDetails,
+	pub extrinsics: Vec<Extrinsic>,
+	pub events: Vec<Event>,
+}
+
 struct BlockRangeResult {
-	res: Vec<(u32, Vec<Event>)>,
+	res: Vec<ScrapedBlock>,
+	elapsed: Duration,
+}
+
+fn into_event(
+	contract_event: GenericContractEvent,
+	block_num: u32,
+	event_index: u32,
+	extrinsic_index: u32,
+) -> Event {
+	use GenericContractEvent::*;
+	// Code events are not tied to a contract.
+	let (contract, event_type) = match contract_event {
+		ContractEmitted { contract, data, topics } =>
+			(Some(contract), EventType::Emitted(EmittedDetails { data, topics })),
+		Called { caller, contract } => {
+			let caller = match caller {
+				contract_events::Origin::Signed(c) => Origin::Signed(c),
+				contract_events::Origin::Root => Origin::Root,
+			};
+			(Some(contract), EventType::Called(CalledDetails { caller, input: None }))
+		},
+		Instantiated { deployer, contract } => (
+			Some(contract),
+			EventType::Instantiated(InstantiatedDetails { deployer, code_hash: None }),
+		),
+		Terminated { contract, beneficiary } =>
+			(Some(contract), EventType::Terminated(TerminatedDetails { beneficiary })),
+		CodeStored { code_hash, uploader } =>
+			(None, EventType::CodeStored(CodeStoredDetails { code_hash, uploader })),
+		CodeRemoved { code_hash, remover } =>
+			(None, EventType::CodeRemoved(CodeRemovedDetails { code_hash, remover })),
+		ContractCodeUpdated { contract, new_code_hash, old_code_hash } => (
+			Some(contract),
+			EventType::ContractCodeUpdated(CodeUpdatedDetails { old_code_hash, new_code_hash }),
+		),
+		DelegateCalled { contract, code_hash } =>
+			(Some(contract), EventType::DelegateCalled(CodeDetails { code_hash })),
+	};
+	Event { contract_account_id: contract, block_num, event_index, extrinsic_index, event_type }
+}
+
+// Pallet and call of an extrinsic that does not decode.
+const UNKNOWN_CALL: &str = "Unknown";
+
+fn outcome(
+	outcomes: &BTreeMap<u32, (bool, Option<u128>)>,
+	extrinsic_index: u32,
+	num: u32,
+) -> (bool, Option<u128>) {
+	match outcomes.get(&extrinsic_index) {
+		Some(outcome) => *outcome,
+		None => {
+			log::error!("Outcome not found for extrinsic {} at block {}", extrinsic_index, num);
+			(false, None)
+		},
+	}
+}
+
+// The extrinsics of block `hash` from `first_index` on, read undecoded, with an unknown pallet and
+// call and no signer.
+async fn undecoded_extrinsics(
+	rpc_client: &RpcClient,
+	hash: BlockHash,
+	num: u32,
+	first_index: u32,
+	outcomes: &BTreeMap<u32, (bool, Option<u128>)>,
+) -> anyhow::Result<Vec<Extrinsic>> {
+	let rpc_methods = LegacyRpcMethods::<Config>::new(rpc_client.clone());
+	let block = rpc_methods
+		.chain_get_block(Some(hash))
+		.await?
+		.ok_or(anyhow::anyhow!("Block not found"))?;
+	let extrinsics = block.block.extrinsics.iter().zip(0..).skip(first_index as usize);
+	Ok(extrinsics
+		.map(|(bytes, extrinsic_index)| {
+			let (success, fee) = outcome(outcomes, extrinsic_index, num);
+			Extrinsic {
+				block_num: num,
+				extrinsic_index,
+				hash: <Config as subxt::Config>::Hasher::hash(&bytes.0),
+				signer: None,
+				pallet: UNKNOWN_CALL.to_string(),
+				call: UNKNOWN_CALL.to_string(),
+				success,
+				fee,
+			}
+		})
+		.collect())
+}
+
+// The extrinsics of the block, and the contract calls they dispatched by extrinsic index. `block`
+// must have been fetched with the client of `runtime`, see `Runtime::client`. Extrinsics that do
+// not decode are stored with an unknown pallet and call rather than failing the block.
+async fn get_extrinsics(
+	block: &Block,
+	rpc_client: &RpcClient,
+	runtime: &Runtime,
+	events: &Events<Config>,
+	num: u32,
+) -> anyhow::Result<(Vec<Extrinsic>, BTreeMap<u32, Vec<GenericContractCall>>)> {
+	// Whether the extrinsic succeeded and the fee it paid, by extrinsic index.
+	let mut outcomes: BTreeMap<u32, (bool, Option<u128>)> = BTreeMap::new();
+	for event in events.iter() {
+		let event = match event {
+			Ok(event) => event,
+			Err(e) => {
+				log::error!("Error decoding event: {}", e);
+				continue;
+			},
+		};
+		let extrinsic_index = match event.phase() {
+			Phase::ApplyExtrinsic(i) => i,
+			_ => continue,
+		};
+		let outcome = outcomes.entry(extrinsic_index).or_insert((false, None));
+		match (event.pallet_name(), event.variant_name()) {
+			("System", "ExtrinsicSuccess") => outcome.0 = true,
+			("System", "ExtrinsicFailed") => outcome.0 = false,
+			_ => {},
+		}
+		if let Ok(Some(fee_paid)) =
+			event.as_event::<azero::transaction_payment::events::TransactionFeePaid>()
+		{
+			outcome.1 = Some(fee_paid.actual_fee);
+		}
+	}
+
+	let mut res = Vec::new();
+	let mut calls = BTreeMap::new();
+	for extrinsic in block.extrinsics().await?.iter() {
+		let extrinsic = match extrinsic {
+			Ok(extrinsic) => extrinsic,
+			Err(e) => {
+				// Decoding stops at the first extrinsic that fails.
+				let first_index = res.len() as u32;
+				log::error!("Error decoding extrinsic {} at block {}: {}", first_index, num, e);
+				let undecoded =
+					undecoded_extrinsics(rpc_client, block.hash(), num, first_index, &outcomes);
+				res.extend(undecoded.await?);
+				break;
+			},
+		};
+		let extrinsic_index = extrinsic.index();
+		let contract_calls = contract_calls(runtime, &extrinsic);
+		if !contract_calls.is_empty() {
+			calls.insert(extrinsic_index, contract_calls);
+		}
+		let signer = extrinsic.address_bytes().and_then(|mut bytes| {
+			match MultiAddress::<AccountId, u32>::decode(&mut bytes) {
+				Ok(MultiAddress::Id(account)) => Some(account),
+				_ => None,
+			}
+		});
+		let (success, fee) = outcome(&outcomes, extrinsic_index, num);
+		let (pallet, call) = match (extrinsic.pallet_name(), extrinsic.variant_name()) {
+			(Ok(pallet), Ok(call)) => (pallet.to_string(), call.to_string()),
+			(Err(e), _) | (_, Err(e)) => {
+				log::error!(
+					"Unknown call of extrinsic {} at block {}: {}",
+					extrinsic_index,
+					num,
+					e
+				);
+				(UNKNOWN_CALL.to_string(), UNKNOWN_CALL.to_string())
+			},
+		};
+		res.push(Extrinsic {
+			block_num: num,
+			extrinsic_index,
+			hash: <Config as subxt::Config>::Hasher::hash(extrinsic.bytes()),
+			signer,
+			pallet,
+			call,
+			success,
+			fee,
+		});
+	}
+	Ok((res, calls))
+}
+
+// Gives the `Called` events of contracts called by the extrinsic itself the input they were called
+// with. The other ones are calls made by contracts, their caller is a contract called or
+// instantiated in the same extrinsic. Calls of a contract are matched with its events in order,
+// unless a call failed without failing the extrinsic, as then it is unclear which event is which.
+fn attach_call_inputs(events: &mut [Event], calls: &BTreeMap<u32, Vec<GenericContractCall>>) {
+	for (extrinsic_index, calls) in calls {
+		let contracts: BTreeSet<AccountId> = events
+			.iter()
+			.filter(|event| {
+				event.extrinsic_index == *extrinsic_index &&
+					matches!(event.event_type, EventType::Called(_) | EventType::Instantiated(_))
+			})
+			.filter_map(|event| event.contract_account_id.clone())
+			.collect();
+		let mut direct: BTreeMap<AccountId, Vec<usize>> = BTreeMap::new();
+		for (i, event) in events.iter().enumerate() {
+			if event.extrinsic_index != *extrinsic_index {
+				continue;
+			}
+			if let (Some(contract), EventType::Called(CalledDetails { caller, .. })) =
+				(&event.contract_account_id, &event.event_type)
+			{
+				if !matches!(caller, Origin::Signed(caller) if contracts.contains(caller)) {
+					direct.entry(contract.clone()).or_default().push(i);
+				}
+			}
+		}
+		for (contract, indices) in direct {
+			let inputs: Vec<&Vec<u8>> = calls
+				.iter()
+				.filter(|call| call.contract == contract)
+				.map(|call| &call.data)
+				.collect();
+			if inputs.len() != indices.len() {
+				continue;
+			}
+			for (i, data) in indices.into_iter().zip(inputs) {
+				if let EventType::Called(details) = &mut events[i].event_type {
+					details.input = CallInput::new(data.clone());
+				}
+			}
+		}
+	}
+}
+
+async fn fetch_filtered_blocks(
+	source: &BlockSource,
+	contract_filter: &ContractFilter,
+	call_decoder: &CallDecoder,
+	num_start: u32,
+	num_end: u32,
+) -> anyhow::Result<Vec<ScrapedBlock>> {
+	let mut res = Vec::new();
+	for block in source.fetch_blocks(num_start, num_end).await? {
+		let block = contract_filter.apply(block).await?;
+		res.push(call_decoder.apply(block).await?);
+	}
+	Ok(res)
 }
 
 async fn scrape_blocks(
+	source: BlockSource,
+	contract_filter: Arc<ContractFilter>,
+	call_decoder: Arc<CallDecoder>,
 	num_start: u32,
 	num_end: u32,
-	tx: oneshot::Sender<BlockRangeResult>,
-) -> anyhow::Result<()> {
-	let rpc_client = match super::get_rpc_client().await {
-		Ok(client) => client,
-		Err(e) => {
-			log::error!("Error getting client: {}", e);
-			return Err(anyhow::anyhow!("Error getting client: {}", e));
+	timeout: Duration,
+	tx: oneshot::Sender<Result<BlockRangeResult, RangeOutcome>>,
+) {
+	let start = Instant::now();
+	let fetch = fetch_filtered_blocks(&source, &contract_filter, &call_decoder, num_start, num_end);
+	let result = match tokio::time::timeout(timeout, fetch).await {
+		Ok(Ok(res)) => Ok(BlockRangeResult { res, elapsed: start.elapsed() }),
+		Ok(Err(_)) => Err(RangeOutcome::Failed),
+		Err(_) => {
+			log::error!("Scraping blocks {}-{} timed out", num_start, num_end);
+			Err(RangeOutcome::TimedOut)
 		},
 	};
+	let _ = tx.send(result);
+}
+
+pub(crate) async fn fetch_blocks(
+	rpc_pool: &RpcPool,
+	num_start: u32,
+	num_end: u32,
+) -> anyhow::Result<Vec<ScrapedBlock>> {
+	let result = rpc_pool
+		.run(|connection| async move {
+			fetch_blocks_from(&connection.rpc_client, &connection.client, num_start, num_end).await
+		})
+		.await;
+	if let Err(e) = &result {
+		log::error!("Error fetching blocks {}-{}: {}", num_start, num_end, e);
+	}
+	result
+}
+
+// The code a contract was instantiated with is not in its event, read it from the state after the
+// block. Contracts terminated in the same block are left without one.
+async fn attach_instantiated_code_hashes(
+	client: &Client,
+	block_hash: BlockHash,
+	events: &mut [Event],
+) -> anyhow::Result<()> {
+	for event in events {
+		if let (Some(contract), EventType::Instantiated(details)) =
+			(&event.contract_account_id, &mut event.event_type)
+		{
+			details.code_hash =
+				backwards_compatible_get_contract_info_at(client, contract, block_hash)
+					.await?
+					.map(|info| info.code_hash);
+		}
+	}
+	Ok(())
+}
 
+async fn fetch_blocks_from(
+	rpc_client: &RpcClient,
+	client: &Client,
+	num_start: u32,
+	num_end: u32,
+) -> anyhow::Result<Vec<ScrapedBlock>> {
 	let mut res = Vec::new();
 	let nums = num_start..=num_end;
 	let mut hashes = Vec::new();
 	for num in nums.clone() {
-		let block_hash = get_hash_from_number(&rpc_client, num)
+		let block_hash = get_hash_from_number(rpc_client, num)
 			.await?
 			.ok_or(anyhow::anyhow!("Block not found"))?;
 		hashes.push(block_hash);
 	}
 
-	let client: Client = Client::from_rpc_client(rpc_client).await?;
 	for (hash, num) in hashes.iter().zip(nums) {
-		let block = client.blocks().at(*hash).await?;
-		let events = match block.events().await {
-			Ok(events) => events,
+		let (runtime, events) = match block_events(client, *hash).await {
+			Ok(block_events) => block_events,
 			Err(e) => {
 				log::error!("Error getting events from block: {}", e);
-				return Err(anyhow::anyhow!("Error getting events from block: {}", e));
+				return Err(anyhow::Error::from(e).context("Error getting events from block"));
 			},
 		};
+		// Fetched with the metadata of its runtime, so its extrinsics decode like its events.
+		let block = runtime.client(client, rpc_client)?.blocks().at(*hash).await?;
+		// The genesis block has no timestamp.
+		let timestamp =
+			client.storage().at(*hash).fetch(&azero::storage().timestamp().now()).await?;
+		let block_details = BlockDetails {
+			block_num: num,
+			hash: *hash,
+			parent_hash: block.header().parent_hash,
+			timestamp,
+		};
+		let (extrinsics, calls) =
+			get_extrinsics(&block, rpc_client, &runtime, &events, num).await?;
 		let mut contract_events = Vec::new();
 
 		for event in events.iter() {
 			match event {
 				Ok(event) => {
 					let extrinsic_index = match event.phase() {
-						subxt::events::Phase::ApplyExtrinsic(i) => Some(i),
+						Phase::ApplyExtrinsic(i) => Some(i),
 						_ => None,
 					};
 					let event_index = event.index();
-					if let Some(e) = backwards_compatible_into_contract_event(event) {
-						use GenericContractEvent::*;
-						match e {
-							ContractEmitted { contract, data } => {
-								let extrinsic_index =
-									match extrinsic_index {
-										Some(i) => i as u32,
-										None => {
-											log::error!("Extrinsic index not found for event {} at block {}", event_index, num);
-											continue;
-										},
-									};
-								contract_events.push(Event::new_emitted(
-									contract,
-									num,
+					if let Some(e) = into_contract_event(&runtime, &event)? {
+						let extrinsic_index = match extrinsic_index {
+							Some(i) => i,
+							None => {
+								log::error!(
+									"Extrinsic index not found for event {} at block {}",
 									event_index,
-									extrinsic_index,
-									data,
-								));
+									num
+								);
+								continue;
 							},
-							Called { caller, contract } => {
-								let extrinsic_index =
-									match extrinsic_index {
-										Some(i) => i as u32,
-										None => {
-											log::error!("Extrinsic index not found for event {} at block {}", event_index, num);
-											continue;
-										},
-									};
-								let caller = match caller {
-									azero_universal::contract_events::Origin::Signed(c) => c,
-									azero_universal::contract_events::Origin::Root => {
-										log::error!("Root caller not supported");
-										continue;
-									},
-								};
-								contract_events.push(Event::new_called(
-									contract,
-									num,
-									event_index,
-									extrinsic_index,
-									caller,
-								));
-							},
-							_ => {},
-						}
+						};
+						contract_events.push(into_event(e, num, event_index, extrinsic_index));
 					}
 				},
 				Err(e) => {
@@ -142,11 +437,11 @@ async fn scrape_blocks(
 				},
 			}
 		}
-		res.push((num, contract_events));
+		attach_call_inputs(&mut contract_events, &calls);
+		attach_instantiated_code_hashes(client, *hash, &mut contract_events).await?;
+		res.push(ScrapedBlock { block: block_details, extrinsics, events: contract_events });
 	}
-
-	let _ = tx.send(BlockRangeResult { res });
-	Ok(())
+	Ok(res)
 }
 
 fn first_not_contained_after(bound: i32, segments: &Vec<(i32, i32)>) -> (i32, i32) {
@@ -166,12 +461,13 @@ fn schedule_right(
 	indexed_to: u32,
 	finalized_num: u32,
 	pending_ranges: &Vec<(u32, u32)>,
+	range_size: u32,
 ) -> Option<(u32, u32)> {
 	let mut ranges_unsigned: Vec<(i32, i32)> =
 		pending_ranges.iter().map(|(a, b)| (*a as i32, *b as i32)).collect();
 	ranges_unsigned.sort();
 	let (a, b) = first_not_contained_after(indexed_to as i32, &ranges_unsigned);
-	let b = std::cmp::min(b, a + (RANGE_SIZE as i32));
+	let b = std::cmp::min(b, a + (range_size as i32));
 	let b = std::cmp::min(b, finalized_num as i32);
 	if a > b {
 		None
@@ -184,12 +480,13 @@ fn schedule_left(
 	indexed_from: u32,
 	minimum_num: u32,
 	pending_ranges: &Vec<(u32, u32)>,
+	range_size: u32,
 ) -> Option<(u32, u32)> {
 	let mut ranges_unsigned: Vec<(i32, i32)> =
 		pending_ranges.iter().map(|(a, b)| (-(*b as i32), -(*a as i32))).collect();
 	ranges_unsigned.sort();
 	let (a, b) = first_not_contained_after(-(indexed_from as i32), &ranges_unsigned);
-	let b = std::cmp::min(b, a + (RANGE_SIZE as i32));
+	let b = std::cmp::min(b, a + (range_size as i32));
 	let b = std::cmp::min(b, -(minimum_num as i32));
 	let (a, b) = (-b, -a);
 	if a > b {
@@ -199,24 +496,39 @@ fn schedule_left(
 	}
 }
 
-pub async fn scrape() -> ! {
-	let (mut indexed_from, mut indexed_to) = event_db::get_bounds().unwrap();
-	log::info!("Indexed from: {}, to: {}", indexed_from, indexed_to);
+pub async fn scrape(context: IndexerContext, new_events: NewEvents, source: BlockSource) -> ! {
+	let first_block_num = u32::max(context.first_block, source.first_block_num().unwrap());
+	let (mut indexed_from, mut indexed_to) = event_db::get_bounds(&context.database_file).unwrap();
+	let mut conn = event_db::get_connection_with_backoff(&context.database_file);
+	let network = context.network.name();
+	log::info!("[{}] Indexed from: {}, to: {}", network, indexed_from, indexed_to);
 	let mut prev_checkpoint = Instant::now();
 	let mut prev_len = indexed_to + 1 - indexed_from;
 	let mut pending: Vec<PendingRange> = Vec::new();
 	let mut solved: Vec<SolvedRange> = Vec::new();
 	let mut finalized_num = indexed_to;
+	let range_timeout = range_timeout_from_env();
+	let mut adaptive_limits =
+		AdaptiveLimits::new(ScrapeLimits::ceiling_from_env(), range_timeout, Instant::now());
+	context.metrics.set_bounds(indexed_from, indexed_to);
+	context.metrics.set_scrape_limits(&adaptive_limits.current());
 	loop {
 		let checkpoint = Instant::now();
 		let elapsed = checkpoint.duration_since(prev_checkpoint).as_secs_f64();
 		let len = indexed_to + 1 - indexed_from;
 		if elapsed > 15.0 {
 			let rate = (len - prev_len) as f64 / elapsed;
-			log::info!("Indexed from: {}, to: {}, rate: {:.3}/s", indexed_from, indexed_to, rate);
+			log::info!(
+				"[{}] Indexed from: {}, to: {}, rate: {:.3}/s",
+				network,
+				indexed_from,
+				indexed_to,
+				rate
+			);
+			context.metrics.set_blocks_per_second(rate);
 			prev_checkpoint = checkpoint;
 			prev_len = len;
-			finalized_num = match get_finalized_block_num().await {
+			finalized_num = match source.finalized_block_num().await {
 				Ok(num) => num,
 				Err(e) => {
 					log::error!("Error getting finalized block number: {}", e);
@@ -224,25 +536,45 @@ pub async fn scrape() -> ! {
 					continue;
 				},
 			};
-			log::info!("Finalized: {}", finalized_num);
+			log::info!("[{}] Finalized: {}", network, finalized_num);
+			context.metrics.set_finalized(finalized_num);
+		}
+
+		if adaptive_limits.adjust(Instant::now()) {
+			log::info!("[{}] Scrape limits: {:?}", network, adaptive_limits.current());
+			context.metrics.set_scrape_limits(&adaptive_limits.current());
 		}
+		let limits = adaptive_limits.current();
 
 		loop {
 			let mut scheduled = false;
 			{
 				let num_pending_right = pending.iter().filter(|p| p.num_from > indexed_to).count();
-				if num_pending_right < NUM_PENDING_RIGHT && solved.len() < MAX_SOLVED {
+				if num_pending_right < limits.num_pending_right && solved.len() < limits.max_solved
+				{
 					let all_intervals = pending
 						.iter()
 						.map(|p| (p.num_from, p.num_to))
 						.chain(solved.iter().map(|p| (p.num_from, p.num_to)));
 					let segments_right: Vec<(u32, u32)> =
 						all_intervals.clone().filter(|(a, _b)| *a > indexed_to).collect();
-					if let Some((a, b)) = schedule_right(indexed_to, finalized_num, &segments_right)
-					{
+					if let Some((a, b)) = schedule_right(
+						indexed_to,
+						finalized_num,
+						&segments_right,
+						limits.range_size,
+					) {
 						let (tx, rx) = oneshot::channel();
 						pending.push(PendingRange { num_from: a, num_to: b, result: rx });
-						tokio::spawn(scrape_blocks(a, b, tx));
+						tokio::spawn(scrape_blocks(
+							source.clone(),
+							context.contract_filter.clone(),
+							context.call_decoder.clone(),
+							a,
+							b,
+							range_timeout,
+							tx,
+						));
 						scheduled = true;
 					}
 				}
@@ -251,7 +583,7 @@ pub async fn scrape() -> ! {
 			{
 				let num_pending_left = pending.iter().filter(|p| p.num_to < indexed_from).count();
 
-				if num_pending_left < NUM_PENDING_LEFT && solved.len() < MAX_SOLVED {
+				if num_pending_left < limits.num_pending_left && solved.len() < limits.max_solved {
 					let all_intervals = pending
 						.iter()
 						.map(|p| (p.num_from, p.num_to))
@@ -259,13 +591,26 @@ pub async fn scrape() -> ! {
 
 					let segments_left: Vec<(u32, u32)> =
 						all_intervals.filter(|(_a, b)| *b < indexed_from).collect();
-					if num_pending_left < NUM_PENDING_LEFT && solved.len() < MAX_SOLVED {
-						if let Some((a, b)) =
-							schedule_left(indexed_from, FIRST_BLOCK_OF_INTEREST, &segments_left)
-						{
+					if num_pending_left < limits.num_pending_left &&
+						solved.len() < limits.max_solved
+					{
+						if let Some((a, b)) = schedule_left(
+							indexed_from,
+							first_block_num,
+							&segments_left,
+							limits.range_size,
+						) {
 							let (tx, rx) = oneshot::channel();
 							pending.push(PendingRange { num_from: a, num_to: b, result: rx });
-							tokio::spawn(scrape_blocks(a, b, tx));
+							tokio::spawn(scrape_blocks(
+								source.clone(),
+								context.contract_filter.clone(),
+								context.call_decoder.clone(),
+								a,
+								b,
+								range_timeout,
+								tx,
+							));
 							scheduled = true;
 						}
 					}
@@ -279,10 +624,15 @@ pub async fn scrape() -> ! {
 
 		pending.retain_mut(|p| match p.result.try_recv() {
 			Ok(None) => true,
-			Ok(Some(r)) => {
+			Ok(Some(Ok(r))) => {
+				adaptive_limits.record(RangeOutcome::Scraped(r.elapsed));
 				solved.push(SolvedRange { num_from: p.num_from, num_to: p.num_to, result: r });
 				false
 			},
+			Ok(Some(Err(outcome))) => {
+				adaptive_limits.record(outcome);
+				false
+			},
 			Err(e) => {
 				log::error!("Error getting result: {}", e);
 				false
@@ -303,10 +653,19 @@ pub async fn scrape() -> ! {
 					res.reverse();
 					res
 				};
-				for (num, events) in to_process {
+				for ScrapedBlock { block, extrinsics, events } in to_process {
+					let num = block.block_num;
 					//log::info!("indexed_from {}, indexed_to {}, num {}", indexed_from,
 					// indexed_to, num);
-					event_db::insert_events_for_block(events, num).unwrap();
+					let events_to_send = if num > indexed_to { Some(events.clone()) } else { None };
+					let insert_start = Instant::now();
+					event_db::insert_events_for_block(events, extrinsics, &block, &mut conn)
+						.unwrap();
+					context.metrics.observe_db_insert(insert_start.elapsed());
+					if let Some(events) = events_to_send {
+						// Fails only if there are no subscribers.
+						let _ = new_events.send(events);
+					}
 					if num > indexed_to {
 						assert!(num == indexed_to + 1);
 						indexed_to = num;
@@ -314,6 +673,7 @@ pub async fn scrape() -> ! {
 						assert!(num == indexed_from - 1);
 						indexed_from = num;
 					}
+					context.metrics.set_bounds(indexed_from, indexed_to);
 				}
 				cnt += 1;
 			} else {
@@ -323,8 +683,181 @@ pub async fn scrape() -> ! {
 				break;
 			}
 		}
+		context.metrics.set_ranges(pending.len(), solved.len());
 		if cnt == 0 {
 			tokio::time::sleep(std::time::Duration::from_millis(2)).await;
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{attach_call_inputs, into_event, schedule_left, schedule_right};
+	use crate::{
+		event_db::{
+			CallInput, CodeDetails, CodeRemovedDetails, CodeStoredDetails, CodeUpdatedDetails,
+			Event, EventType, InstantiatedDetails, Origin, TerminatedDetails,
+		},
+		AccountId,
+	};
+	use azero_universal::{
+		contract_calls::GenericContractCall, contract_events::GenericContractEvent,
+	};
+	use std::collections::BTreeMap;
+	use subxt::utils::H256;
+
+	#[test]
+	fn schedule_right_skips_pending_ranges_and_stops_at_finalized() {
+		assert_eq!(schedule_right(100, 200, &vec![(101, 106)], 6), Some((107, 113)));
+		assert_eq!(schedule_right(100, 110, &vec![(101, 106)], 6), Some((107, 110)));
+		assert_eq!(schedule_right(100, 106, &vec![(101, 106)], 6), None);
+		assert_eq!(schedule_right(100, 200, &vec![(101, 106)], 2), Some((107, 109)));
+	}
+
+	#[test]
+	fn schedule_left_skips_pending_ranges_and_stops_at_minimum() {
+		assert_eq!(schedule_left(100, 50, &vec![(94, 99)], 6), Some((87, 93)));
+		assert_eq!(schedule_left(100, 90, &vec![(94, 99)], 6), Some((90, 93)));
+		assert_eq!(schedule_left(100, 94, &vec![(94, 99)], 6), None);
+		assert_eq!(schedule_left(100, 50, &vec![(94, 99)], 2), Some((91, 93)));
+	}
+
+	fn called(contract: u8, caller: u8, event_index: u32) -> Event {
+		Event::new_called(
+			AccountId::from([contract; 32]),
+			7,
+			event_index,
+			1,
+			Origin::Signed(AccountId::from([caller; 32])),
+		)
+	}
+
+	fn call(contract: u8, data: Vec<u8>) -> GenericContractCall {
+		GenericContractCall { contract: AccountId::from([contract; 32]), value: 0, data }
+	}
+
+	fn input(event: &Event) -> Option<CallInput> {
+		match &event.event_type {
+			EventType::Called(details) => details.input.clone(),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn call_inputs_go_to_calls_made_by_the_extrinsic() {
+		// Account 9 calls contract 1 twice in a batch, contract 1 calls contract 2 the first time
+		// and deploys contract 3, which calls contract 2, the second time.
+		let mut events = vec![
+			called(2, 1, 0),
+			called(1, 9, 1),
+			Event::new(
+				AccountId::from([3u8; 32]),
+				7,
+				2,
+				1,
+				EventType::Instantiated(InstantiatedDetails {
+					deployer: AccountId::from([1u8; 32]),
+					code_hash: None,
+				}),
+			),
+			called(2, 3, 3),
+			called(1, 9, 4),
+		];
+		let calls =
+			BTreeMap::from([(1, vec![call(1, vec![1, 1, 1, 1]), call(1, vec![2, 2, 2, 2, 2])])]);
+		attach_call_inputs(&mut events, &calls);
+		let inputs: Vec<Option<Vec<u8>>> =
+			events.iter().map(|event| input(event).map(|input| input.data)).collect();
+		assert_eq!(
+			inputs,
+			vec![None, Some(vec![1, 1, 1, 1]), None, None, Some(vec![2, 2, 2, 2, 2])]
+		);
+		assert_eq!(input(&events[4]).unwrap().selector, [2, 2, 2, 2]);
+
+		// One of the calls failed, so which event is which is unknown.
+		let mut events = vec![called(1, 9, 0)];
+		attach_call_inputs(&mut events, &calls);
+		assert_eq!(input(&events[0]), None);
+	}
+
+	#[test]
+	fn lifecycle_events_map_to_their_contract_or_to_none() {
+		let (contract, account) = (AccountId::from([1u8; 32]), AccountId::from([2u8; 32]));
+		let (code, other_code) = (H256([8u8; 32]), H256([9u8; 32]));
+		let cases = [
+			(
+				GenericContractEvent::Instantiated {
+					deployer: account.clone(),
+					contract: contract.clone(),
+				},
+				Some(contract.clone()),
+				EventType::Instantiated(InstantiatedDetails {
+					deployer: account.clone(),
+					code_hash: None,
+				}),
+			),
+			(
+				GenericContractEvent::Terminated {
+					contract: contract.clone(),
+					beneficiary: account.clone(),
+				},
+				Some(contract.clone()),
+				EventType::Terminated(TerminatedDetails { beneficiary: account.clone() }),
+			),
+			(
+				GenericContractEvent::CodeStored {
+					code_hash: code,
+					uploader: Some(account.clone()),
+				},
+				None,
+				EventType::CodeStored(CodeStoredDetails {
+					code_hash: code,
+					uploader: Some(account.clone()),
+				}),
+			),
+			(
+				GenericContractEvent::CodeRemoved {
+					code_hash: code,
+					remover: Some(account.clone()),
+				},
+				None,
+				EventType::CodeRemoved(CodeRemovedDetails {
+					code_hash: code,
+					remover: Some(account.clone()),
+				}),
+			),
+			(
+				GenericContractEvent::ContractCodeUpdated {
+					contract: contract.clone(),
+					new_code_hash: other_code,
+					old_code_hash: code,
+				},
+				Some(contract.clone()),
+				EventType::ContractCodeUpdated(CodeUpdatedDetails {
+					old_code_hash: code,
+					new_code_hash: other_code,
+				}),
+			),
+			(
+				GenericContractEvent::DelegateCalled {
+					contract: contract.clone(),
+					code_hash: code,
+				},
+				Some(contract.clone()),
+				EventType::DelegateCalled(CodeDetails { code_hash: code }),
+			),
+		];
+		for (contract_event, contract_account_id, event_type) in cases {
+			assert_eq!(
+				into_event(contract_event, 7, 3, 1),
+				Event {
+					contract_account_id,
+					block_num: 7,
+					event_index: 3,
+					extrinsic_index: 1,
+					event_type
+				}
+			);
+		}
+	}
+}
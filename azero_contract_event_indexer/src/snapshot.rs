@@ -0,0 +1,202 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use rusqlite::{backup::Backup, Connection};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{config::Network, event_db};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+	pub dir: String,
+	/// Snapshots are only taken on demand if not set.
+	pub interval_secs: Option<u64>,
+	/// Older snapshots of the network are deleted.
+	pub keep: usize,
+}
+
+impl Default for SnapshotConfig {
+	fn default() -> Self {
+		Self { dir: "db/snapshots".to_string(), interval_secs: None, keep: 3 }
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+	#[error("Another snapshot is being taken")]
+	Busy,
+	#[error("Database error: {0}")]
+	DatabaseError(#[from] rusqlite::Error),
+	#[error("IO error: {0}")]
+	IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SnapshotInfo {
+	pub file: String,
+	/// Bounds stored in the snapshot.
+	pub indexed_from: u32,
+	pub indexed_to: u32,
+}
+
+/// Point-in-time copies of the database of one network, taken with the SQLite online backup API
+/// while the scraper keeps writing.
+pub struct Snapshots {
+	config: SnapshotConfig,
+	network: Network,
+	database_file: String,
+	taking: Mutex<()>,
+}
+
+impl Snapshots {
+	pub fn new(config: SnapshotConfig, network: Network, database_file: String) -> Self {
+		Self { config, network, database_file, taking: Mutex::new(()) }
+	}
+
+	pub fn interval(&self) -> Option<Duration> {
+		self.config.interval_secs.map(Duration::from_secs)
+	}
+
+	/// Takes a snapshot into `<dir>/<network>_<time>_<indexed_from>_<indexed_to>.db`, then deletes
+	/// all but the newest `keep` ones. Blocks until done, fails with `SnapshotError::Busy` if a
+	/// snapshot is already being taken.
+	pub fn take(&self) -> Result<SnapshotInfo, SnapshotError> {
+		let _taking = self.taking.try_lock().ok_or(SnapshotError::Busy)?;
+		let dir = Path::new(&self.config.dir);
+		fs::create_dir_all(dir)?;
+		let prefix = format!("{}_{}", self.network.name(), Utc::now().format("%Y%m%dT%H%M%SZ"));
+		let tmp_file = dir.join(format!("{}.db.tmp", prefix));
+
+		let source = event_db::get_connection(&self.database_file)?;
+		let (indexed_from, indexed_to) = {
+			let mut snapshot = Connection::open(&tmp_file)?;
+			// All pages in one step, i.e. within one read transaction. In WAL mode that does not
+			// block the scraper, and the copy is consistent even though the scraper keeps writing.
+			Backup::new(&source, &mut snapshot)?.run_to_completion(-1, Duration::ZERO, None)?;
+			// A single file, without a WAL next to it.
+			snapshot.pragma_update(None, "journal_mode", "DELETE")?;
+			event_db::get_bounds_with_conn(&snapshot)?
+		};
+		let file = dir.join(format!("{}_{}_{}.db", prefix, indexed_from, indexed_to));
+		fs::rename(&tmp_file, &file)?;
+		log::info!(
+			"{}: snapshot of blocks {}-{} saved to {}",
+			self.network.name(),
+			indexed_from,
+			indexed_to,
+			file.display()
+		);
+		self.prune()?;
+		Ok(SnapshotInfo { file: file.display().to_string(), indexed_from, indexed_to })
+	}
+
+	// Snapshot file names start with the network and the time, so they sort by age.
+	fn prune(&self) -> std::io::Result<()> {
+		let prefix = format!("{}_", self.network.name());
+		let mut files: Vec<PathBuf> = fs::read_dir(&self.config.dir)?
+			.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+			.filter(|path| {
+				let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+				name.starts_with(&prefix) && name.ends_with(".db")
+			})
+			.collect();
+		files.sort();
+		let excess = files.len().saturating_sub(self.config.keep.max(1));
+		for file in &files[..excess] {
+			log::info!("{}: deleting old snapshot {}", self.network.name(), file.display());
+			fs::remove_file(file)?;
+		}
+		Ok(())
+	}
+}
+
+/// Takes a snapshot every `interval`, if one is configured.
+pub async fn run_schedule(snapshots: Arc<Snapshots>) {
+	let Some(interval) = snapshots.interval() else {
+		return;
+	};
+	loop {
+		tokio::time::sleep(interval).await;
+		let snapshots = snapshots.clone();
+		match tokio::task::spawn_blocking(move || snapshots.take()).await.unwrap() {
+			Ok(_) | Err(SnapshotError::Busy) => {},
+			Err(e) => log::error!("Error taking snapshot: {}", e),
+		}
+	}
+}
+
+/// Copies `snapshot` to `database_file`, unless that already exists. The scraper then continues
+/// from the bounds stored in the snapshot. Returns whether it was copied.
+pub fn restore(snapshot: &str, database_file: &str) -> Result<bool, SnapshotError> {
+	if Path::new(database_file).exists() {
+		log::info!("{} exists, not restoring snapshot {}", database_file, snapshot);
+		return Ok(false);
+	}
+	let bounds = event_db::get_bounds_with_conn(&Connection::open_with_flags(
+		snapshot,
+		rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+	)?)?;
+	let tmp_file = format!("{}.tmp", database_file);
+	fs::copy(snapshot, &tmp_file)?;
+	fs::rename(&tmp_file, database_file)?;
+	log::info!(
+		"Restored snapshot {} of blocks {}-{} to {}",
+		snapshot,
+		bounds.0,
+		bounds.1,
+		database_file
+	);
+	Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{restore, SnapshotConfig, SnapshotError, Snapshots};
+	use crate::{config::Network, event_db};
+	use std::fs;
+
+	#[test]
+	fn snapshots_keep_bounds_and_restore() {
+		let dir = std::env::temp_dir().join(format!("snapshot_test_{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let database_file = dir.join("events.db").display().to_string();
+		event_db::init_db(&database_file, 100).unwrap();
+		let snapshots = Snapshots::new(
+			SnapshotConfig {
+				dir: dir.join("snapshots").display().to_string(),
+				interval_secs: None,
+				keep: 1,
+			},
+			Network::Testnet,
+			database_file.clone(),
+		);
+
+		let info = snapshots.take().unwrap();
+		assert_eq!((info.indexed_from, info.indexed_to), (100, 99));
+		{
+			let _taking = snapshots.taking.lock();
+			assert!(matches!(snapshots.take(), Err(SnapshotError::Busy)));
+		}
+
+		assert!(!restore(&info.file, &database_file).unwrap());
+		let restored_file = dir.join("restored.db").display().to_string();
+		assert!(restore(&info.file, &restored_file).unwrap());
+		assert_eq!(event_db::get_bounds(&restored_file).unwrap(), (100, 99));
+
+		// Snapshots taken within the same second share a name.
+		std::thread::sleep(std::time::Duration::from_secs(1));
+		let newer = snapshots.take().unwrap();
+		assert_eq!(fs::read_dir(dir.join("snapshots")).unwrap().count(), 1);
+		assert!(std::path::Path::new(&newer.file).exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
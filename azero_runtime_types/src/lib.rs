@@ -1,8 +1,19 @@
+#[subxt::subxt(runtime_metadata_path = "./metadata/azero-runtime-68.scale")]
+pub mod v_68 {
+	pub fn spec_version() -> u32 {
+		68
+	}
+
+	pub const METADATA: &[u8] = include_bytes!("../metadata/azero-runtime-68.scale");
+}
+
 #[subxt::subxt(runtime_metadata_path = "./metadata/azero-runtime-69.scale")]
 pub mod v_69 {
 	pub fn spec_version() -> u32 {
 		69
 	}
+
+	pub const METADATA: &[u8] = include_bytes!("../metadata/azero-runtime-69.scale");
 }
 
 #[subxt::subxt(runtime_metadata_path = "./metadata/azero-runtime-73.scale")]
@@ -10,4 +21,6 @@ pub mod v_73 {
 	pub fn spec_version() -> u32 {
 		73
 	}
+
+	pub const METADATA: &[u8] = include_bytes!("../metadata/azero-runtime-73.scale");
 }
@@ -0,0 +1,25 @@
+use azero_config::{AccountId, Client, Config};
+use serde::{Deserialize, Serialize};
+use subxt::blocks::ExtrinsicDetails;
+
+use crate::runtime::Runtime;
+
+/// A `Contracts::call` dispatched by an extrinsic, either directly or wrapped in `Utility`,
+/// `Proxy`, `Multisig` or `Sudo` calls. `data` is the input of the contract, for ink! the message
+/// selector followed by the SCALE encoded arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenericContractCall {
+	pub contract: AccountId,
+	pub value: u128,
+	pub data: Vec<u8>,
+}
+
+/// The contract calls dispatched by `extrinsic`, in order. Calls that fail are included, their
+/// `Called` events are missing. `extrinsic` must be of a block executed by `runtime`, its call is
+/// decoded with the types or the metadata of that runtime whatever the metadata of the client is.
+pub fn contract_calls(
+	runtime: &Runtime,
+	extrinsic: &ExtrinsicDetails<Config, Client>,
+) -> Vec<GenericContractCall> {
+	runtime.types().contract_calls(extrinsic.call_bytes()).unwrap_or_default()
+}
@@ -1,10 +1,16 @@
 use std::collections::BTreeMap;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use subxt::utils::{AccountId32, H256};
+use subxt::{
+	dynamic::Value,
+	storage::{DynamicAddress, Storage},
+	utils::{AccountId32, H256},
+};
 
-use azero_config::Client;
+use azero_config::{BlockHash, Client, Config};
+
+use crate::runtime::Runtime;
 
 fn contract_info_of_key_to_account_id(key: &[u8]) -> AccountId32 {
 	let account_bytes = key[40..].to_vec();
@@ -12,132 +18,56 @@ fn contract_info_of_key_to_account_id(key: &[u8]) -> AccountId32 {
 	AccountId32::from(array_u8)
 }
 
-mod v_73 {
-	use std::collections::BTreeMap;
-
-	use super::{contract_info_of_key_to_account_id, GenericContractInfo};
-	use anyhow::Result;
-	use azero_config::Client;
-	use azero_runtime_types::v_73 as azero;
-	use subxt::utils::AccountId32;
-	impl From<azero::runtime_types::pallet_contracts::storage::ContractInfo> for GenericContractInfo {
-		fn from(info: azero::runtime_types::pallet_contracts::storage::ContractInfo) -> Self {
-			Self { trie_id: info.trie_id.0, code_hash: info.code_hash }
-		}
-	}
-
-	pub(crate) async fn get_contract_info(
-		api: &Client,
-		address: &AccountId32,
-	) -> Result<Option<GenericContractInfo>> {
-		let storage_address = azero::storage().contracts().contract_info_of(address);
-		let contract_info = api
-			.storage()
-			.at_latest()
-			.await?
-			.fetch(&storage_address)
-			.await
-			.map_err(|e| anyhow::anyhow!("Get contract info failed {:?}", e))?;
-		Ok(contract_info.map(|info| info.into()))
-	}
-
-	pub(crate) async fn get_contract_infos(
-		api: &Client,
-	) -> Result<BTreeMap<AccountId32, GenericContractInfo>> {
-		let storage_address = azero::storage().contracts().contract_info_of_iter();
-		let mut res = BTreeMap::new();
-		let mut stream = api.storage().at_latest().await?.iter(storage_address).await?;
-		while let Some(Ok((key, value))) = stream.next().await {
-			let account = contract_info_of_key_to_account_id(&key);
-			res.insert(account, value.into());
-		}
-		Ok(res)
-	}
+fn storage_address(keys: Vec<Value>) -> DynamicAddress<Value> {
+	subxt::dynamic::storage("Contracts", "ContractInfoOf", keys)
 }
 
-mod v_69 {
-	use std::collections::BTreeMap;
-
-	use super::{contract_info_of_key_to_account_id, GenericContractInfo};
-	use anyhow::Result;
-	use azero_config::{AccountId, Client};
-	use azero_runtime_types::v_69 as azero;
-	impl From<azero::runtime_types::pallet_contracts::storage::ContractInfo> for GenericContractInfo {
-		fn from(info: azero::runtime_types::pallet_contracts::storage::ContractInfo) -> Self {
-			Self { trie_id: info.trie_id.0, code_hash: info.code_hash }
-		}
-	}
-
-	pub(crate) async fn get_contract_info(
-		api: &Client,
-		address: &AccountId,
-	) -> Result<Option<GenericContractInfo>> {
-		let storage_address = azero::storage().contracts().contract_info_of(address);
-		let contract_info = api
-			.storage()
-			.at_latest()
-			.await?
-			.fetch(&storage_address)
-			.await
-			.map_err(|e| anyhow::anyhow!("Get contract info failed {:?}", e))?;
-		Ok(contract_info.map(|info| info.into()))
-	}
-
-	pub(crate) async fn get_contract_infos(
-		api: &Client,
-	) -> Result<BTreeMap<AccountId, GenericContractInfo>> {
-		let storage_address = azero::storage().contracts().contract_info_of_iter();
-		let mut res = BTreeMap::new();
-		let mut stream = api.storage().at_latest().await?.iter(storage_address).await?;
-		while let Some(Ok((key, value))) = stream.next().await {
-			let account = contract_info_of_key_to_account_id(&key);
-			res.insert(account, value.into());
-		}
-		Ok(res)
-	}
+// The contract info of `address` in `storage`, which must be of a block executed by `runtime`.
+async fn get_contract_info_in(
+	api: &Client,
+	runtime: &Runtime,
+	storage: Storage<Config, Client>,
+	address: &AccountId32,
+) -> Result<Option<GenericContractInfo>> {
+	let key = api
+		.storage()
+		.address_bytes(&storage_address(vec![Value::from_bytes(address)]))?;
+	let bytes = storage.fetch_raw(key).await.context("Get contract info failed")?;
+	bytes.map(|bytes| runtime.types().contract_info(&bytes)).transpose()
 }
 
+/// The contract infos in the latest state.
 pub async fn backwards_compatible_get_contract_infos(
 	api: &Client,
 ) -> Result<BTreeMap<AccountId32, GenericContractInfo>> {
-	let err_69 = match v_69::get_contract_infos(api).await {
-		Ok(suc) => {
-			return Ok(suc);
-		},
-		Err(e) => e,
-	};
-
-	let err_68 = match v_73::get_contract_infos(api).await {
-		Ok(suc) => {
-			return Ok(suc);
-		},
-		Err(e) => e,
-	};
-	Err(anyhow::anyhow!("Get contract infos failed, errors: {:?} {:?}", err_69, err_68))
+	let runtime = Runtime::latest(api);
+	let mut res = BTreeMap::new();
+	let mut stream = api.storage().at_latest().await?.iter(storage_address(vec![])).await?;
+	while let Some(Ok((key, value))) = stream.next().await {
+		let account = contract_info_of_key_to_account_id(&key);
+		res.insert(account, runtime.types().contract_info(value.encoded())?);
+	}
+	Ok(res)
 }
 
+/// The contract info of `address` in the latest state, `None` if it does not exist.
 pub async fn backwards_compatible_get_contract_info(
 	api: &Client,
 	address: &AccountId32,
 ) -> Result<Option<GenericContractInfo>> {
-	let err_69 = match v_69::get_contract_info(api, address).await {
-		Ok(suc) => {
-			return Ok(suc);
-		},
-		Err(e) => e,
-	};
-	let err_68 = match v_73::get_contract_info(api, address).await {
-		Ok(suc) => {
-			return Ok(suc);
-		},
-		Err(e) => e,
-	};
-	Err(anyhow::anyhow!(
-		"Get contract info failed for {}, errors: {:?} {:?}",
-		address,
-		err_69,
-		err_68
-	))
+	let storage = api.storage().at_latest().await?;
+	get_contract_info_in(api, &Runtime::latest(api), storage, address).await
+}
+
+/// The contract info of `address` in the state of block `block_hash`, `None` if it does not exist
+/// there. It is decoded with the types or the metadata of the runtime that executed the block.
+pub async fn backwards_compatible_get_contract_info_at(
+	api: &Client,
+	address: &AccountId32,
+	block_hash: BlockHash,
+) -> Result<Option<GenericContractInfo>> {
+	let runtime = Runtime::at(api, block_hash).await?;
+	get_contract_info_in(api, &runtime, api.storage().at(block_hash), address).await
 }
 
 pub struct GenericContractInfo {
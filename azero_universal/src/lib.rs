@@ -1,8 +1,12 @@
 use azero_config::{BlockHash, Client, Config, RpcClient};
+pub use rpc_pool::{EndpointHealth, RpcConnection, RpcPool};
 use subxt::backend::legacy::LegacyRpcMethods;
 
+pub mod contract_calls;
 pub mod contract_events;
 pub mod contract_info;
+pub mod rpc_pool;
+pub mod runtime;
 
 pub async fn get_hash_from_number(
 	client: &RpcClient,
@@ -14,24 +18,11 @@ pub async fn get_hash_from_number(
 	Ok(block_hash)
 }
 
+/// Connects to `url`, retrying until it succeeds. For several endpoints of a network, use
+/// `RpcPool`.
 pub async fn initialize_client(url: &str) -> (RpcClient, Client) {
-	loop {
-		match RpcClient::from_url(url).await {
-			Ok(rpc_client) => match Client::from_rpc_client(rpc_client.clone()).await {
-				Ok(client) => {
-					return (rpc_client, client);
-				},
-				Err(e) => {
-					log::info!("Error {} initializing client at {}", e, url);
-					tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-				},
-			},
-			Err(e) => {
-				log::info!("Error {} initializing client at {}", e, url);
-				tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-			},
-		}
-	}
+	let connection = RpcPool::new([url]).wait_for_connection().await;
+	(connection.rpc_client, connection.client)
 }
 
 pub struct AccountIdSchema;
@@ -0,0 +1,328 @@
+use std::{
+	future::Future,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use azero_config::{Client, RpcClient};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+// Weight of the newest request in the moving averages of latency and error rate.
+const SMOOTHING: f64 = 0.2;
+// An endpoint failing every request ranks as if it were this many times slower.
+const ERROR_PENALTY: f64 = 10.0;
+
+// Whether `error` is the fault of the endpoint, i.e. the request did not reach it or it did not
+// answer, rather than a failure of the request itself, e.g. to decode what the endpoint answered
+// or to find a block, which any other endpoint would fail the same way.
+fn is_endpoint_error(error: &anyhow::Error) -> bool {
+	error.chain().any(|cause| {
+		matches!(cause.downcast_ref(), Some(subxt::Error::Rpc(_) | subxt::Error::Io(_))) ||
+			cause.is::<subxt::error::RpcError>() ||
+			cause.is::<std::io::Error>()
+	})
+}
+
+/// A long-lived connection to one endpoint of an `RpcPool`.
+#[derive(Clone)]
+pub struct RpcConnection {
+	pub url: String,
+	pub rpc_client: RpcClient,
+	pub client: Client,
+}
+
+#[derive(Debug, Clone)]
+pub struct EndpointHealth {
+	pub url: String,
+	/// Moving average of the request latency, `None` before the first successful request.
+	pub latency: Option<Duration>,
+	/// Moving average of the share of failed requests.
+	pub error_rate: f64,
+	pub consecutive_failures: u32,
+	/// The endpoint is avoided until then.
+	pub backoff_until: Option<Instant>,
+	pub requests: u64,
+	pub failures: u64,
+}
+
+impl EndpointHealth {
+	fn new(url: String) -> Self {
+		Self {
+			url,
+			latency: None,
+			error_rate: 0.0,
+			consecutive_failures: 0,
+			backoff_until: None,
+			requests: 0,
+			failures: 0,
+		}
+	}
+
+	pub fn is_backing_off(&self, now: Instant) -> bool {
+		self.backoff_until.is_some_and(|until| until > now)
+	}
+
+	// Lower is better. Endpoints without a measured latency are tried first.
+	fn score(&self) -> f64 {
+		let latency = self.latency.map_or(0.0, |latency| latency.as_secs_f64());
+		latency * (1.0 + ERROR_PENALTY * self.error_rate)
+	}
+
+	fn record_success(&mut self, latency: Duration) {
+		self.requests += 1;
+		self.latency = Some(match self.latency {
+			Some(average) => average.mul_f64(1.0 - SMOOTHING) + latency.mul_f64(SMOOTHING),
+			None => latency,
+		});
+		self.error_rate *= 1.0 - SMOOTHING;
+		self.consecutive_failures = 0;
+		self.backoff_until = None;
+	}
+
+	fn record_failure(&mut self, now: Instant) {
+		self.requests += 1;
+		self.failures += 1;
+		self.error_rate = self.error_rate * (1.0 - SMOOTHING) + SMOOTHING;
+		let backoff = MIN_BACKOFF.saturating_mul(1 << self.consecutive_failures.min(16));
+		self.backoff_until = Some(now + backoff.min(MAX_BACKOFF));
+		self.consecutive_failures += 1;
+	}
+}
+
+struct Endpoint {
+	health: Mutex<EndpointHealth>,
+	connection: tokio::sync::Mutex<Option<RpcConnection>>,
+}
+
+/// A set of RPC endpoints of one network. Keeps a connection to every endpoint that was used,
+/// ranks endpoints by latency and error rate, and backs off from failing ones. Cheap to clone,
+/// clones share connections and health.
+#[derive(Clone)]
+pub struct RpcPool {
+	endpoints: Arc<Vec<Endpoint>>,
+}
+
+impl RpcPool {
+	pub fn new<S: Into<String>>(urls: impl IntoIterator<Item = S>) -> Self {
+		let endpoints: Vec<Endpoint> = urls
+			.into_iter()
+			.map(|url| Endpoint {
+				health: Mutex::new(EndpointHealth::new(url.into())),
+				connection: tokio::sync::Mutex::new(None),
+			})
+			.collect();
+		assert!(!endpoints.is_empty(), "RpcPool needs at least one endpoint");
+		Self { endpoints: Arc::new(endpoints) }
+	}
+
+	/// Endpoints from the comma separated list in the environment variable `var`, or `default`
+	/// if it is not set.
+	pub fn from_env(var: &str, default: &str) -> Self {
+		let urls = std::env::var(var).unwrap_or_else(|_| default.to_string());
+		Self::new(urls.split(',').map(str::trim).filter(|url| !url.is_empty()))
+	}
+
+	pub fn urls(&self) -> Vec<String> {
+		self.endpoints
+			.iter()
+			.map(|endpoint| endpoint.health.lock().unwrap().url.clone())
+			.collect()
+	}
+
+	pub fn health(&self) -> Vec<EndpointHealth> {
+		self.endpoints
+			.iter()
+			.map(|endpoint| endpoint.health.lock().unwrap().clone())
+			.collect()
+	}
+
+	// Indices of the endpoints, best first. Backing off endpoints go last, the one to recover
+	// first ahead.
+	fn ranked(&self) -> Vec<usize> {
+		let now = Instant::now();
+		let health = self.health();
+		let mut indices: Vec<usize> = (0..health.len()).collect();
+		indices.sort_by(|&a, &b| {
+			let (a, b) = (&health[a], &health[b]);
+			match (a.is_backing_off(now), b.is_backing_off(now)) {
+				(false, false) => a.score().total_cmp(&b.score()),
+				(true, true) => a.backoff_until.cmp(&b.backoff_until),
+				(backing_off, _) => backing_off.cmp(&!backing_off),
+			}
+		});
+		indices
+	}
+
+	fn find(&self, url: &str) -> Option<&Endpoint> {
+		self.endpoints
+			.iter()
+			.find(|endpoint| endpoint.health.lock().unwrap().url == url)
+	}
+
+	/// Records a request to `url` that succeeded after `latency`.
+	pub fn report_success(&self, url: &str, latency: Duration) {
+		if let Some(endpoint) = self.find(url) {
+			endpoint.health.lock().unwrap().record_success(latency);
+		}
+	}
+
+	/// Records a failed request to `url`. The endpoint backs off and its connection is dropped,
+	/// the next request opens a new one.
+	pub fn report_failure(&self, url: &str) {
+		if let Some(endpoint) = self.find(url) {
+			Self::fail(endpoint);
+		}
+	}
+
+	fn fail(endpoint: &Endpoint) {
+		endpoint.health.lock().unwrap().record_failure(Instant::now());
+		// If the connection is locked, it is being opened right now and is fresh.
+		if let Ok(mut connection) = endpoint.connection.try_lock() {
+			*connection = None;
+		}
+	}
+
+	async fn connect(endpoint: &Endpoint) -> anyhow::Result<RpcConnection> {
+		let mut connection = endpoint.connection.lock().await;
+		if let Some(connection) = connection.as_ref() {
+			return Ok(connection.clone());
+		}
+		let url = endpoint.health.lock().unwrap().url.clone();
+		let rpc_client = RpcClient::from_url(&url)
+			.await
+			.map_err(|e| anyhow::anyhow!("Failed to connect to endpoint {}: {}", url, e))?;
+		let client = Client::from_rpc_client(rpc_client.clone())
+			.await
+			.map_err(|e| anyhow::anyhow!("Failed to initialize client at {}: {}", url, e))?;
+		log::info!("Connected to {}", url);
+		let new_connection = RpcConnection { url, rpc_client, client };
+		*connection = Some(new_connection.clone());
+		Ok(new_connection)
+	}
+
+	/// A connection to the best endpoint that accepts one.
+	pub async fn connection(&self) -> anyhow::Result<RpcConnection> {
+		let mut last_error = None;
+		for index in self.ranked() {
+			let endpoint = &self.endpoints[index];
+			match Self::connect(endpoint).await {
+				Ok(connection) => return Ok(connection),
+				Err(e) => {
+					log::warn!("{}", e);
+					Self::fail(endpoint);
+					last_error = Some(e);
+				},
+			}
+		}
+		Err(last_error.unwrap())
+	}
+
+	/// Like `connection`, but retries until some endpoint accepts.
+	pub async fn wait_for_connection(&self) -> RpcConnection {
+		loop {
+			match self.connection().await {
+				Ok(connection) => return connection,
+				Err(_) => tokio::time::sleep(MIN_BACKOFF).await,
+			}
+		}
+	}
+
+	/// Runs `request` on the best endpoint, failing over to the next ones if it fails with an RPC
+	/// or transport error. The outcome and latency of every attempt count towards the health of
+	/// its endpoint. Other errors, e.g. decoding the response, are not the endpoint's fault and
+	/// are returned right away. Returns the error of the last attempt if all fail.
+	pub async fn run<T, F, Fut>(&self, request: F) -> anyhow::Result<T>
+	where
+		F: Fn(RpcConnection) -> Fut,
+		Fut: Future<Output = anyhow::Result<T>>,
+	{
+		let mut last_error = None;
+		for index in self.ranked() {
+			let endpoint = &self.endpoints[index];
+			let connection = match Self::connect(endpoint).await {
+				Ok(connection) => connection,
+				Err(e) => {
+					log::warn!("{}", e);
+					Self::fail(endpoint);
+					last_error = Some(e);
+					continue;
+				},
+			};
+			let url = connection.url.clone();
+			let start = Instant::now();
+			match request(connection).await {
+				Ok(result) => {
+					endpoint.health.lock().unwrap().record_success(start.elapsed());
+					return Ok(result);
+				},
+				Err(e) if is_endpoint_error(&e) => {
+					log::warn!("Request to {} failed: {}", url, e);
+					Self::fail(endpoint);
+					last_error = Some(e);
+				},
+				Err(e) => {
+					endpoint.health.lock().unwrap().record_success(start.elapsed());
+					return Err(e);
+				},
+			}
+		}
+		Err(last_error.unwrap())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_endpoint_error, RpcPool, MIN_BACKOFF};
+	use crate::runtime::RuntimeError;
+	use std::{
+		io,
+		time::{Duration, Instant},
+	};
+
+	#[test]
+	fn failing_endpoints_rank_last() {
+		let pool = RpcPool::new(["a", "b", "c"]);
+		pool.report_success("a", Duration::from_millis(300));
+		pool.report_success("b", Duration::from_millis(100));
+		pool.report_success("c", Duration::from_millis(200));
+		assert_eq!(pool.ranked(), vec![1, 2, 0]);
+
+		pool.report_failure("b");
+		assert_eq!(pool.ranked(), vec![2, 0, 1]);
+		let health = &pool.health()[1];
+		assert!(health.is_backing_off(Instant::now()));
+		assert!(!health.is_backing_off(Instant::now() + MIN_BACKOFF));
+
+		pool.report_failure("c");
+		pool.report_failure("c");
+		assert_eq!(pool.ranked(), vec![0, 1, 2]);
+
+		pool.report_success("c", Duration::from_millis(200));
+		assert_eq!(pool.ranked(), vec![0, 2, 1]);
+	}
+
+	#[test]
+	fn only_rpc_and_transport_errors_are_the_endpoints_fault() {
+		let reset = || io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+		assert!(is_endpoint_error(&subxt::Error::Io(reset()).into()));
+		assert!(is_endpoint_error(&anyhow::Error::from(reset()).context("Fetching block")));
+		let runtime_error = RuntimeError::Subxt(subxt::Error::Io(reset()));
+		assert!(is_endpoint_error(&anyhow::Error::from(runtime_error).context("Fetching events")));
+
+		assert!(!is_endpoint_error(&anyhow::anyhow!("Block not found")));
+		assert!(!is_endpoint_error(&RuntimeError::UnknownSpecVersion(12).into()));
+		let undecodable = codec::Error::from("Not enough data");
+		assert!(!is_endpoint_error(&subxt::Error::Codec(undecodable).into()));
+	}
+
+	#[test]
+	fn errors_outweigh_latency() {
+		let pool = RpcPool::new(["a", "b"]);
+		pool.report_success("a", Duration::from_millis(100));
+		pool.report_failure("a");
+		pool.report_success("a", Duration::from_millis(100));
+		pool.report_success("b", Duration::from_millis(200));
+		assert_eq!(pool.ranked(), vec![1, 0]);
+	}
+}
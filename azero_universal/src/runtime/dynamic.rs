@@ -0,0 +1,260 @@
+// Decodes by name with the metadata of the runtime, so it works across runtimes as long as the
+// fields read here keep their names and types. Fields added by later runtimes are skipped.
+
+use azero_config::{AccountId, Config};
+use subxt::{
+	events::{EventDetails, StaticEvent},
+	ext::{
+		scale_decode::DecodeAsType,
+		scale_value::{self, Composite, Value, ValueDef},
+	},
+	utils::H256,
+	Metadata,
+};
+
+use crate::{
+	contract_calls::GenericContractCall,
+	contract_events::{GenericContractEvent, Origin},
+	contract_info::GenericContractInfo,
+	runtime::{RuntimeError, RuntimeTypes},
+};
+
+/// The types of a runtime without generated ones.
+pub(super) struct DynamicTypes {
+	spec_version: u32,
+	metadata: Metadata,
+}
+
+impl DynamicTypes {
+	pub(super) fn new(spec_version: u32, metadata: Metadata) -> Self {
+		Self { spec_version, metadata }
+	}
+}
+
+macro_rules! contracts_event {
+	($name:ident { $($field:ident: $ty:ty),* }) => {
+		#[derive(DecodeAsType)]
+		#[decode_as_type(crate_path = "subxt::ext::scale_decode")]
+		struct $name {
+			$($field: $ty),*
+		}
+
+		impl StaticEvent for $name {
+			const PALLET: &'static str = "Contracts";
+			const EVENT: &'static str = stringify!($name);
+		}
+	};
+}
+
+#[derive(DecodeAsType)]
+#[decode_as_type(crate_path = "subxt::ext::scale_decode")]
+enum CallOrigin {
+	Root,
+	Signed(AccountId),
+}
+
+contracts_event!(Instantiated { deployer: AccountId, contract: AccountId });
+contracts_event!(Terminated { contract: AccountId, beneficiary: AccountId });
+contracts_event!(CodeStored { code_hash: H256, uploader: AccountId });
+contracts_event!(ContractEmitted { contract: AccountId, data: Vec<u8> });
+contracts_event!(CodeRemoved { code_hash: H256, remover: AccountId });
+contracts_event!(ContractCodeUpdated {
+	contract: AccountId,
+	new_code_hash: H256,
+	old_code_hash: H256
+});
+contracts_event!(Called { caller: CallOrigin, contract: AccountId });
+contracts_event!(DelegateCalled { contract: AccountId, code_hash: H256 });
+
+#[derive(DecodeAsType)]
+#[decode_as_type(crate_path = "subxt::ext::scale_decode")]
+struct ContractInfo {
+	trie_id: Vec<u8>,
+	code_hash: H256,
+}
+
+// An event that does not decode, e.g. as a field read here was renamed or retyped by the runtime,
+// is an error rather than left out, as it would be lost for good.
+fn decode<E: StaticEvent>(event: &EventDetails<Config>) -> Result<E, RuntimeError> {
+	let undecodable = |e| RuntimeError::UndecodableEvent(E::EVENT.to_string(), e);
+	event.as_event::<E>().map_err(undecodable)?.ok_or_else(|| {
+		undecodable(subxt::Error::Other(format!("Not a Contracts::{} event", E::EVENT)))
+	})
+}
+
+// A call is decoded as a tree of values: the variant of the outer call enum is the pallet, its only
+// field the call of the pallet, a variant with named fields.
+fn variant<T>(value: &Value<T>) -> Option<(&str, &Composite<T>)> {
+	match &value.value {
+		ValueDef::Variant(variant) => Some((variant.name.as_str(), &variant.values)),
+		_ => None,
+	}
+}
+
+fn field<'a, T>(fields: &'a Composite<T>, name: &str) -> Option<&'a Value<T>> {
+	match fields {
+		Composite::Named(fields) =>
+			fields.iter().find(|(field, _)| field == name).map(|(_, value)| value),
+		Composite::Unnamed(_) => None,
+	}
+}
+
+// The bytes of a `Vec<u8>`, a `[u8; N]` or a newtype around one, as `AccountId32` is.
+fn bytes<T>(value: &Value<T>) -> Option<Vec<u8>> {
+	let ValueDef::Composite(composite) = &value.value else { return None };
+	let values: Vec<_> = composite.values().collect();
+	if let [inner @ Value { value: ValueDef::Composite(_), .. }] = values.as_slice() {
+		return bytes(inner);
+	}
+	values
+		.into_iter()
+		.map(|byte| byte.as_u128().and_then(|b| u8::try_from(b).ok()))
+		.collect()
+}
+
+fn contract_call<T>(fields: &Composite<T>) -> Option<GenericContractCall> {
+	let (address, account) = variant(field(fields, "dest")?)?;
+	if address != "Id" {
+		return None;
+	}
+	let contract: [u8; 32] = bytes(account.values().next()?)?.try_into().ok()?;
+	Some(GenericContractCall {
+		contract: AccountId::from(contract),
+		value: field(fields, "value")?.as_u128()?,
+		data: bytes(field(fields, "data")?)?,
+	})
+}
+
+// Depth first, so the calls come in the order they are dispatched, as with the generated types.
+fn collect<T>(call: &Value<T>, calls: &mut Vec<GenericContractCall>) {
+	let Some((pallet, pallet_call)) = variant(call) else { return };
+	let Some((name, fields)) = pallet_call.values().next().and_then(variant) else { return };
+	match (pallet, name) {
+		("Contracts", "call" | "call_old_weight") => calls.extend(contract_call(fields)),
+		("Utility", "batch" | "batch_all" | "force_batch") =>
+			if let Some(ValueDef::Composite(batch)) = field(fields, "calls").map(|v| &v.value) {
+				for call in batch.values() {
+					collect(call, calls);
+				}
+			},
+		("Utility", "as_derivative" | "dispatch_as" | "with_weight") |
+		("Proxy", "proxy" | "proxy_announced") |
+		("Multisig", "as_multi_threshold_1" | "as_multi") |
+		("Sudo", "sudo" | "sudo_unchecked_weight" | "sudo_as") =>
+			if let Some(call) = field(fields, "call") {
+				collect(call, calls);
+			},
+		_ => {},
+	}
+}
+
+impl RuntimeTypes for DynamicTypes {
+	fn spec_version(&self) -> u32 {
+		self.spec_version
+	}
+
+	fn metadata(&self) -> Metadata {
+		self.metadata.clone()
+	}
+
+	fn contract_event(
+		&self,
+		event: &EventDetails<Config>,
+	) -> Result<Option<GenericContractEvent>, RuntimeError> {
+		if event.pallet_name() != "Contracts" {
+			return Ok(None);
+		}
+		let contract_event = match event.variant_name() {
+			"Instantiated" => {
+				let e = decode::<Instantiated>(event)?;
+				GenericContractEvent::Instantiated { deployer: e.deployer, contract: e.contract }
+			},
+			"Terminated" => {
+				let e = decode::<Terminated>(event)?;
+				GenericContractEvent::Terminated {
+					contract: e.contract,
+					beneficiary: e.beneficiary,
+				}
+			},
+			"CodeStored" => {
+				let e = decode::<CodeStored>(event)?;
+				GenericContractEvent::CodeStored {
+					code_hash: e.code_hash,
+					uploader: Some(e.uploader),
+				}
+			},
+			"ContractEmitted" => {
+				let e = decode::<ContractEmitted>(event)?;
+				GenericContractEvent::ContractEmitted {
+					contract: e.contract,
+					data: e.data,
+					topics: event.topics().to_vec(),
+				}
+			},
+			"CodeRemoved" => {
+				let e = decode::<CodeRemoved>(event)?;
+				GenericContractEvent::CodeRemoved {
+					code_hash: e.code_hash,
+					remover: Some(e.remover),
+				}
+			},
+			"ContractCodeUpdated" => {
+				let e = decode::<ContractCodeUpdated>(event)?;
+				GenericContractEvent::ContractCodeUpdated {
+					contract: e.contract,
+					new_code_hash: e.new_code_hash,
+					old_code_hash: e.old_code_hash,
+				}
+			},
+			"Called" => {
+				let e = decode::<Called>(event)?;
+				let caller = match e.caller {
+					CallOrigin::Root => Origin::Root,
+					CallOrigin::Signed(account) => Origin::Signed(account),
+				};
+				GenericContractEvent::Called { caller, contract: e.contract }
+			},
+			"DelegateCalled" => {
+				let e = decode::<DelegateCalled>(event)?;
+				GenericContractEvent::DelegateCalled {
+					contract: e.contract,
+					code_hash: e.code_hash,
+				}
+			},
+			_ => return Ok(None),
+		};
+		Ok(Some(contract_event))
+	}
+
+	fn contract_calls(&self, call_bytes: &[u8]) -> Option<Vec<GenericContractCall>> {
+		let call_type = self.metadata.outer_enums().call_enum_ty();
+		let call = match scale_value::scale::decode_as_type(
+			&mut &call_bytes[..],
+			call_type,
+			self.metadata.types(),
+		) {
+			Ok(call) => call,
+			Err(e) => {
+				log::warn!("Cannot decode call dynamically: {}", e);
+				return None;
+			},
+		};
+		let mut calls = Vec::new();
+		collect(&call, &mut calls);
+		Some(calls)
+	}
+
+	fn contract_info(&self, bytes: &[u8]) -> anyhow::Result<GenericContractInfo> {
+		let value_type = self
+			.metadata
+			.pallet_by_name("Contracts")
+			.and_then(|pallet| pallet.storage())
+			.and_then(|storage| storage.entry_by_name("ContractInfoOf"))
+			.ok_or(anyhow::anyhow!("Contracts::ContractInfoOf not in the metadata"))?
+			.entry_type()
+			.value_ty();
+		let info =
+			ContractInfo::decode_as_type(&mut &bytes[..], value_type, self.metadata.types())?;
+		Ok(GenericContractInfo { trie_id: info.trie_id, code_hash: info.code_hash })
+	}
+}
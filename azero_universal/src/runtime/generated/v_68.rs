@@ -0,0 +1,124 @@
+use std::sync::OnceLock;
+
+use azero_config::Config;
+use azero_runtime_types::v_68::{
+	self as azero,
+	runtime_types::{
+		pallet_contracts::{
+			pallet::{Call as ContractsCall, Event as ContractsEvent},
+			storage::ContractInfo,
+			Origin as CallOrigin,
+		},
+		pallet_multisig::pallet::Call as MultisigCall,
+		pallet_sudo::pallet::Call as SudoCall,
+		pallet_utility::pallet::Call as UtilityCall,
+	},
+	Call, Event,
+};
+use codec::Decode;
+use subxt::{events::EventDetails, utils::MultiAddress, Metadata};
+
+use crate::{
+	contract_calls::GenericContractCall,
+	contract_events::{GenericContractEvent, Origin},
+	contract_info::GenericContractInfo,
+	runtime::{decode_metadata, RuntimeError, RuntimeTypes},
+};
+
+pub(in crate::runtime) struct Types;
+
+impl RuntimeTypes for Types {
+	fn spec_version(&self) -> u32 {
+		azero::spec_version()
+	}
+
+	fn metadata(&self) -> Metadata {
+		static METADATA: OnceLock<Metadata> = OnceLock::new();
+		METADATA.get_or_init(|| decode_metadata(azero::METADATA)).clone()
+	}
+
+	fn contract_event(
+		&self,
+		event: &EventDetails<Config>,
+	) -> Result<Option<GenericContractEvent>, RuntimeError> {
+		if event.pallet_name() != "Contracts" {
+			return Ok(None);
+		}
+		let contracts_event = match event.as_root_event::<Event>() {
+			Ok(Event::Contracts(contracts_event)) => contracts_event,
+			Ok(_) => return Ok(None),
+			Err(e) =>
+				return Err(RuntimeError::UndecodableEvent(event.variant_name().to_string(), e)),
+		};
+		Ok(match contracts_event {
+			ContractsEvent::Instantiated { deployer, contract } =>
+				Some(GenericContractEvent::Instantiated { deployer, contract }),
+			ContractsEvent::Terminated { contract, beneficiary } =>
+				Some(GenericContractEvent::Terminated { contract, beneficiary }),
+			// The uploader and the remover of a code are only in the events since runtime 69.
+			ContractsEvent::CodeStored { code_hash } =>
+				Some(GenericContractEvent::CodeStored { code_hash, uploader: None }),
+			ContractsEvent::ContractEmitted { contract, data } =>
+				Some(GenericContractEvent::ContractEmitted {
+					contract,
+					data,
+					topics: event.topics().to_vec(),
+				}),
+			ContractsEvent::CodeRemoved { code_hash } =>
+				Some(GenericContractEvent::CodeRemoved { code_hash, remover: None }),
+			ContractsEvent::ContractCodeUpdated { contract, new_code_hash, old_code_hash } =>
+				Some(GenericContractEvent::ContractCodeUpdated {
+					contract,
+					new_code_hash,
+					old_code_hash,
+				}),
+			ContractsEvent::Called { caller, contract } => match caller {
+				CallOrigin::Signed(c) =>
+					Some(GenericContractEvent::Called { caller: Origin::Signed(c), contract }),
+				CallOrigin::Root =>
+					Some(GenericContractEvent::Called { caller: Origin::Root, contract }),
+				CallOrigin::__Ignore(_) => None,
+			},
+			ContractsEvent::DelegateCalled { contract, code_hash } =>
+				Some(GenericContractEvent::DelegateCalled { contract, code_hash }),
+		})
+	}
+
+	fn contract_calls(&self, call_bytes: &[u8]) -> Option<Vec<GenericContractCall>> {
+		let call = Call::decode(&mut &call_bytes[..]).ok()?;
+		let mut calls = Vec::new();
+		collect(call, &mut calls);
+		Some(calls)
+	}
+
+	fn contract_info(&self, bytes: &[u8]) -> anyhow::Result<GenericContractInfo> {
+		let info = ContractInfo::decode(&mut &bytes[..])?;
+		Ok(GenericContractInfo { trie_id: info.trie_id.0, code_hash: info.code_hash })
+	}
+}
+
+// Depth first, so the calls come in the order they are dispatched. Runtime 68 has no `Proxy`.
+fn collect(call: Call, calls: &mut Vec<GenericContractCall>) {
+	match call {
+		Call::Contracts(ContractsCall::call { dest, value, data, .. }) |
+		Call::Contracts(ContractsCall::call_old_weight { dest, value, data, .. }) =>
+			if let MultiAddress::Id(contract) = dest {
+				calls.push(GenericContractCall { contract, value, data });
+			},
+		Call::Utility(UtilityCall::batch { calls: batch }) |
+		Call::Utility(UtilityCall::batch_all { calls: batch }) |
+		Call::Utility(UtilityCall::force_batch { calls: batch }) =>
+			for call in batch {
+				collect(call, calls);
+			},
+		Call::Utility(UtilityCall::as_derivative { call, .. }) |
+		Call::Utility(UtilityCall::dispatch_as { call, .. }) |
+		Call::Utility(UtilityCall::with_weight { call, .. }) |
+		Call::Multisig(MultisigCall::as_multi_threshold_1 { call, .. }) |
+		Call::Multisig(MultisigCall::as_multi { call, .. }) |
+		Call::Sudo(SudoCall::sudo { call }) |
+		Call::Sudo(SudoCall::sudo_unchecked_weight { call, .. }) |
+		Call::Sudo(SudoCall::sudo_as { call, .. }) => collect(*call, calls),
+		_ => {},
+	}
+}
@@ -0,0 +1,558 @@
+use std::{
+	collections::BTreeMap,
+	fmt,
+	sync::{Arc, Mutex, OnceLock},
+};
+
+use azero_config::{BlockHash, Client, Config, RpcClient};
+use azero_runtime_types::v_73;
+use codec::Decode;
+use subxt::{
+	events::{EventDetails, Events},
+	Metadata,
+};
+use thiserror::Error;
+
+use crate::{
+	contract_calls::GenericContractCall, contract_events::GenericContractEvent,
+	contract_info::GenericContractInfo,
+};
+
+mod dynamic;
+mod generated;
+
+use dynamic::DynamicTypes;
+
+/// How the events, calls and storage of the blocks executed by a runtime are decoded. It is
+/// implemented once per module of `azero_runtime_types`, and by `DynamicTypes` with the metadata
+/// of runtimes that have none.
+pub trait RuntimeTypes: Send + Sync {
+	fn spec_version(&self) -> u32;
+
+	fn metadata(&self) -> Metadata;
+
+	/// The contract event `event` is, if any. An error if it is an event of `Contracts` that does
+	/// not decode, so that it is not lost silently.
+	fn contract_event(
+		&self,
+		event: &EventDetails<Config>,
+	) -> Result<Option<GenericContractEvent>, RuntimeError>;
+
+	/// The contract calls dispatched by the call of an extrinsic, in order. `None` if the call
+	/// does not decode.
+	fn contract_calls(&self, call_bytes: &[u8]) -> Option<Vec<GenericContractCall>>;
+
+	/// Decodes a value of `Contracts::ContractInfoOf`.
+	fn contract_info(&self, bytes: &[u8]) -> anyhow::Result<GenericContractInfo>;
+}
+
+// The runtimes with generated types. This is the only place they are listed, a runtime is added
+// here once `generated` implements `RuntimeTypes` for its module of `azero_runtime_types`.
+fn registered() -> &'static [Arc<dyn RuntimeTypes>] {
+	static RUNTIMES: OnceLock<Vec<Arc<dyn RuntimeTypes>>> = OnceLock::new();
+	RUNTIMES.get_or_init(|| {
+		vec![
+			Arc::new(generated::v_68::Types),
+			Arc::new(generated::v_69::Types),
+			Arc::new(generated::v_73::Types),
+		]
+	})
+}
+
+// Decodes the metadata the types of a registered runtime were generated from.
+fn decode_metadata(bytes: &[u8]) -> Metadata {
+	Metadata::decode(&mut &bytes[..])
+		.unwrap_or_else(|e| panic!("Invalid metadata of a registered runtime: {}", e))
+}
+
+// The runtimes without generated types met so far, by genesis hash of the network and spec
+// version.
+fn dynamic_runtimes() -> &'static Mutex<BTreeMap<(BlockHash, u32), Runtime>> {
+	static RUNTIMES: OnceLock<Mutex<BTreeMap<(BlockHash, u32), Runtime>>> = OnceLock::new();
+	RUNTIMES.get_or_init(Default::default)
+}
+
+/// The runtime that executed a block. Events, calls and storage of the block are decoded with the
+/// generated types of `azero_runtime_types` if there are some for the runtime, and dynamically
+/// with its metadata otherwise.
+#[derive(Clone)]
+pub struct Runtime(Arc<dyn RuntimeTypes>);
+
+#[derive(Error, Debug)]
+pub enum RuntimeError {
+	#[error("No types are registered for runtime spec version {0}")]
+	UnknownSpecVersion(u32),
+	#[error("Runtime version not found at block {0:?}")]
+	VersionNotFound(BlockHash),
+	#[error("Cannot decode Contracts::{0}: {1}")]
+	UndecodableEvent(String, subxt::Error),
+	#[error("Invalid metadata: {0}")]
+	InvalidMetadata(#[from] codec::Error),
+	#[error("Subxt error: {0}")]
+	Subxt(#[from] subxt::Error),
+}
+
+impl fmt::Debug for Runtime {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("Runtime").field(&self.spec_version()).finish()
+	}
+}
+
+impl Runtime {
+	pub fn spec_version(&self) -> u32 {
+		self.0.spec_version()
+	}
+
+	/// The metadata the types of the runtime were generated from, or the one fetched from the node.
+	pub fn metadata(&self) -> Metadata {
+		self.0.metadata()
+	}
+
+	pub fn types(&self) -> &dyn RuntimeTypes {
+		self.0.as_ref()
+	}
+
+	/// The registered runtime of `spec_version`.
+	pub fn from_spec_version(spec_version: u32) -> Result<Runtime, RuntimeError> {
+		registered()
+			.iter()
+			.find(|types| types.spec_version() == spec_version)
+			.map(|types| Runtime(Arc::clone(types)))
+			.ok_or(RuntimeError::UnknownSpecVersion(spec_version))
+	}
+
+	/// The runtime the metadata of `api` is of, i.e. the runtime of the latest blocks.
+	pub fn latest(api: &Client) -> Runtime {
+		let spec_version = api.runtime_version().spec_version;
+		Runtime::from_spec_version(spec_version)
+			.unwrap_or_else(|_| Runtime(Arc::new(DynamicTypes::new(spec_version, api.metadata()))))
+	}
+
+	/// The runtime that executed block `block_hash`. A runtime upgrade only takes effect in the
+	/// block after the one that sets the code, so this reads the last upgrade from the state after
+	/// the block rather than the version of the code in it.
+	pub async fn at(api: &Client, block_hash: BlockHash) -> Result<Runtime, RuntimeError> {
+		let upgrade = api
+			.storage()
+			.at(block_hash)
+			.fetch(&v_73::storage().system().last_runtime_upgrade())
+			.await?;
+		let spec_version = match upgrade {
+			Some(upgrade) => upgrade.spec_version.0,
+			// Chains that were never upgraded have none, e.g. at genesis.
+			None => version_of_code(api, block_hash).await?,
+		};
+		match Runtime::from_spec_version(spec_version) {
+			Err(RuntimeError::UnknownSpecVersion(_)) =>
+				Runtime::dynamic(api, spec_version, block_hash).await,
+			result => result,
+		}
+	}
+
+	async fn dynamic(
+		api: &Client,
+		spec_version: u32,
+		block_hash: BlockHash,
+	) -> Result<Runtime, RuntimeError> {
+		let key = (api.genesis_hash(), spec_version);
+		if let Some(runtime) = dynamic_runtimes().lock().unwrap().get(&key) {
+			return Ok(runtime.clone());
+		}
+		let bytes: Vec<u8> = api
+			.runtime_api()
+			.at(code_block(api, block_hash).await?)
+			.call_raw("Metadata_metadata", None)
+			.await?;
+		let metadata = Metadata::decode(&mut &bytes[..])?;
+		log::warn!(
+			"No types are registered for runtime spec version {}, decoding it with the metadata of \
+			 the node",
+			spec_version
+		);
+		let runtime = Runtime(Arc::new(DynamicTypes::new(spec_version, metadata)));
+		dynamic_runtimes().lock().unwrap().insert(key, runtime.clone());
+		Ok(runtime)
+	}
+
+	/// A client of the network of `api` with the metadata of this runtime, over the connection of
+	/// `rpc_client`. Blocks fetched with it decode their extrinsics with this runtime, whatever the
+	/// metadata of `api` is.
+	pub fn client(&self, api: &Client, rpc_client: &RpcClient) -> Result<Client, RuntimeError> {
+		if self.spec_version() == api.runtime_version().spec_version {
+			return Ok(api.clone());
+		}
+		Ok(Client::from_rpc_client_with(
+			api.genesis_hash(),
+			api.runtime_version(),
+			self.metadata(),
+			rpc_client.clone(),
+		)?)
+	}
+
+	/// The events of block `block_hash`, which must have been executed by this runtime. Unlike
+	/// `api.blocks().at(block_hash).events()`, they are decoded with the metadata of this runtime
+	/// rather than the one of the client.
+	pub async fn events_at(
+		&self,
+		api: &Client,
+		block_hash: BlockHash,
+	) -> Result<Events<Config>, RuntimeError> {
+		let key = api.storage().address_bytes(&v_73::storage().system().events().unvalidated())?;
+		// A block without events has no `Events` in its state, which decodes like an empty list.
+		let bytes = api.storage().at(block_hash).fetch_raw(key).await?.unwrap_or(vec![0]);
+		Ok(Events::new(self.metadata(), block_hash, bytes))
+	}
+}
+
+// The block in whose state the code that executed block `block_hash` is, i.e. its parent, or the
+// genesis block itself.
+async fn code_block(api: &Client, block_hash: BlockHash) -> Result<BlockHash, RuntimeError> {
+	let block = api.blocks().at(block_hash).await?;
+	Ok(if block.number() == 0 { block_hash } else { block.header().parent_hash })
+}
+
+// The spec version of the code that executed block `block_hash`, as `state_getRuntimeVersion` at
+// its code block gives it. `Core_version` returns the `RuntimeVersion` of the code, its spec
+// version comes after the spec name, the implementation name and the authoring version.
+async fn version_of_code(api: &Client, block_hash: BlockHash) -> Result<u32, RuntimeError> {
+	let bytes: Vec<u8> = api
+		.runtime_api()
+		.at(code_block(api, block_hash).await?)
+		.call_raw("Core_version", None)
+		.await?;
+	let (_, _, _, spec_version) = <(String, String, u32, u32)>::decode(&mut &bytes[..])
+		.map_err(|_| RuntimeError::VersionNotFound(block_hash))?;
+	Ok(spec_version)
+}
+
+/// The runtime that executed block `block_hash`, and the events of the block decoded with it.
+pub async fn block_events(
+	api: &Client,
+	block_hash: BlockHash,
+) -> Result<(Runtime, Events<Config>), RuntimeError> {
+	let runtime = Runtime::at(api, block_hash).await?;
+	let events = runtime.events_at(api, block_hash).await?;
+	Ok((runtime, events))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{registered, DynamicTypes, Runtime, RuntimeError, RuntimeTypes};
+	use crate::{
+		contract_calls::GenericContractCall,
+		contract_events::{GenericContractEvent, Origin},
+	};
+	use azero_config::{AccountId, Config};
+	use azero_runtime_types::{
+		v_68, v_69,
+		v_73::{
+			runtime_types::{
+				frame_system::{EventRecord, Phase},
+				pallet_contracts::{pallet::Event as ContractsEvent, Origin as CallOrigin},
+			},
+			Event,
+		},
+	};
+	use codec::Encode;
+	use subxt::{
+		events::Events,
+		utils::{MultiAddress, H256},
+	};
+
+	fn account(id: u8) -> AccountId {
+		AccountId::from([id; 32])
+	}
+
+	// The events of SCALE encoded `EventRecord`s of `runtime`, decoded with its metadata.
+	fn decode_events(runtime: &Runtime, records: impl Encode) -> Vec<Option<GenericContractEvent>> {
+		let events = Events::<Config>::new(runtime.metadata(), H256::zero(), records.encode());
+		events
+			.iter()
+			.map(|event| runtime.types().contract_event(&event.unwrap()).unwrap())
+			.collect()
+	}
+
+	fn called(caller: u8, contract: u8) -> GenericContractEvent {
+		GenericContractEvent::Called {
+			caller: Origin::Signed(account(caller)),
+			contract: account(contract),
+		}
+	}
+
+	#[test]
+	fn runtime_68_events_decode_with_its_types() {
+		use v_68::runtime_types::{
+			frame_system::{EventRecord, Phase},
+			pallet_contracts::{pallet::Event as ContractsEvent, Origin as CallOrigin},
+		};
+		let records: Vec<_> = [
+			ContractsEvent::CodeStored { code_hash: H256([3u8; 32]) },
+			ContractsEvent::Called { caller: CallOrigin::Signed(account(1)), contract: account(2) },
+			ContractsEvent::CodeRemoved { code_hash: H256([3u8; 32]) },
+		]
+		.into_iter()
+		.map(|event| EventRecord {
+			phase: Phase::ApplyExtrinsic(1),
+			event: v_68::Event::Contracts(event),
+			topics: vec![],
+		})
+		.collect();
+		// Code events of runtime 68 have no uploader or remover.
+		assert_eq!(
+			decode_events(&Runtime::from_spec_version(68).unwrap(), records),
+			vec![
+				Some(GenericContractEvent::CodeStored {
+					code_hash: H256([3u8; 32]),
+					uploader: None
+				}),
+				Some(called(1, 2)),
+				Some(GenericContractEvent::CodeRemoved {
+					code_hash: H256([3u8; 32]),
+					remover: None
+				}),
+			]
+		);
+	}
+
+	#[test]
+	fn runtime_69_events_decode_with_its_types() {
+		use v_69::runtime_types::{
+			frame_system::{EventRecord, Phase},
+			pallet_contracts::{pallet::Event as ContractsEvent, Origin as CallOrigin},
+		};
+		let records: Vec<_> = [
+			ContractsEvent::CodeStored {
+				code_hash: H256([3u8; 32]),
+				deposit_held: 10,
+				uploader: account(1),
+			},
+			ContractsEvent::Called { caller: CallOrigin::Signed(account(1)), contract: account(2) },
+			ContractsEvent::StorageDepositTransferredAndHeld {
+				from: account(1),
+				to: account(2),
+				amount: 10,
+			},
+		]
+		.into_iter()
+		.map(|event| EventRecord {
+			phase: Phase::ApplyExtrinsic(1),
+			event: v_69::Event::Contracts(event),
+			topics: vec![],
+		})
+		.collect();
+		assert_eq!(
+			decode_events(&Runtime::from_spec_version(69).unwrap(), records),
+			vec![
+				Some(GenericContractEvent::CodeStored {
+					code_hash: H256([3u8; 32]),
+					uploader: Some(account(1))
+				}),
+				Some(called(1, 2)),
+				None
+			]
+		);
+	}
+
+	#[test]
+	fn dynamic_call_decoding_matches_generated_types() {
+		use azero_runtime_types::v_73::{
+			runtime_types::{
+				pallet_contracts::pallet::Call as ContractsCall,
+				pallet_proxy::pallet::Call as ProxyCall, pallet_sudo::pallet::Call as SudoCall,
+				pallet_utility::pallet::Call as UtilityCall, sp_weights::weight_v2::Weight,
+			},
+			Call,
+		};
+		let call = |data| {
+			Call::Contracts(ContractsCall::call {
+				dest: MultiAddress::Id(account(2)),
+				value: 5,
+				gas_limit: Weight { ref_time: 1, proof_size: 1 },
+				storage_deposit_limit: Some(10.into()),
+				data,
+			})
+		};
+		let batch = Call::Utility(UtilityCall::batch_all {
+			calls: vec![
+				call(vec![1]),
+				Call::Proxy(ProxyCall::proxy {
+					real: MultiAddress::Id(account(1)),
+					force_proxy_type: None,
+					call: Box::new(Call::Sudo(SudoCall::sudo { call: Box::new(call(vec![2])) })),
+				}),
+				Call::Contracts(ContractsCall::call {
+					dest: MultiAddress::Raw(vec![2]),
+					value: 5,
+					gas_limit: Weight { ref_time: 1, proof_size: 1 },
+					storage_deposit_limit: None,
+					data: vec![3],
+				}),
+				call(vec![]),
+			],
+		});
+		let generated = Runtime::from_spec_version(73).unwrap();
+		let dynamic = DynamicTypes::new(73, generated.metadata());
+		let calls = dynamic.contract_calls(&batch.encode());
+		assert_eq!(calls, generated.types().contract_calls(&batch.encode()));
+		assert_eq!(
+			calls,
+			Some(vec![contract_call(vec![1]), contract_call(vec![2]), contract_call(vec![])])
+		);
+		assert_eq!(dynamic.contract_calls(&[0xff, 0xff]), None);
+	}
+
+	#[test]
+	fn dynamic_contract_info_matches_generated_types() {
+		// A `ContractInfo` of runtime 73, field by field. The map of delegate dependencies is
+		// encoded as a list of pairs.
+		let bytes = (
+			vec![7u8; 4],
+			H256([3u8; 32]),
+			1u32,
+			2u32,
+			10u128,
+			20u128,
+			30u128,
+			vec![(H256([4u8; 32]), 40u128)],
+		)
+			.encode();
+		let generated = Runtime::from_spec_version(73).unwrap();
+		let dynamic = DynamicTypes::new(73, generated.metadata());
+		for info in [generated.types().contract_info(&bytes), dynamic.contract_info(&bytes)] {
+			let info = info.unwrap();
+			assert_eq!(info.trie_id, vec![7u8; 4]);
+			assert_eq!(info.code_hash, H256([3u8; 32]));
+		}
+		assert!(dynamic.contract_info(&bytes[..10]).is_err());
+	}
+
+	fn contract_call(data: Vec<u8>) -> GenericContractCall {
+		GenericContractCall { contract: account(2), value: 5, data }
+	}
+
+	#[test]
+	fn runtime_68_calls_decode_with_its_types() {
+		use v_68::{
+			runtime_types::{
+				pallet_contracts::pallet::Call as ContractsCall,
+				pallet_sudo::pallet::Call as SudoCall, pallet_utility::pallet::Call as UtilityCall,
+				sp_weights::weight_v2::Weight,
+			},
+			Call,
+		};
+		let call = |data| {
+			Call::Contracts(ContractsCall::call {
+				dest: MultiAddress::Id(account(2)),
+				value: 5,
+				gas_limit: Weight { ref_time: 1, proof_size: 1 },
+				storage_deposit_limit: None,
+				data,
+			})
+		};
+		let batch = Call::Utility(UtilityCall::batch {
+			calls: vec![
+				call(vec![1]),
+				Call::Sudo(SudoCall::sudo { call: Box::new(call(vec![2])) }),
+			],
+		});
+		assert_eq!(
+			Runtime::from_spec_version(68).unwrap().types().contract_calls(&batch.encode()),
+			Some(vec![contract_call(vec![1]), contract_call(vec![2])])
+		);
+	}
+
+	#[test]
+	fn runtime_69_calls_decode_with_its_types() {
+		use v_69::{
+			runtime_types::{
+				pallet_contracts::pallet::Call as ContractsCall,
+				pallet_proxy::pallet::Call as ProxyCall, sp_weights::weight_v2::Weight,
+			},
+			Call,
+		};
+		let proxy = Call::Proxy(ProxyCall::proxy {
+			real: MultiAddress::Id(account(1)),
+			force_proxy_type: None,
+			call: Box::new(Call::Contracts(ContractsCall::call {
+				dest: MultiAddress::Id(account(2)),
+				value: 5,
+				gas_limit: Weight { ref_time: 1, proof_size: 1 },
+				storage_deposit_limit: None,
+				data: vec![3],
+			})),
+		});
+		let runtime = Runtime::from_spec_version(69).unwrap();
+		assert_eq!(
+			runtime.types().contract_calls(&proxy.encode()),
+			Some(vec![contract_call(vec![3])])
+		);
+		assert_eq!(runtime.types().contract_calls(&[0xff, 0xff]), None);
+	}
+
+	#[test]
+	fn registered_runtimes_resolve_by_spec_version() {
+		for types in registered() {
+			let runtime = Runtime::from_spec_version(types.spec_version()).unwrap();
+			assert_eq!(runtime.spec_version(), types.spec_version());
+			assert!(runtime.metadata().pallet_by_name("Contracts").is_some());
+		}
+		assert!(matches!(
+			Runtime::from_spec_version(70),
+			Err(RuntimeError::UnknownSpecVersion(70))
+		));
+	}
+
+	#[test]
+	fn dynamic_decoding_matches_generated_types() {
+		let contracts_events = vec![
+			ContractsEvent::Instantiated { deployer: account(1), contract: account(2) },
+			ContractsEvent::CodeStored {
+				code_hash: H256([3u8; 32]),
+				deposit_held: 10,
+				uploader: account(1),
+			},
+			ContractsEvent::ContractEmitted { contract: account(2), data: vec![1, 2, 3] },
+			ContractsEvent::Called { caller: CallOrigin::Signed(account(1)), contract: account(2) },
+			ContractsEvent::Called { caller: CallOrigin::Root, contract: account(2) },
+			ContractsEvent::DelegateCalled { contract: account(2), code_hash: H256([3u8; 32]) },
+			ContractsEvent::ContractCodeUpdated {
+				contract: account(2),
+				new_code_hash: H256([4u8; 32]),
+				old_code_hash: H256([3u8; 32]),
+			},
+			ContractsEvent::Terminated { contract: account(2), beneficiary: account(1) },
+			ContractsEvent::CodeRemoved {
+				code_hash: H256([3u8; 32]),
+				deposit_released: 10,
+				remover: account(1),
+			},
+		];
+		let records: Vec<_> = contracts_events
+			.into_iter()
+			.map(|event| EventRecord {
+				phase: Phase::ApplyExtrinsic(1),
+				event: Event::Contracts(event),
+				topics: vec![H256([5u8; 32])],
+			})
+			.collect();
+		let generated = Runtime::from_spec_version(73).unwrap();
+		let dynamic = DynamicTypes::new(73, generated.metadata());
+		let events = Events::<Config>::new(generated.metadata(), H256::zero(), records.encode());
+		let mut decoded = Vec::new();
+		for event in events.iter() {
+			let event = event.unwrap();
+			let generic = dynamic.contract_event(&event).unwrap();
+			assert_eq!(generic, generated.types().contract_event(&event).unwrap());
+			decoded.extend(generic);
+		}
+		assert_eq!(decoded.len(), 9);
+		assert_eq!(
+			decoded[2],
+			GenericContractEvent::ContractEmitted {
+				contract: account(2),
+				data: vec![1, 2, 3],
+				topics: vec![H256([5u8; 32])],
+			}
+		);
+	}
+}
@@ -7,6 +7,7 @@ use axum::{
 	Router,
 };
 use azero_config::{WS_AZERO_MAINNET, WS_AZERO_TESTNET};
+use azero_universal::RpcPool;
 use azero_webserver_psp22::{
 	token_db::{tracker::TokenDBTracker, TokenDB},
 	MAINNET_TOKEN_DB_FILEPATH_JSON, TESTNET_TOKEN_DB_FILEPATH_JSON,
@@ -26,8 +27,10 @@ async fn main() {
 	env_logger::builder().filter_level(log::LevelFilter::Info).init();
 	let static_files_path = env::var("STATIC_FILES_PATH").unwrap_or("./static/".to_string());
 	let static_files_service = ServeDir::new(static_files_path);
-	let testnet_app = create_app("testnet", TESTNET_TOKEN_DB_FILEPATH_JSON, WS_AZERO_TESTNET).await;
-	let mainnet_app = create_app("mainnet", MAINNET_TOKEN_DB_FILEPATH_JSON, WS_AZERO_MAINNET).await;
+	let testnet_rpc = RpcPool::from_env("RPC_TESTNET", WS_AZERO_TESTNET);
+	let mainnet_rpc = RpcPool::from_env("RPC_MAINNET", WS_AZERO_MAINNET);
+	let testnet_app = create_app("testnet", TESTNET_TOKEN_DB_FILEPATH_JSON, testnet_rpc).await;
+	let mainnet_app = create_app("mainnet", MAINNET_TOKEN_DB_FILEPATH_JSON, mainnet_rpc).await;
 	let app = Router::new()
 		.nest("/testnet", testnet_app)
 		.nest("/mainnet", mainnet_app)
@@ -39,9 +42,9 @@ async fn main() {
 	axum::serve(listener, app.into_make_service()).await.unwrap();
 }
 
-async fn create_app(network: &str, backup_path: &str, ws_url: &str) -> Router {
+async fn create_app(network: &str, backup_path: &str, rpc_pool: RpcPool) -> Router {
 	let token_db = TokenDB::from_disk(backup_path);
-	let tracker = TokenDBTracker::new(token_db.clone(), network, backup_path, ws_url)
+	let tracker = TokenDBTracker::new(token_db.clone(), network, backup_path, rpc_pool)
 		.await
 		.unwrap();
 	let state = NetworkTokens { network: network.to_string(), db: token_db.clone() };
@@ -9,11 +9,12 @@ use azero_contracts::{
 	storage::{get_contract_state_root_from_trie_id, get_contract_storage_from_trie_id},
 };
 use azero_universal::{
-	contract_events::{backwards_compatible_into_contract_event, GenericContractEvent},
+	contract_events::{into_contract_event, GenericContractEvent},
 	contract_info::{
 		backwards_compatible_get_contract_info, backwards_compatible_get_contract_infos,
 	},
-	initialize_client,
+	runtime::block_events,
+	RpcPool,
 };
 
 use parking_lot::Mutex;
@@ -29,7 +30,7 @@ use super::{ContractInfo, PSP22Contract, PSP22ContractMetadata, TokenDB};
 pub struct TokenDBTracker {
 	db: TokenDB,
 	network: String,
-	endpoint: String,
+	rpc_pool: RpcPool,
 	backup_path: String,
 }
 
@@ -154,13 +155,14 @@ impl AccountPQ {
 	}
 }
 
-async fn signal_contract_events(network: &str, endpoint: &str, queue: AccountPQ) -> ! {
+async fn signal_contract_events(network: &str, rpc_pool: &RpcPool, queue: AccountPQ) -> ! {
 	loop {
-		let (_, client) = initialize_client(endpoint).await;
-		let mut block_stream = match client.blocks().subscribe_finalized().await {
+		let connection = rpc_pool.wait_for_connection().await;
+		let mut block_stream = match connection.client.blocks().subscribe_finalized().await {
 			Ok(stream) => stream,
 			Err(e) => {
 				log::error!("{}: Error subscribing to blocks: {}", network, e);
+				rpc_pool.report_failure(&connection.url);
 				tokio::time::sleep(std::time::Duration::from_secs(2)).await;
 				continue;
 			},
@@ -179,8 +181,13 @@ async fn signal_contract_events(network: &str, endpoint: &str, queue: AccountPQ)
 					let block_number = block.header().number;
 					let block_hash = block.hash();
 					log::debug!("{}: Stream: block {} {}", network, block_number, block_hash);
-					let events = match block.events().await {
-						Ok(events) => events,
+					let events = rpc_pool
+						.run(|connection| async move {
+							Ok(block_events(&connection.client, block_hash).await?)
+						})
+						.await;
+					let (runtime, events) = match events {
+						Ok(block_events) => block_events,
 						Err(e) => {
 							log::error!("{}: Error getting events from block: {}", network, e);
 							tokio::time::sleep(std::time::Duration::from_secs(1)).await;
@@ -190,7 +197,14 @@ async fn signal_contract_events(network: &str, endpoint: &str, queue: AccountPQ)
 					for event in events.iter() {
 						match event {
 							Ok(event) => {
-								if let Some(e) = backwards_compatible_into_contract_event(event) {
+								let contract_event = match into_contract_event(&runtime, &event) {
+									Ok(contract_event) => contract_event,
+									Err(e) => {
+										log::error!("{}: Error decoding contract event: {}", network, e);
+										None
+									},
+								};
+								if let Some(e) = contract_event {
 									use GenericContractEvent::*;
 									match e {
 										Instantiated { contract, .. } => {
@@ -225,6 +239,7 @@ async fn signal_contract_events(network: &str, endpoint: &str, queue: AccountPQ)
 				},
 				None => {
 					log::error!("{}: Block stream ended", network);
+					rpc_pool.report_failure(&connection.url);
 					tokio::time::sleep(std::time::Duration::from_secs(10)).await;
 					break;
 				},
@@ -238,12 +253,12 @@ impl TokenDBTracker {
 		db: TokenDB,
 		network: &str,
 		backup_path: &str,
-		endpoint: &str,
+		rpc_pool: RpcPool,
 	) -> Result<Self> {
 		Ok(Self {
 			db,
 			network: network.to_string(),
-			endpoint: endpoint.to_string(),
+			rpc_pool,
 			backup_path: backup_path.to_string(),
 		})
 	}
@@ -252,32 +267,40 @@ impl TokenDBTracker {
 		let queue = AccountPQ::new();
 		let mut last_db_update = std::time::Instant::now();
 		let queue_cloned = queue.clone();
-		let url = self.endpoint.clone();
+		let rpc_pool = self.rpc_pool.clone();
 		let name = self.network.clone();
-		tokio::spawn(async move { signal_contract_events(&name, &url, queue_cloned).await });
-		let (mut rpc_client, mut client) = initialize_client(&self.endpoint).await;
-		let mut fail_tracker = 0;
+		tokio::spawn(async move { signal_contract_events(&name, &rpc_pool, queue_cloned).await });
 		let mut iter_no: u64 = 0;
 		loop {
-			if fail_tracker >= 10 {
-				fail_tracker = 0;
-				log::warn!("{}: Initializing client again after 10 failures", self.network);
-				(rpc_client, client) = initialize_client(&self.endpoint).await;
-			}
 			if let Some((address, prio)) = queue.pop() {
 				iter_no += 1;
 				if iter_no % 100 == 0 {
 					log::info!("{}: {} contracts left in queue", self.network, queue.len());
 				}
 				let old_info = self.db.inner.read().contracts.get(&address).cloned();
-				match get_contract(&rpc_client, &client, &address, old_info).await {
+				let contract = self
+					.rpc_pool
+					.run(|connection| {
+						let address = &address;
+						let old_info = old_info.clone();
+						async move {
+							get_contract(
+								&connection.rpc_client,
+								&connection.client,
+								address,
+								old_info,
+							)
+							.await
+						}
+					})
+					.await;
+				match contract {
 					Ok(contract) => {
 						let mut db = self.db.inner.write();
 						db.contracts.insert(address, contract);
 					},
 					Err(e) => {
 						log::debug!("{}: Error updating contract {}: {}", self.network, address, e);
-						fail_tracker += 1;
 					},
 				}
 				if prio == 0 {
@@ -285,14 +308,19 @@ impl TokenDBTracker {
 				}
 			} else {
 				log::info!("{}: Starting a new cycle over all contracts", self.network);
-				match get_current_contracts(&client).await {
+				let contracts = self
+					.rpc_pool
+					.run(
+						|connection| async move { get_current_contracts(&connection.client).await },
+					)
+					.await;
+				match contracts {
 					Ok(contracts) =>
 						for c in contracts {
 							queue.insert_or_update(c, 0);
 						},
 					Err(e) => {
 						log::error!("{}: Error {} getting contracts", self.network, e);
-						fail_tracker += 1;
 					},
 				}
 			}
@@ -0,0 +1,68 @@
+//! Query parameters and responses of the HTTP API, shared by the server and by `client`.
+
+use azero_config::AccountId;
+use price_feed::PriceFeed;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::tokens::{get_price_by_token_address, Token};
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+pub struct GetTradesParams {
+	pub block_start: u32,
+	pub block_stop: u32,
+	pub contract_address: Option<AccountId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+pub struct GetVolumeParams {
+	pub account: AccountId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+pub struct GetLastWeekTradesParams {
+	pub account: AccountId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TradeDisplay {
+	pub block_num: u32,
+	pub user: AccountId,
+	/// Symbol of sold token.
+	pub token_in: String,
+	/// Symbol of bought token.
+	pub token_out: String,
+	pub amount_in: f64,
+	pub amount_out: f64,
+	/// Path of token symbols traded.
+	pub path: Vec<String>,
+	pub volume: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Status {
+	pub indexed_from: u32,
+	pub indexed_till: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenWithPrice {
+	pub address: AccountId,
+	pub name: Option<String>,
+	pub symbol: Option<String>,
+	pub decimals: u8,
+	pub price: Option<f64>,
+}
+
+impl TokenWithPrice {
+	pub fn new(token: Token, price_feed: &PriceFeed) -> Self {
+		let price = get_price_by_token_address(&token.address, price_feed);
+		TokenWithPrice {
+			address: token.address,
+			name: token.name,
+			symbol: token.symbol,
+			decimals: token.decimals,
+			price,
+		}
+	}
+}
@@ -0,0 +1,62 @@
+//! Typed client of the HTTP API, built on the transport of the event indexer client.
+
+use azero_config::AccountId;
+use azero_contract_event_indexer::client::{ApiClient, ClientError, RetryPolicy};
+
+use crate::{
+	api::{
+		GetLastWeekTradesParams, GetTradesParams, GetVolumeParams, Status, TokenWithPrice,
+		TradeDisplay,
+	},
+	event_db::Pool,
+	QueryResultMultiSwaps,
+};
+
+/// Client of the API of `common_indexer`.
+#[derive(Debug, Clone)]
+pub struct CommonIndexerClient {
+	api: ApiClient,
+}
+
+impl CommonIndexerClient {
+	pub fn new(base_url: &str) -> Self {
+		CommonIndexerClient { api: ApiClient::new(base_url) }
+	}
+
+	pub fn with_retry(self, retry: RetryPolicy) -> Self {
+		CommonIndexerClient { api: self.api.with_retry(retry) }
+	}
+
+	pub async fn status(&self) -> Result<Status, ClientError> {
+		self.api.json(self.api.get("/status")).await
+	}
+
+	pub async fn pools(&self) -> Result<Vec<Pool>, ClientError> {
+		self.api.json(self.api.get("/pools")).await
+	}
+
+	pub async fn tokens(&self) -> Result<Vec<TokenWithPrice>, ClientError> {
+		self.api.json(self.api.get("/tokens")).await
+	}
+
+	/// The trades are not paginated, `is_complete` is false if they did not fit in the response.
+	pub async fn trades(
+		&self,
+		params: &GetTradesParams,
+	) -> Result<QueryResultMultiSwaps, ClientError> {
+		self.api.json(self.api.get("/trades").query(params)).await
+	}
+
+	pub async fn one_week_volume(&self, account: &AccountId) -> Result<f64, ClientError> {
+		let params = GetVolumeParams { account: account.clone() };
+		self.api.json(self.api.get("/checker/one_week_volume").query(&params)).await
+	}
+
+	pub async fn one_week_trades(
+		&self,
+		account: &AccountId,
+	) -> Result<Vec<TradeDisplay>, ClientError> {
+		let params = GetLastWeekTradesParams { account: account.clone() };
+		self.api.json(self.api.get("/checker/one_week_trades").query(&params)).await
+	}
+}
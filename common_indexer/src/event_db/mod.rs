@@ -3,7 +3,7 @@ use parking_lot::Mutex;
 use r2d2::Pool as DBPool;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{params, Connection, Result as SqliteResult};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::{path::Path, str::FromStr, sync::Arc};
 use thiserror::Error;
@@ -31,7 +31,7 @@ impl Trade {
 }
 
 #[serde_as]
-#[derive(Debug, Clone, Serialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Pool {
 	pub pool: AccountId,
 	pub token_0: AccountId,
@@ -1,7 +1,7 @@
 use multiswaps::MultiSwap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use serde_with::SerializeAs;
+use serde_with::{DeserializeAs, SerializeAs};
 use utoipa::ToSchema;
 
 pub type Client = azero_config::Client;
@@ -9,6 +9,8 @@ pub type RpcClient = azero_config::RpcClient;
 pub type BlockHash = azero_config::BlockHash;
 pub type AccountId = azero_config::AccountId;
 
+pub mod api;
+pub mod client;
 pub mod event_db;
 pub mod multiswaps;
 pub mod pools;
@@ -27,7 +29,7 @@ pub struct QueryResult<T> {
 }
 
 // This type is separate because of issues with utoipa and generating a Schema with generics
-#[derive(Serialize, Clone, Debug, ToSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 pub struct QueryResultMultiSwaps {
 	pub data: Vec<MultiSwap>,
 	/// Whether the result is complete or it is a partial result (full result didn't fit within the
@@ -52,6 +54,15 @@ impl SerializeAs<u128> for U128AsDecString {
 	}
 }
 
+impl<'de> DeserializeAs<'de, u128> for U128AsDecString {
+	fn deserialize_as<D>(deserializer: D) -> Result<u128, D::Error>
+	where
+		D: serde::Deserializer<'de>,
+	{
+		String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+	}
+}
+
 pub fn u128_dec() -> utoipa::openapi::schema::Schema {
 	utoipa::openapi::ObjectBuilder::new()
 		.description(Some("A 128-bit unsigned integer encoded as a string in decimal"))
@@ -1,8 +1,13 @@
 use axum::{extract::Query, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
 use azero_config::{AccountId, WS_AZERO_MAINNET};
 
-use azero_universal::AccountIdSchema;
+use azero_contract_event_indexer::client::EventIndexerClient;
+use azero_universal::{AccountIdSchema, RpcPool};
 use common_indexer::{
+	api::{
+		GetLastWeekTradesParams, GetTradesParams, GetVolumeParams, Status, TokenWithPrice,
+		TradeDisplay,
+	},
 	event_db::{
 		get_indexed_till, get_pools, get_shared_pool, get_tokens, get_trades_by_origin_limited,
 		get_trades_by_origin_with_limit, get_trades_by_range_limited, init_db, Pool, SharedPool,
@@ -10,18 +15,16 @@ use common_indexer::{
 	multiswaps::{aggregate_trades, trade_result_to_multiswaps, MultiSwap},
 	psp_list::PSPList,
 	scraper::Endpoints,
-	tokens::{get_price_by_token_address, Token},
 	QueryResultMultiSwaps, COMMON_START_BLOCK,
 };
 
-use chrono::Local;
+use chrono::{Local, Utc};
 use common_indexer::scraper;
 use env_logger::{Builder, Target};
 use price_feed::PriceFeed;
-use serde::{Deserialize, Serialize};
 use std::{collections::BTreeMap, io::Write};
 use tower_http::cors::CorsLayer;
-use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 #[derive(OpenApi)]
@@ -51,14 +54,10 @@ struct AppState {
 	db_pool: SharedPool,
 	price_feed: PriceFeed,
 	psp_list: PSPList,
+	event_indexer: EventIndexerClient,
 }
 
-#[derive(Debug, Deserialize, IntoParams)]
-struct GetTradesParams {
-	block_start: u32,
-	block_stop: u32,
-	contract_address: Option<AccountId>,
-}
+const ONE_WEEK_MILLIS: u64 = 7 * 24 * 60 * 60 * 1000;
 
 #[utoipa::path(
     get,
@@ -99,21 +98,19 @@ async fn handle_get_trades(
 	}
 }
 
-#[derive(Debug, Deserialize, IntoParams)]
-struct GetVolumeParams {
-	account: AccountId,
-}
-
-fn get_last_week_trades_and_volume(
+async fn get_last_week_trades_and_volume(
 	account: &AccountId,
 	app_state: &AppState,
 ) -> anyhow::Result<(Vec<TradeDisplay>, f64)> {
+	let week_ago = Utc::now().timestamp_millis() as u64 - ONE_WEEK_MILLIS;
+	let first_block = app_state.event_indexer.first_block_since(week_ago).await?;
 	let conn = {
 		let pool = app_state.db_pool.lock();
 		pool.get().unwrap()
 	};
 	let indexed_till = get_indexed_till(&conn)?;
-	let one_week_ago = indexed_till - 7 * 24 * 60 * 60;
+	// Without a block indexed in the last week, there are no trades in it either.
+	let one_week_ago = first_block.unwrap_or(indexed_till + 1);
 	let trades =
 		get_trades_by_origin_with_limit(&conn, one_week_ago, indexed_till, account, None)?.data;
 	let tokens = get_tokens(&conn)?;
@@ -180,7 +177,7 @@ async fn handle_get_volume(
 	Query(params): Query<GetVolumeParams>,
 	app_state: AppState,
 ) -> impl IntoResponse {
-	let result = get_last_week_trades_and_volume(&params.account, &app_state);
+	let result = get_last_week_trades_and_volume(&params.account, &app_state).await;
 
 	match result {
 		Ok((_, volume)) => Json(volume).into_response(),
@@ -189,26 +186,6 @@ async fn handle_get_volume(
 	}
 }
 
-#[derive(Debug, Serialize, ToSchema)]
-struct TradeDisplay {
-	block_num: u32,
-	user: AccountId,
-	/// Symbol of sold token.
-	token_in: String,
-	/// Symbol of bought token.
-	token_out: String,
-	amount_in: f64,
-	amount_out: f64,
-	/// Path of token symbols traded.
-	path: Vec<String>,
-	volume: f64,
-}
-
-#[derive(Debug, Deserialize, IntoParams)]
-struct GetLastWeekTradesParams {
-	account: AccountId,
-}
-
 #[utoipa::path(
     get,
     path = "/checker/one_week_trades",
@@ -223,7 +200,7 @@ async fn handle_get_last_week_trades(
 	Query(params): Query<GetLastWeekTradesParams>,
 	app_state: AppState,
 ) -> impl IntoResponse {
-	let result = get_last_week_trades_and_volume(&params.account, &app_state);
+	let result = get_last_week_trades_and_volume(&params.account, &app_state).await;
 
 	match result {
 		Ok((trades, _)) => Json(trades).into_response(),
@@ -232,12 +209,6 @@ async fn handle_get_last_week_trades(
 	}
 }
 
-#[derive(Debug, Serialize, ToSchema)]
-struct Status {
-	indexed_from: u32,
-	indexed_till: u32,
-}
-
 #[utoipa::path(
     get,
     path = "/status",
@@ -279,28 +250,6 @@ async fn handle_get_pools(app_state: AppState) -> impl IntoResponse {
 	}
 }
 
-#[derive(Serialize, ToSchema)]
-struct TokenWithPrice {
-	address: AccountId,
-	name: Option<String>,
-	symbol: Option<String>,
-	decimals: u8,
-	price: Option<f64>,
-}
-
-impl TokenWithPrice {
-	fn new(token: Token, price_feed: &PriceFeed) -> Self {
-		let price = get_price_by_token_address(&token.address, price_feed);
-		TokenWithPrice {
-			address: token.address,
-			name: token.name,
-			symbol: token.symbol,
-			decimals: token.decimals,
-			price,
-		}
-	}
-}
-
 #[utoipa::path(
     get,
     path = "/tokens",
@@ -344,18 +293,19 @@ async fn main() {
 		.init();
 
 	let port = std::env::var("PORT").unwrap_or_else(|_| "3001".to_string());
-	let rpc_azero = std::env::var("RPC_AZERO").unwrap_or_else(|_| WS_AZERO_MAINNET.to_string());
+	let rpc_azero = RpcPool::from_env("RPC_AZERO", WS_AZERO_MAINNET);
 	let indexer_url = std::env::var("INDEXER_URL")
 		.unwrap_or_else(|_| "https://indexer.azero-tools.com".to_string());
 
 	log::info!(
 		"Running config: port: {}, rpc_azero: {}, indexer_url: {}",
 		port,
-		rpc_azero,
+		rpc_azero.urls().join(","),
 		indexer_url
 	);
 
 	init_db().unwrap();
+	let event_indexer = EventIndexerClient::new(&indexer_url);
 	tokio::spawn(async {
 		let endpoints = Endpoints::new(rpc_azero, indexer_url);
 		scraper::run(&endpoints).await;
@@ -365,7 +315,7 @@ async fn main() {
 	let shared_pool = get_shared_pool();
 	let price_feed = PriceFeed::new().await.unwrap();
 
-	let app_state = AppState { db_pool: shared_pool, price_feed, psp_list };
+	let app_state = AppState { db_pool: shared_pool, price_feed, psp_list, event_indexer };
 
 	let app = Router::new()
 		.route(
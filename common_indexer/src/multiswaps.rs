@@ -1,12 +1,12 @@
 use std::collections::BTreeMap;
 
 use crate::{event_db::Trade, u128_dec, AccountId, QueryResult, U128AsDecString};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use utoipa::ToSchema;
 
 #[serde_as]
-#[derive(Debug, Clone, Serialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct MultiSwap {
 	pub origin: AccountId,
 	pub token_in: AccountId,
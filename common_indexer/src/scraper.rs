@@ -1,11 +1,13 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use azero_contract_event_indexer::{
-	event_db::{CalledDetails, EmittedDetails, Event, EventType},
+	api::GetEventsParams,
+	call_tree::{build_call_tree, CallTreeNode},
+	client::EventIndexerClient,
+	event_db::{CalledDetails, EmittedDetails, Event, EventType, Origin},
 	Bounds,
 };
-use azero_universal::{get_hash_from_number, initialize_client};
-use serde::Deserialize;
+use azero_universal::{get_hash_from_number, RpcPool};
 
 use crate::{
 	event_db::{get_connection_with_backoff, get_indexed_till, insert_trades, Pool, Trade},
@@ -19,13 +21,13 @@ use codec::DecodeAll;
 use rusqlite::Connection;
 
 pub struct Endpoints {
-	rpc: String,
-	event_indexer: String,
+	rpc: RpcPool,
+	event_indexer: EventIndexerClient,
 }
 
 impl Endpoints {
-	pub fn new(rpc: String, event_indexer: String) -> Self {
-		Self { rpc, event_indexer }
+	pub fn new(rpc: RpcPool, event_indexer: String) -> Self {
+		Self { rpc, event_indexer: EventIndexerClient::new(&event_indexer) }
 	}
 }
 
@@ -77,64 +79,6 @@ async fn get_pools_and_tokens_at_num(
 	Ok((pools, tokens))
 }
 
-#[derive(Debug, Deserialize)]
-struct GetEventsResponse {
-	data: Vec<Event>,
-	is_complete: bool,
-}
-
-async fn get_bounds(base_url: &str) -> anyhow::Result<Bounds> {
-	let client = reqwest::Client::new();
-	let url = format!("{}/status", base_url);
-	let response = client.get(&url).send().await?;
-	if response.status().is_success() {
-		let response: Bounds = response.json().await?;
-		Ok(response)
-	} else {
-		Err(anyhow::anyhow!("Error fetching status: {:?}", response.status()))
-	}
-}
-
-async fn get_events_by_range(
-	base_url: &str,
-	block_start: u32,
-	block_stop: u32,
-) -> anyhow::Result<Vec<Event>> {
-	let client = reqwest::Client::new();
-	let url = format!("{}/events", base_url);
-	let mut events = Vec::new();
-	let mut current_start = block_start;
-	loop {
-		let response = client
-			.get(&url)
-			.query(&[("block_start", current_start), ("block_stop", block_stop)])
-			.send()
-			.await?;
-		if response.status().is_success() {
-			let response: GetEventsResponse = response.json().await?;
-			events.extend(response.data);
-			if response.is_complete {
-				break;
-			}
-			current_start = events.last().unwrap().block_num;
-		} else {
-			let status = response.status();
-			let error_text = response.text().await?;
-			return Err(anyhow::anyhow!("Error fetching events: {} - {}", status, error_text));
-		}
-	}
-	// sort by (block_num, event_index)
-	events.sort_by(|a, b| {
-		if a.block_num == b.block_num {
-			a.extrinsic_index.cmp(&b.extrinsic_index)
-		} else {
-			a.block_num.cmp(&b.block_num)
-		}
-	});
-	events.dedup();
-	Ok(events)
-}
-
 fn trade_from_pair_event(
 	pair_event: PairEvent,
 	pool: &Pool,
@@ -183,6 +127,10 @@ fn trade_from_pair_event(
 	}
 }
 
+fn pool_of<'a>(event: &Event, pools_map: &'a BTreeMap<AccountId, Pool>) -> Option<&'a Pool> {
+	pools_map.get(event.contract_account_id.as_ref()?)
+}
+
 fn trades_from_events(events: Vec<Event>, pools_map: &BTreeMap<AccountId, Pool>) -> Vec<Trade> {
 	let mut trades = Vec::new();
 	let mut agg_events: BTreeMap<(u32, u32), Vec<Event>> = BTreeMap::new();
@@ -190,35 +138,52 @@ fn trades_from_events(events: Vec<Event>, pools_map: &BTreeMap<AccountId, Pool>)
 		let key = (event.block_num, event.extrinsic_index);
 		agg_events.entry(key).or_insert_with(Vec::new).push(event);
 	}
-	for ((_, _), events) in agg_events {
-		let last = events.last().unwrap().clone();
-		let origin = match last.event_type {
-			EventType::Called(CalledDetails { caller, .. }) => caller,
-			_ => {
-				// This is instantiation -- we don't care about it
-				continue;
-			},
-		};
-		for event in events {
-			if !pools_map.contains_key(&event.contract_account_id) {
-				continue;
-			}
-			if let EventType::Emitted(EmittedDetails { data }) = &event.event_type {
-				// decode to PairEvent
-				let pair_event = match PairEvent::decode_all(&mut &data[..]) {
-					Ok(pair_event) => pair_event,
-					Err(e) => {
-						log::error!("Error decoding event: {}", e);
-						continue;
-					},
+	for ((block_num, extrinsic_index), events) in agg_events {
+		// Every call made by the extrinsic itself, attributed to its caller.
+		for node in build_call_tree(events) {
+			let frame = match node {
+				CallTreeNode::Frame(frame) => frame,
+				CallTreeNode::Event(_) => continue,
+			};
+			let events = frame.events();
+			let origin = match &frame.entry.event_type {
+				EventType::Called(CalledDetails { caller: Origin::Signed(caller), .. }) =>
+					caller.clone(),
+				EventType::Called(CalledDetails { caller: Origin::Root, .. }) => {
+					// Trades are attributed to a user, and there is none behind a sudo or
+					// governance call.
+					if events.iter().any(|e| pool_of(e, pools_map).is_some()) {
+						log::warn!(
+							"Skipping pool events of Root-origin extrinsic {}-{}",
+							block_num,
+							extrinsic_index
+						);
+					}
+					continue;
+				},
+				_ => {
+					// This is instantiation -- we don't care about it
+					continue;
+				},
+			};
+			for event in events {
+				let Some(pool) = pool_of(event, pools_map) else {
+					continue;
 				};
-				if let Some(trade) = trade_from_pair_event(
-					pair_event,
-					&pools_map[&event.contract_account_id],
-					&event,
-					origin.clone(),
-				) {
-					trades.push(trade);
+				if let EventType::Emitted(EmittedDetails { data, .. }) = &event.event_type {
+					// decode to PairEvent
+					let pair_event = match PairEvent::decode_all(&mut &data[..]) {
+						Ok(pair_event) => pair_event,
+						Err(e) => {
+							log::error!("Error decoding event: {}", e);
+							continue;
+						},
+					};
+					if let Some(trade) =
+						trade_from_pair_event(pair_event, pool, event, origin.clone())
+					{
+						trades.push(trade);
+					}
 				}
 			}
 		}
@@ -232,16 +197,28 @@ async fn fetch_trades_from_indexer(
 	to: u32,
 	pools_map: &BTreeMap<AccountId, Pool>,
 ) -> anyhow::Result<Vec<Trade>> {
-	let endpoint = endpoints.event_indexer.clone();
-	let events = get_events_by_range(&endpoint, from, to).await?;
+	let params =
+		GetEventsParams { block_start: Some(from), block_stop: Some(to), ..Default::default() };
+	let events = endpoints.event_indexer.all_events(&params).await?;
 	log::info!("Fetched {} events", events.len());
 	let trades = trades_from_events(events, pools_map);
 	Ok(trades)
 }
 
 async fn fetch_range_from_indexer(endpoints: &Endpoints) -> anyhow::Result<Bounds> {
-	let endpoint = endpoints.event_indexer.clone();
-	get_bounds(&endpoint).await
+	Ok(endpoints.event_indexer.status().await?)
+}
+
+async fn pools_and_tokens_at_num(
+	endpoints: &Endpoints,
+	num: u32,
+) -> anyhow::Result<(Vec<Pair>, Vec<Token>)> {
+	endpoints
+		.rpc
+		.run(
+			|connection| async move { get_pools_and_tokens_at_num(&connection.rpc_client, num).await },
+		)
+		.await
 }
 
 async fn run_iter(
@@ -249,9 +226,12 @@ async fn run_iter(
 	endpoints: &Endpoints,
 	state_hint: &Option<(u32, Vec<Pair>, Vec<Token>)>,
 ) -> anyhow::Result<Option<State>> {
-	let (rpc_client, client) = initialize_client(&endpoints.rpc).await;
-	let block = client.blocks().at_latest().await?;
-	let block_num = block.header().number;
+	let block_num = endpoints
+		.rpc
+		.run(|connection| async move {
+			Ok(connection.client.blocks().at_latest().await?.header().number)
+		})
+		.await?;
 	let fetched_till = get_indexed_till(conn)?;
 	let target_num = u32::min(fetched_till + 50000, block_num - 20);
 	let bounds = fetch_range_from_indexer(endpoints).await?;
@@ -265,12 +245,11 @@ async fn run_iter(
 				if *hint_at >= target_num {
 					(*hint_at, hint_pools.clone(), hint_tokens.clone())
 				} else {
-					let (pools, tokens) =
-						get_pools_and_tokens_at_num(&rpc_client, target_num).await?;
+					let (pools, tokens) = pools_and_tokens_at_num(endpoints, target_num).await?;
 					(target_num, pools, tokens)
 				},
 			None => {
-				let (pools, tokens) = get_pools_and_tokens_at_num(&rpc_client, block_num).await?;
+				let (pools, tokens) = pools_and_tokens_at_num(endpoints, block_num).await?;
 				(block_num, pools, tokens)
 			},
 		}